@@ -7,9 +7,20 @@ fn main() {
         // extension name
         v6,
         // list of all JS files in the extension
-        esm_entry_point = "ext:v6/src/runtime.js",
+        esm_entry_point = "ext:v6/runtime.js",
         // the entrypoint to our extension
-        esm = ["src/runtime.js"]
+        esm = [
+            dir "src",
+            "runtime.js",
+            "modules/index.js",
+            "modules/http.js",
+            "modules/data.js",
+            "modules/encoding.js",
+            "modules/cjs.js",
+            "modules/metrics.js",
+            "modules/execution.js",
+            "modules/checks.js"
+        ]
     );
 
     let out_dir = PathBuf::from(env::var_os("OUT_DIR").unwrap());
@@ -0,0 +1,818 @@
+use anyhow::Result;
+use deno_ast::{
+    MediaType, ParseParams,
+    dep::{DependencyDescriptor, DynamicArgument, DynamicDependencyKind, DynamicTemplatePart, StaticDependencyKind},
+};
+use deno_core::{ModuleLoader, ModuleType, RequestedModuleType, ResolutionKind};
+use deno_error::JsErrorBox;
+use futures::FutureExt;
+use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    collections::{BTreeMap, HashMap, HashSet},
+    io::Read,
+    path::Path,
+    rc::Rc,
+    sync::Arc,
+};
+
+use crate::cli::TestConfig;
+use crate::engine::extract_inline_source_map;
+use crate::node_resolver::{self, RequireResolution};
+
+pub const ARCHIVE_EXTENSION: &str = "tar";
+const MANIFEST_PATH: &str = "manifest.json";
+const ARCHIVE_VERSION: u32 = 1;
+const CJS_RUNTIME_MODULE: &str = "ext:v6/modules/cjs.js";
+
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    version: u32,
+    main_module: String,
+    config: TestConfig,
+    resolutions: Vec<Resolution>,
+    modules: Vec<ModuleEntry>,
+    requires: Vec<RequireEntry>,
+    files: Vec<FileEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Resolution {
+    specifier: String,
+    referrer: String,
+    resolved: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ModuleEntry {
+    specifier: String,
+    module_type: ModuleType,
+    path: String,
+}
+
+// A `require(id)` call made from the CommonJS file `referrer`
+#[derive(Serialize, Deserialize, Clone)]
+struct RequireEntry {
+    id: String,
+    referrer: String,
+    filename: String,
+    url: String,
+    format: String,
+}
+
+// A file read by the CommonJS loader
+#[derive(Serialize, Deserialize)]
+struct FileEntry {
+    filename: String,
+    path: String,
+}
+
+/// Every resolution and module source seen while loading a module graph,
+/// and the files CommonJS modules in it require
+#[derive(Default)]
+pub struct ModuleGraphRecording {
+    resolutions: Vec<Resolution>,
+    modules: BTreeMap<String, (ModuleType, Vec<u8>)>,
+    requires: Vec<RequireEntry>,
+    files: BTreeMap<String, String>,
+}
+
+/// A self-contained test: transpiled sources, data files, the resolutions
+/// between them and the resolved config, written by `v6 bundle` as a tar file
+pub struct Archive {
+    pub main_module: deno_core::ModuleSpecifier,
+    pub config: TestConfig,
+    resolutions: HashMap<(String, String), String>,
+    modules: HashMap<String, (ModuleType, Vec<u8>)>,
+    requires: HashMap<(String, String), RequireEntry>,
+    files: HashMap<String, String>,
+}
+
+impl Archive {
+    pub fn is_archive_path(file: &str) -> bool {
+        Path::new(file)
+            .extension()
+            .is_some_and(|ext| ext == ARCHIVE_EXTENSION)
+    }
+
+    pub fn from_recording(
+        main_module: deno_core::ModuleSpecifier,
+        config: TestConfig,
+        recording: &ModuleGraphRecording,
+    ) -> Self {
+        Self {
+            main_module,
+            config,
+            resolutions: recording
+                .resolutions
+                .iter()
+                .map(|r| ((r.specifier.clone(), r.referrer.clone()), r.resolved.clone()))
+                .collect(),
+            modules: recording
+                .modules
+                .iter()
+                .map(|(specifier, module)| (specifier.clone(), module.clone()))
+                .collect(),
+            requires: recording
+                .requires
+                .iter()
+                .map(|r| ((r.id.clone(), r.referrer.clone()), r.clone()))
+                .collect(),
+            files: recording
+                .files
+                .iter()
+                .map(|(filename, code)| (filename.clone(), code.clone()))
+                .collect(),
+        }
+    }
+
+    pub fn module_count(&self) -> usize {
+        self.modules.len() + self.files.len()
+    }
+
+    /// The recorded resolution of `require(id)` from `referrer`, for `op_require_resolve`
+    pub fn resolve_require(&self, id: &str, referrer: &str) -> Result<RequireResolution, JsErrorBox> {
+        let entry = self
+            .requires
+            .get(&(id.to_string(), referrer.to_string()))
+            .ok_or_else(|| {
+                JsErrorBox::generic(format!(
+                    "Cannot find module \"{}\" in the archive (required from '{}')",
+                    id, referrer
+                ))
+            })?;
+        Ok(RequireResolution {
+            filename: entry.filename.clone(),
+            url: entry.url.clone(),
+            format: if entry.format == "json" { "json" } else { "commonjs" },
+        })
+    }
+
+    /// A file the CommonJS loader reads, for `op_require_read`
+    pub fn read_require(&self, filename: &str) -> Result<String, JsErrorBox> {
+        self.files
+            .get(filename)
+            .cloned()
+            .ok_or_else(|| JsErrorBox::generic(format!("File '{}' is not part of the archive", filename)))
+    }
+
+    pub fn read(path: &str) -> Result<Self> {
+        let file = std::fs::File::open(path)
+            .map_err(|e| anyhow::anyhow!("Failed to open archive '{}': {}", path, e))?;
+
+        let mut files = HashMap::new();
+        let mut tar = tar::Archive::new(file);
+        for entry in tar.entries()? {
+            let mut entry = entry?;
+            let entry_path = entry.path()?.to_string_lossy().into_owned();
+            let mut data = Vec::new();
+            entry.read_to_end(&mut data)?;
+            files.insert(entry_path, data);
+        }
+
+        let manifest: Manifest = files
+            .get(MANIFEST_PATH)
+            .ok_or_else(|| anyhow::anyhow!("'{}' is not a V6 archive (missing {})", path, MANIFEST_PATH))
+            .and_then(|data| serde_json::from_slice(data).map_err(Into::into))?;
+        if manifest.version != ARCHIVE_VERSION {
+            return Err(anyhow::anyhow!(
+                "Unsupported archive version {} in '{}' (expected {})",
+                manifest.version,
+                path,
+                ARCHIVE_VERSION
+            ));
+        }
+
+        let modules = manifest
+            .modules
+            .into_iter()
+            .map(|entry| {
+                let code = files
+                    .remove(&entry.path)
+                    .ok_or_else(|| anyhow::anyhow!("Archive '{}' is missing '{}'", path, entry.path))?;
+                Ok((entry.specifier, (entry.module_type, code)))
+            })
+            .collect::<Result<HashMap<_, _>>>()?;
+
+        let required_files = manifest
+            .files
+            .into_iter()
+            .map(|entry| {
+                let code = files
+                    .remove(&entry.path)
+                    .ok_or_else(|| anyhow::anyhow!("Archive '{}' is missing '{}'", path, entry.path))?;
+                let code = String::from_utf8(code)
+                    .map_err(|_| anyhow::anyhow!("Archived file '{}' is not valid UTF-8", entry.filename))?;
+                Ok((entry.filename, code))
+            })
+            .collect::<Result<HashMap<_, _>>>()?;
+
+        Ok(Self {
+            main_module: deno_core::resolve_url(&manifest.main_module)?,
+            config: manifest.config,
+            resolutions: manifest
+                .resolutions
+                .into_iter()
+                .map(|r| ((r.specifier, r.referrer), r.resolved))
+                .collect(),
+            modules,
+            requires: manifest
+                .requires
+                .into_iter()
+                .map(|r| ((r.id.clone(), r.referrer.clone()), r))
+                .collect(),
+            files: required_files,
+        })
+    }
+
+    pub fn write(&self, path: &str) -> Result<()> {
+        let file = std::fs::File::create(path)
+            .map_err(|e| anyhow::anyhow!("Failed to create archive '{}': {}", path, e))?;
+        let mut tar = tar::Builder::new(file);
+
+        let mut specifiers: Vec<&String> = self.modules.keys().collect();
+        specifiers.sort();
+
+        let mut module_entries = Vec::with_capacity(specifiers.len());
+        for (index, specifier) in specifiers.into_iter().enumerate() {
+            let (module_type, code) = &self.modules[specifier];
+            let entry_path = format!("modules/{}", index);
+            append_file(&mut tar, &entry_path, code)?;
+            module_entries.push(ModuleEntry {
+                specifier: specifier.clone(),
+                module_type: module_type.clone(),
+                path: entry_path,
+            });
+        }
+
+        let mut resolutions: Vec<Resolution> = self
+            .resolutions
+            .iter()
+            .map(|((specifier, referrer), resolved)| Resolution {
+                specifier: specifier.clone(),
+                referrer: referrer.clone(),
+                resolved: resolved.clone(),
+            })
+            .collect();
+        resolutions.sort_by(|a, b| (&a.referrer, &a.specifier).cmp(&(&b.referrer, &b.specifier)));
+
+        let mut filenames: Vec<&String> = self.files.keys().collect();
+        filenames.sort();
+
+        let mut file_entries = Vec::with_capacity(filenames.len());
+        for (index, filename) in filenames.into_iter().enumerate() {
+            let entry_path = format!("files/{}", index);
+            append_file(&mut tar, &entry_path, self.files[filename].as_bytes())?;
+            file_entries.push(FileEntry {
+                filename: filename.clone(),
+                path: entry_path,
+            });
+        }
+
+        let mut requires: Vec<RequireEntry> = self.requires.values().cloned().collect();
+        requires.sort_by(|a, b| (&a.referrer, &a.id).cmp(&(&b.referrer, &b.id)));
+
+        let manifest = Manifest {
+            version: ARCHIVE_VERSION,
+            main_module: self.main_module.to_string(),
+            config: self.config.clone(),
+            resolutions,
+            modules: module_entries,
+            requires,
+            files: file_entries,
+        };
+        append_file(&mut tar, MANIFEST_PATH, &serde_json::to_vec_pretty(&manifest)?)?;
+
+        tar.finish()?;
+        Ok(())
+    }
+}
+
+fn append_file(tar: &mut tar::Builder<std::fs::File>, path: &str, data: &[u8]) -> Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    tar.append_data(&mut header, path, data)?;
+    Ok(())
+}
+
+/// Wraps the regular loader and records what it resolves and loads, for `v6 bundle`
+pub struct RecordingModuleLoader {
+    inner: Rc<dyn ModuleLoader>,
+    recording: Rc<RefCell<ModuleGraphRecording>>,
+}
+
+impl RecordingModuleLoader {
+    pub fn new(inner: Rc<dyn ModuleLoader>, recording: Rc<RefCell<ModuleGraphRecording>>) -> Self {
+        Self { inner, recording }
+    }
+
+    /// Load everything reachable from `main_module` that evaluation didn't load: imports of
+    /// functions that never ran, dynamic imports and the files CommonJS modules require.
+    /// Computed specifiers can't be followed, so they fail the bundle.
+    pub async fn record_full_graph(&self, main_module: &deno_core::ModuleSpecifier) -> Result<()> {
+        if !self.is_recorded(main_module) {
+            self.load_module(main_module, None, false, RequestedModuleType::None).await?;
+        }
+
+        let mut analyzed = HashSet::new();
+        loop {
+            let pending: Vec<(String, String)> = self
+                .recording
+                .borrow()
+                .modules
+                .iter()
+                .filter(|(specifier, (module_type, _))| {
+                    *module_type == ModuleType::JavaScript && !analyzed.contains(*specifier)
+                })
+                .map(|(specifier, (_, code))| (specifier.clone(), String::from_utf8_lossy(code).into_owned()))
+                .collect();
+            if pending.is_empty() {
+                return Ok(());
+            }
+
+            for (referrer, code) in pending {
+                let referrer_url = deno_core::resolve_url(&referrer)?;
+                for dependency in analyze_dependencies(&referrer, &code, MediaType::JavaScript)? {
+                    let kind = if dependency.is_dynamic {
+                        ResolutionKind::DynamicImport
+                    } else {
+                        ResolutionKind::Import
+                    };
+                    let resolved = self
+                        .resolve(&dependency.specifier, &referrer, kind)
+                        .map_err(|e| anyhow::anyhow!("Failed to bundle \"{}\": {}", referrer, e))?;
+                    if resolved.as_str() == CJS_RUNTIME_MODULE {
+                        self.record_commonjs(&referrer_url)?;
+                    }
+                    // Built-in modules are part of the runtime snapshot
+                    if resolved.scheme() == "ext" || self.is_recorded(&resolved) {
+                        continue;
+                    }
+                    let requested_module_type = match dependency.module_type.as_deref() {
+                        None => RequestedModuleType::None,
+                        Some("json") => RequestedModuleType::Json,
+                        Some(module_type) => RequestedModuleType::Other(Cow::Owned(module_type.to_string())),
+                    };
+                    self.load_module(&resolved, Some(&referrer_url), dependency.is_dynamic, requested_module_type)
+                        .await?;
+                }
+                analyzed.insert(referrer);
+            }
+        }
+    }
+
+    fn is_recorded(&self, specifier: &deno_core::ModuleSpecifier) -> bool {
+        self.recording.borrow().modules.contains_key(specifier.as_str())
+    }
+
+    async fn load_module(
+        &self,
+        specifier: &deno_core::ModuleSpecifier,
+        referrer: Option<&deno_core::ModuleSpecifier>,
+        is_dyn_import: bool,
+        requested_module_type: RequestedModuleType,
+    ) -> Result<()> {
+        let result = match self.load(specifier, referrer, is_dyn_import, requested_module_type) {
+            deno_core::ModuleLoadResponse::Sync(result) => result,
+            deno_core::ModuleLoadResponse::Async(future) => future.await,
+        };
+        result
+            .map(drop)
+            .map_err(|e| anyhow::anyhow!("Failed to bundle \"{}\": {}", specifier, e))
+    }
+
+    // Record the files the CommonJS module behind `wrapper` requires, so `op_require_resolve`
+    // and `op_require_read` can serve them from the archive
+    fn record_commonjs(&self, wrapper: &deno_core::ModuleSpecifier) -> Result<()> {
+        let path = wrapper
+            .to_file_path()
+            .map_err(|_| anyhow::anyhow!("CommonJS module \"{}\" is not a local file", wrapper))?;
+        let filename = path.to_string_lossy().into_owned();
+
+        // The wrapper requires its own file first, see `commonjs_module_wrapper`
+        let mut pending = vec![(filename.clone(), filename)];
+        while let Some((id, referrer)) = pending.pop() {
+            // A require() that doesn't resolve throws when it runs, from the archive too
+            let Ok(resolution) = node_resolver::resolve_require(&id, Path::new(&referrer)) else {
+                continue;
+            };
+            let mut recording = self.recording.borrow_mut();
+            recording.requires.push(RequireEntry {
+                id,
+                referrer,
+                filename: resolution.filename.clone(),
+                url: resolution.url.clone(),
+                format: resolution.format.to_string(),
+            });
+            if recording.files.contains_key(&resolution.filename) {
+                continue;
+            }
+
+            let code = std::fs::read_to_string(&resolution.filename)
+                .map_err(|e| anyhow::anyhow!("Failed to read '{}': {}", resolution.filename, e))?;
+            recording.files.insert(resolution.filename.clone(), code.clone());
+            drop(recording);
+
+            if resolution.format == "commonjs" {
+                for dependency in analyze_dependencies(&resolution.url, &code, MediaType::Cjs)? {
+                    pending.push((dependency.specifier, resolution.filename.clone()));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// An import, dynamic import or `require()` found in a module's source
+struct Dependency {
+    specifier: String,
+    is_dynamic: bool,
+    // The `type` import attribute
+    module_type: Option<String>,
+}
+
+// The imports of an ES module, or the `require()` calls of a CommonJS file
+fn analyze_dependencies(specifier: &str, code: &str, media_type: MediaType) -> Result<Vec<Dependency>> {
+    let parsed = deno_ast::parse_program(ParseParams {
+        specifier: deno_core::resolve_url(specifier)?,
+        text: code.into(),
+        media_type,
+        capture_tokens: false,
+        scope_analysis: false,
+        maybe_syntax: None,
+    })
+    .map_err(|e| anyhow::anyhow!("Failed to parse \"{}\" for bundling: {}", specifier, e))?;
+    let is_commonjs = media_type == MediaType::Cjs;
+
+    let mut dependencies = Vec::new();
+    for descriptor in parsed.analyze_dependencies() {
+        match descriptor {
+            DependencyDescriptor::Static(descriptor)
+                if !is_commonjs
+                    && matches!(descriptor.kind, StaticDependencyKind::Import | StaticDependencyKind::Export) =>
+            {
+                dependencies.push(Dependency {
+                    specifier: descriptor.specifier.to_string(),
+                    is_dynamic: false,
+                    module_type: descriptor.import_attributes.get("type").cloned(),
+                });
+            }
+            DependencyDescriptor::Dynamic(descriptor)
+                if is_commonjs == (descriptor.kind == DynamicDependencyKind::Require) =>
+            {
+                let call = if is_commonjs { "require()" } else { "import()" };
+                let Some(target) = static_argument(&descriptor.argument) else {
+                    let line = parsed
+                        .text_info_lazy()
+                        .line_and_column_display(descriptor.range.start)
+                        .line_number;
+                    return Err(anyhow::anyhow!(
+                        "Can't bundle \"{}\": the {} on line {} has a computed specifier",
+                        specifier,
+                        call,
+                        line
+                    ));
+                };
+                dependencies.push(Dependency {
+                    specifier: target,
+                    is_dynamic: true,
+                    module_type: descriptor.import_attributes.get("type").cloned(),
+                });
+            }
+            _ => {}
+        }
+    }
+    Ok(dependencies)
+}
+
+// String literals and template literals without expressions
+fn static_argument(argument: &DynamicArgument) -> Option<String> {
+    match argument {
+        DynamicArgument::String(value) => Some(value.to_string()),
+        DynamicArgument::Template(parts) => parts
+            .iter()
+            .map(|part| match part {
+                DynamicTemplatePart::String(value) => Some(value.as_str()),
+                DynamicTemplatePart::Expr => None,
+            })
+            .collect(),
+        DynamicArgument::Expr => None,
+    }
+}
+
+impl ModuleLoader for RecordingModuleLoader {
+    fn resolve(
+        &self,
+        specifier: &str,
+        referrer: &str,
+        kind: deno_core::ResolutionKind,
+    ) -> Result<deno_core::ModuleSpecifier, deno_core::error::ModuleLoaderError> {
+        let resolved = self.inner.resolve(specifier, referrer, kind)?;
+        self.recording.borrow_mut().resolutions.push(Resolution {
+            specifier: specifier.to_string(),
+            referrer: referrer.to_string(),
+            resolved: resolved.to_string(),
+        });
+        Ok(resolved)
+    }
+
+    fn load(
+        &self,
+        module_specifier: &deno_core::ModuleSpecifier,
+        maybe_referrer: Option<&deno_core::ModuleSpecifier>,
+        is_dyn_import: bool,
+        requested_module_type: deno_core::RequestedModuleType,
+    ) -> deno_core::ModuleLoadResponse {
+        let recording = self.recording.clone();
+        let specifier = module_specifier.to_string();
+        let record = move |source: deno_core::ModuleSource| {
+            recording.borrow_mut().modules.insert(
+                specifier,
+                (source.module_type.clone(), source.code.as_bytes().to_vec()),
+            );
+            source
+        };
+
+        match self
+            .inner
+            .load(module_specifier, maybe_referrer, is_dyn_import, requested_module_type)
+        {
+            deno_core::ModuleLoadResponse::Sync(result) => {
+                deno_core::ModuleLoadResponse::Sync(result.map(record))
+            }
+            deno_core::ModuleLoadResponse::Async(future) => {
+                deno_core::ModuleLoadResponse::Async(future.map(|result| result.map(record)).boxed_local())
+            }
+        }
+    }
+
+    fn get_source_map(&self, file_name: &str) -> Option<Cow<'_, [u8]>> {
+        self.inner.get_source_map(file_name)
+    }
+}
+
+/// Serves modules from an archive only, so a bundled test never touches the original tree
+pub struct ArchiveModuleLoader {
+    archive: Arc<Archive>,
+}
+
+impl ArchiveModuleLoader {
+    pub fn new(archive: Arc<Archive>) -> Self {
+        Self { archive }
+    }
+}
+
+impl ModuleLoader for ArchiveModuleLoader {
+    fn resolve(
+        &self,
+        specifier: &str,
+        referrer: &str,
+        _kind: deno_core::ResolutionKind,
+    ) -> Result<deno_core::ModuleSpecifier, deno_core::error::ModuleLoaderError> {
+        match self
+            .archive
+            .resolutions
+            .get(&(specifier.to_string(), referrer.to_string()))
+        {
+            Some(resolved) => deno_core::resolve_url(resolved).map_err(Into::into),
+            None => deno_core::resolve_import(specifier, referrer).map_err(Into::into),
+        }
+    }
+
+    fn load(
+        &self,
+        module_specifier: &deno_core::ModuleSpecifier,
+        _maybe_referrer: Option<&deno_core::ModuleSpecifier>,
+        _is_dyn_import: bool,
+        _requested_module_type: deno_core::RequestedModuleType,
+    ) -> deno_core::ModuleLoadResponse {
+        let result = match self.archive.modules.get(module_specifier.as_str()) {
+            Some((module_type, code)) => module_source_code(module_type, code).map(|code| {
+                deno_core::ModuleSource::new(module_type.clone(), code, module_specifier, None)
+            }),
+            None => Err(JsErrorBox::generic(format!(
+                "Module \"{}\" is not part of the archive",
+                module_specifier
+            ))),
+        };
+
+        deno_core::ModuleLoadResponse::Sync(result.map_err(Into::into))
+    }
+
+    fn get_source_map(&self, file_name: &str) -> Option<Cow<'_, [u8]>> {
+        let (module_type, code) = self.archive.modules.get(file_name)?;
+        if *module_type != ModuleType::JavaScript {
+            return None;
+        }
+        extract_inline_source_map(std::str::from_utf8(code).ok()?).map(Cow::Owned)
+    }
+}
+
+// Data modules of type "bytes" stay binary, everything else is text
+fn module_source_code(
+    module_type: &ModuleType,
+    code: &[u8],
+) -> Result<deno_core::ModuleSourceCode, JsErrorBox> {
+    if matches!(module_type, ModuleType::Other(ty) if ty == "bytes") {
+        return Ok(deno_core::ModuleSourceCode::Bytes(code.to_vec().into_boxed_slice().into()));
+    }
+
+    String::from_utf8(code.to_vec())
+        .map(|code| deno_core::ModuleSourceCode::String(code.into()))
+        .map_err(|_| JsErrorBox::type_error("Archived module is not valid UTF-8"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{LoaderOptions, TsModuleLoader};
+
+    fn write_files(root: &Path, files: &[(&str, &str)]) {
+        for (path, content) in files {
+            let path = root.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+    }
+
+    fn recording_loader(root: &Path) -> (RecordingModuleLoader, Rc<RefCell<ModuleGraphRecording>>) {
+        let inner = TsModuleLoader::new(&LoaderOptions {
+            root_dir: root.to_path_buf(),
+            ..Default::default()
+        })
+        .unwrap();
+        let recording = Rc::new(RefCell::new(ModuleGraphRecording::default()));
+        (
+            RecordingModuleLoader::new(Rc::new(inner), recording.clone()),
+            recording,
+        )
+    }
+
+    fn file_url(path: &Path) -> deno_core::ModuleSpecifier {
+        deno_core::url::Url::from_file_path(path).unwrap()
+    }
+
+    #[tokio::test]
+    async fn bundles_dynamic_imports_and_commonjs_requires() {
+        let project = tempfile::tempdir().unwrap();
+        let root = project.path();
+        write_files(
+            root,
+            &[
+                (
+                    "main.js",
+                    "import { helper } from \"./helper.js\";\n\
+                     import pkg from \"cjs-pkg\";\n\
+                     export default async function () {\n\
+                       await import(\"./lazy.js\");\n\
+                       await import(`./data.json`, { with: { type: \"json\" } });\n\
+                     }\n",
+                ),
+                ("helper.js", "export const helper = 1;"),
+                ("lazy.js", "import \"./nested.js\";"),
+                ("nested.js", ""),
+                ("data.json", "{}"),
+                (
+                    "node_modules/cjs-pkg/package.json",
+                    r#"{ "main": "index.js" }"#,
+                ),
+                (
+                    "node_modules/cjs-pkg/index.js",
+                    "const util = require(\"./util\");\n\
+                     const config = require(\"./config.json\");\n\
+                     try { require(\"optional-dependency\"); } catch {}\n\
+                     module.exports = util;\n",
+                ),
+                ("node_modules/cjs-pkg/util.js", "exports.value = 1;"),
+                ("node_modules/cjs-pkg/config.json", "{}"),
+            ],
+        );
+        let main = file_url(&root.join("main.js"));
+        let (loader, recording) = recording_loader(root);
+        loader.record_full_graph(&main).await.unwrap();
+
+        let archive = Archive::from_recording(
+            main.clone(),
+            TestConfig {
+                iterations: "1".to_string(),
+                duration: 30.0,
+                timeout: 30.0,
+                vus: 1,
+                thresholds: Default::default(),
+            },
+            &recording.borrow(),
+        );
+        let path = root.join("test.tar");
+        archive.write(path.to_str().unwrap()).unwrap();
+        let archive = Archive::read(path.to_str().unwrap()).unwrap();
+
+        let package_dir = root.join("node_modules/cjs-pkg");
+        let index = package_dir.join("index.js").to_string_lossy().into_owned();
+        let mut specifiers: Vec<_> = archive.modules.keys().map(String::as_str).collect();
+        specifiers.sort();
+        let expected = [
+            "data.json",
+            "helper.js",
+            "lazy.js",
+            "main.js",
+            "nested.js",
+            "node_modules/cjs-pkg/index.js",
+        ];
+        assert_eq!(
+            specifiers,
+            expected
+                .iter()
+                .map(|path| file_url(&root.join(path)).to_string())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            archive.modules[file_url(&root.join("data.json")).as_str()].0,
+            ModuleType::Json
+        );
+        assert_eq!(archive.module_count(), 9);
+
+        // Modules a dynamic import loads resolve and load from the archive
+        let archive = Arc::new(archive);
+        let archive_loader = ArchiveModuleLoader::new(archive.clone());
+        let lazy = archive_loader
+            .resolve("./lazy.js", main.as_str(), ResolutionKind::DynamicImport)
+            .unwrap();
+        assert_eq!(lazy, file_url(&root.join("lazy.js")));
+        let deno_core::ModuleLoadResponse::Sync(source) =
+            archive_loader.load(&lazy, Some(&main), true, RequestedModuleType::None)
+        else {
+            panic!("archive modules load synchronously");
+        };
+        assert!(source.is_ok());
+
+        // The CommonJS loader's requires are replayed from the archive
+        let resolution = archive.resolve_require(&index, &index).unwrap();
+        assert_eq!(
+            (resolution.filename.as_str(), resolution.format),
+            (index.as_str(), "commonjs")
+        );
+        let util = archive.resolve_require("./util", &index).unwrap();
+        assert_eq!(
+            archive.read_require(&util.filename).unwrap(),
+            "exports.value = 1;"
+        );
+        let config = archive.resolve_require("./config.json", &index).unwrap();
+        assert_eq!(config.format, "json");
+        assert_eq!(archive.read_require(&config.filename).unwrap(), "{}");
+        let Err(error) = archive.resolve_require("optional-dependency", &index) else {
+            panic!("unresolved requires aren't archived");
+        };
+        assert!(
+            error
+                .to_string()
+                .contains("Cannot find module \"optional-dependency\" in the archive")
+        );
+    }
+
+    #[tokio::test]
+    async fn computed_specifiers_fail_the_bundle() {
+        let project = tempfile::tempdir().unwrap();
+        let root = project.path();
+        write_files(
+            root,
+            &[
+                (
+                    "main.js",
+                    "const name = \"lazy\";\nawait import(`./${name}.js`);\n",
+                ),
+                ("required.js", "import \"cjs-pkg\";"),
+                (
+                    "node_modules/cjs-pkg/index.js",
+                    "const name = \"./util\";\nrequire(name);\n",
+                ),
+            ],
+        );
+
+        let (loader, _) = recording_loader(root);
+        let error = loader
+            .record_full_graph(&file_url(&root.join("main.js")))
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.contains("the import() on line 2 has a computed specifier"),
+            "{}",
+            error
+        );
+
+        let (loader, _) = recording_loader(root);
+        let error = loader
+            .record_full_graph(&file_url(&root.join("required.js")))
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.contains("the require() on line 2 has a computed specifier"),
+            "{}",
+            error
+        );
+    }
+}
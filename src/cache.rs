@@ -0,0 +1,249 @@
+use anyhow::Result;
+use deno_error::JsErrorBox;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+pub const LOCKFILE_NAME: &str = "v6.lock";
+const DEFAULT_CACHE_DIR: &str = ".v6/cache";
+const LOCKFILE_VERSION: u32 = 1;
+
+/// `v6.lock`: maps every remote module URL to the sha256 of its contents
+#[derive(Serialize, Deserialize)]
+struct Lockfile {
+    version: u32,
+    remote: BTreeMap<String, String>,
+}
+
+impl Default for Lockfile {
+    fn default() -> Self {
+        Self {
+            version: LOCKFILE_VERSION,
+            remote: BTreeMap::new(),
+        }
+    }
+}
+
+/// Content-addressed cache for `https://` modules, pinned by `v6.lock`.
+/// Files are stored as `<cache dir>/<sha256>`; the lockfile is the index.
+pub struct RemoteModuleCache {
+    cache_dir: PathBuf,
+    lockfile_path: PathBuf,
+    lockfile: RefCell<Lockfile>,
+    cached_only: bool,
+    client: reqwest::Client,
+}
+
+impl RemoteModuleCache {
+    /// Open the cache in `$V6_CACHE_DIR` (default `.v6/cache`) with `v6.lock`, both relative to
+    /// `root_dir`, the directory of the test file, so every command of a project shares them
+    pub fn new(root_dir: &Path, cached_only: bool) -> Result<Self> {
+        let cache_dir = root_dir.join(
+            std::env::var_os("V6_CACHE_DIR")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| PathBuf::from(DEFAULT_CACHE_DIR)),
+        );
+        Self::open(cache_dir, root_dir.join(LOCKFILE_NAME), cached_only)
+    }
+
+    fn open(cache_dir: PathBuf, lockfile_path: PathBuf, cached_only: bool) -> Result<Self> {
+        let lockfile = if lockfile_path.exists() {
+            let content = std::fs::read_to_string(&lockfile_path)
+                .map_err(|e| anyhow::anyhow!("Failed to read '{}': {}", lockfile_path.display(), e))?;
+            serde_json::from_str(&content)
+                .map_err(|e| anyhow::anyhow!("Invalid lockfile '{}': {}", lockfile_path.display(), e))?
+        } else {
+            Lockfile::default()
+        };
+
+        Ok(Self {
+            cache_dir,
+            lockfile_path,
+            lockfile: RefCell::new(lockfile),
+            cached_only,
+            client: reqwest::Client::new(),
+        })
+    }
+
+    /// Number of remote modules pinned in the lockfile
+    pub fn locked_count(&self) -> usize {
+        self.lockfile.borrow().remote.len()
+    }
+
+    /// Return the contents of a remote module, downloading it only when it isn't cached yet.
+    /// Contents are always checked against the hash recorded in `v6.lock`.
+    pub async fn load(&self, specifier: &deno_core::ModuleSpecifier) -> Result<Vec<u8>, JsErrorBox> {
+        let url = specifier.as_str();
+        // Plain HTTP would let anyone on the network swap the code before it's first pinned
+        if specifier.scheme() != "https" {
+            return Err(JsErrorBox::generic(format!(
+                "Remote module \"{}\" must be loaded over https://",
+                url
+            )));
+        }
+        let locked_hash = self.lockfile.borrow().remote.get(url).cloned();
+
+        if let Some(hash) = &locked_hash {
+            let cached_path = self.cache_dir.join(hash);
+            if cached_path.exists() {
+                let code = std::fs::read(&cached_path).map_err(|e| {
+                    JsErrorBox::generic(format!("Failed to read cache entry '{}': {}", cached_path.display(), e))
+                })?;
+                if sha256_hex(&code) != *hash {
+                    return Err(JsErrorBox::generic(format!(
+                        "Cache entry for \"{}\" is corrupted ('{}'). Delete it and run `v6 vendor` again",
+                        url,
+                        cached_path.display()
+                    )));
+                }
+                return Ok(code);
+            }
+        }
+
+        if self.cached_only {
+            return Err(JsErrorBox::generic(format!(
+                "\"{}\" is not in the module cache and --cached-only is set. Run `v6 vendor` first",
+                url
+            )));
+        }
+
+        let code = self.download(specifier).await?;
+        let hash = sha256_hex(&code);
+
+        match locked_hash {
+            Some(locked_hash) if locked_hash != hash => {
+                return Err(JsErrorBox::generic(format!(
+                    "Integrity check failed for \"{}\": {} expects {}, got {}",
+                    url, LOCKFILE_NAME, locked_hash, hash
+                )));
+            }
+            Some(_) => {}
+            None => {
+                self.lockfile.borrow_mut().remote.insert(url.to_string(), hash.clone());
+                self.write_lockfile()?;
+            }
+        }
+
+        std::fs::create_dir_all(&self.cache_dir)
+            .and_then(|_| std::fs::write(self.cache_dir.join(&hash), &code))
+            .map_err(|e| JsErrorBox::generic(format!("Failed to write module cache for \"{}\": {}", url, e)))?;
+
+        Ok(code)
+    }
+
+    async fn download(&self, specifier: &deno_core::ModuleSpecifier) -> Result<Vec<u8>, JsErrorBox> {
+        let response = self
+            .client
+            .get(specifier.as_str())
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| JsErrorBox::generic(format!("Failed to download \"{}\": {}", specifier, e)))?;
+
+        let bytes = response
+            .bytes()
+            .await
+            .map_err(|e| JsErrorBox::generic(format!("Failed to download \"{}\": {}", specifier, e)))?;
+
+        Ok(bytes.to_vec())
+    }
+
+    fn write_lockfile(&self) -> Result<(), JsErrorBox> {
+        let content = serde_json::to_string_pretty(&*self.lockfile.borrow())
+            .map_err(|e| JsErrorBox::generic(format!("Failed to serialize {}: {}", LOCKFILE_NAME, e)))?;
+        std::fs::write(&self.lockfile_path, content + "\n").map_err(|e| {
+            JsErrorBox::generic(format!("Failed to write '{}': {}", self.lockfile_path.display(), e))
+        })
+    }
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URL: &str = "https://example.com/lib.js";
+    const CODE: &[u8] = b"export const answer = 42;";
+
+    fn open_cache(dir: &Path, locked_hash: Option<&str>, cached_only: bool) -> RemoteModuleCache {
+        let mut lockfile = Lockfile::default();
+        if let Some(hash) = locked_hash {
+            lockfile.remote.insert(URL.to_string(), hash.to_string());
+        }
+        std::fs::write(dir.join(LOCKFILE_NAME), serde_json::to_string(&lockfile).unwrap()).unwrap();
+        RemoteModuleCache::open(dir.join("cache"), dir.join(LOCKFILE_NAME), cached_only).unwrap()
+    }
+
+    fn write_entry(dir: &Path, hash: &str, code: &[u8]) {
+        std::fs::create_dir_all(dir.join("cache")).unwrap();
+        std::fs::write(dir.join("cache").join(hash), code).unwrap();
+    }
+
+    #[tokio::test]
+    async fn serves_pinned_modules_from_the_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let hash = sha256_hex(CODE);
+        write_entry(dir.path(), &hash, CODE);
+        let cache = open_cache(dir.path(), Some(&hash), true);
+
+        let code = cache.load(&deno_core::resolve_url(URL).unwrap()).await.unwrap();
+        assert_eq!(code, CODE);
+        assert_eq!(cache.locked_count(), 1);
+    }
+
+    #[tokio::test]
+    async fn rejects_cache_entries_not_matching_the_lockfile() {
+        let dir = tempfile::tempdir().unwrap();
+        let hash = sha256_hex(CODE);
+        write_entry(dir.path(), &hash, b"export const answer = 0;");
+        let cache = open_cache(dir.path(), Some(&hash), true);
+
+        let error = cache.load(&deno_core::resolve_url(URL).unwrap()).await.unwrap_err();
+        assert!(error.to_string().contains("is corrupted"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn cache_miss_fails_with_cached_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = open_cache(dir.path(), None, true);
+
+        let error = cache.load(&deno_core::resolve_url(URL).unwrap()).await.unwrap_err();
+        assert!(error.to_string().contains("--cached-only"), "{}", error);
+        assert!(!dir.path().join("cache").exists());
+    }
+
+    #[tokio::test]
+    async fn pinned_module_missing_from_the_cache_fails_with_cached_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = open_cache(dir.path(), Some(&sha256_hex(CODE)), true);
+
+        let error = cache.load(&deno_core::resolve_url(URL).unwrap()).await.unwrap_err();
+        assert!(error.to_string().contains("--cached-only"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn rejects_plain_http_modules() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = open_cache(dir.path(), None, false);
+
+        let error = cache
+            .load(&deno_core::resolve_url("http://example.com/lib.js").unwrap())
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("https://"), "{}", error);
+    }
+
+    #[test]
+    fn lockfile_lives_in_the_root_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = RemoteModuleCache::new(dir.path(), false).unwrap();
+        assert_eq!(cache.lockfile_path, dir.path().join(LOCKFILE_NAME));
+    }
+}
@@ -1,7 +1,10 @@
 use anyhow::Result;
 use clap::{Parser, Subcommand};
+use deno_ast::MediaType;
 use serde::{Serialize, Deserialize};
-use std::path::Path;
+use std::{path::Path, time::Duration};
+
+use crate::thresholds::ThresholdsConfig;
 
 #[derive(Parser)]
 #[command(name = "v6")]
@@ -15,7 +18,8 @@ pub struct Cli {
 pub enum Commands {
     /// Initialize a new test file
     Init {
-        /// Name of the test file to create
+        /// Name of the test file to create. TypeScript files (.ts, .mts, .cts, .tsx) get a
+        /// typed template and v6.d.ts next to them
         #[arg(short, long)]
         file: String,
         
@@ -55,19 +59,117 @@ pub enum Commands {
         /// Number of virtual users (overrides file config)
         #[arg(short, long)]
         vus: Option<usize>,
+
+        /// Only load remote modules from the local module cache (see `v6 vendor`)
+        #[arg(long)]
+        cached_only: bool,
+
+        /// Import map file (defaults to the import map in the test file's v6.json, if any)
+        #[arg(long)]
+        import_map: Option<String>,
+
+        /// Write the end-of-test summary (metrics, checks, thresholds, metadata) as JSON
+        #[arg(long)]
+        summary_export: Option<String>,
+
+        /// Stream every metric sample to an output: json=<file> or csv=<file> (gzip-compressed
+        /// when the file name ends with .gz), prometheus-rw=<url>[,interval=5s] or
+        /// prometheus-scrape=<[host]:port>, otlp=<endpoint>[,protocol=http|grpc][,interval=10s],
+        /// influxdb=<url>[,token=<token>][,interval=1s] or
+        /// statsd=<host:port>[,namespace=v6.][,tags=datadog|influx|none]. Can be repeated.
+        #[arg(long = "out", value_name = "TYPE=TARGET")]
+        outputs: Vec<String>,
+
+        /// Write a report once the test is over: junit=<file> with a testcase per threshold
+        /// and check, markdown=<file> with key results as tables, or html=<file> with charts
+        /// over time and per-tag and per-group breakdowns. Can be repeated.
+        #[arg(long = "report", value_name = "TYPE=FILE")]
+        reports: Vec<String>,
+    },
+    /// Download the remote modules a test file imports into the module cache
+    Vendor {
+        /// Path to the test file
+        file: String,
+
+        /// Import map file (defaults to the import map in the test file's v6.json, if any)
+        #[arg(long)]
+        import_map: Option<String>,
+    },
+    /// Bundle a test file, its imports, data files and resolved config into one archive
+    Bundle {
+        /// Path to the test file
+        file: String,
+
+        /// Path of the archive to write (default: <file name>.tar)
+        #[arg(short, long)]
+        output: Option<String>,
+
+        /// Only load remote modules from the local module cache (see `v6 vendor`)
+        #[arg(long)]
+        cached_only: bool,
+
+        /// Import map file (defaults to the import map in the test file's v6.json, if any)
+        #[arg(long)]
+        import_map: Option<String>,
+    },
+    /// Write TypeScript declarations for the V6 runtime
+    Types {
+        /// Path of the declaration file to write
+        #[arg(short, long, default_value = "v6.d.ts")]
+        output: String,
     },
 }
 
-#[derive(Serialize, Deserialize)]
+/// Declarations for the runtime globals and built-in `v6/*` modules
+pub const TYPE_DECLARATIONS: &str = include_str!("types/v6.d.ts");
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct TestConfig {
     pub iterations: String,
     pub duration: f64,
     pub timeout: f64,
     pub vus: usize,
+    #[serde(default)]
+    pub thresholds: ThresholdsConfig,
 }
 
 pub fn init_command(file: &str, iterations: &str, duration: f64, timeout: f64, vus: usize) -> Result<()> {
-    let template = format!(r#"console.log("Starting test: {file}");
+    // The extensions the module loader transpiles as TypeScript
+    let is_typescript = matches!(
+        MediaType::from_path(Path::new(file)),
+        MediaType::TypeScript | MediaType::Mts | MediaType::Cts | MediaType::Tsx
+    );
+    let iterations_value = if iterations == "inf" { "Infinity".to_string() } else { iterations.to_string() };
+
+    let template = if is_typescript {
+        format!(r#"/// <reference path="./v6.d.ts" />
+import {{ sleep }} from "v6";
+
+console.log("Starting test: {file}");
+
+export default defineConfig({{
+  iterations: {iterations_value},
+  duration: {duration},
+  timeout: {timeout}, // max timeout for each iteration
+  vus: {vus}, // Virtual Users
+  iteration: async () => {{
+    console.log("[JS] iteration starting");
+
+    // Add your test logic here
+    // Example: await sleep(1000);
+
+    console.log("[JS] iteration completed");
+  }},
+}});
+"#,
+            file = file,
+            iterations_value = iterations_value,
+            duration = duration,
+            timeout = timeout,
+            vus = vus
+        )
+    } else {
+        format!(r#"console.log("Starting test: {file}");
 
 defineConfig({{
   iterations: {iterations_value},
@@ -84,19 +186,36 @@ defineConfig({{
   }},
 }});
 "#, 
-        file = file,
-        iterations_value = if iterations == "inf" { "Infinity".to_string() } else { iterations.to_string() },
-        duration = duration,
-        timeout = timeout,
-        vus = vus
-    );
+            file = file,
+            iterations_value = iterations_value,
+            duration = duration,
+            timeout = timeout,
+            vus = vus
+        )
+    };
     
     std::fs::write(file, template)?;
     println!("✅ Created test file: {}", file);
+
+    if is_typescript {
+        // Keep the declarations next to the test so the reference path resolves
+        let types_path = Path::new(file).with_file_name("v6.d.ts");
+        if !types_path.exists() {
+            types_command(&types_path.to_string_lossy())?;
+        }
+    }
+
     println!("📝 Edit the file to add your test logic inside the iteration function");
     Ok(())
 }
 
+pub fn types_command(output: &str) -> Result<()> {
+    std::fs::write(output, TYPE_DECLARATIONS)
+        .map_err(|e| anyhow::anyhow!("Failed to write '{}': {}", output, e))?;
+    println!("✅ Wrote type declarations: {}", output);
+    Ok(())
+}
+
 pub fn parse_iterations_override(iterations_str: &str) -> f64 {
     match iterations_str {
         "inf" | "infinity" => f64::INFINITY,
@@ -104,6 +223,22 @@ pub fn parse_iterations_override(iterations_str: &str) -> f64 {
     }
 }
 
+/// "500ms", "30s", "5m" or "1h"
+pub fn parse_duration(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    let split = value.find(|c: char| !c.is_ascii_digit() && c != '.')?;
+    let (amount, unit) = value.split_at(split);
+    let amount: f64 = amount.parse().ok()?;
+    let seconds = match unit {
+        "ms" => amount / 1000.0,
+        "s" => amount,
+        "m" => amount * 60.0,
+        "h" => amount * 3600.0,
+        _ => return None,
+    };
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
 pub fn validate_file_exists(file: &str) -> Result<()> {
     if !Path::new(file).exists() {
         eprintln!("❌ File not found: {}", file);
@@ -130,4 +265,34 @@ pub fn display_test_config(
     println!("⏰ Timeout per iteration: {}s", timeout);
     println!("👥 Virtual Users: {}", vus);
     println!("{}", "─".repeat(50));
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn init_uses_the_typescript_template_for_every_typescript_extension() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["test.ts", "test.mts", "test.cts", "test.tsx"] {
+            let file = dir.path().join(name);
+            init_command(file.to_str().unwrap(), "1", 10.0, 30.0, 1).unwrap();
+            let template = std::fs::read_to_string(&file).unwrap();
+            assert!(
+                template.starts_with("/// <reference path=\"./v6.d.ts\" />"),
+                "{}",
+                name
+            );
+        }
+        assert!(dir.path().join("v6.d.ts").exists());
+
+        let js_dir = tempfile::tempdir().unwrap();
+        for name in ["test.js", "test.mjs", "test.jsx"] {
+            let file = js_dir.path().join(name);
+            init_command(file.to_str().unwrap(), "inf", 10.0, 30.0, 1).unwrap();
+            let template = std::fs::read_to_string(&file).unwrap();
+            assert!(!template.contains("reference"), "{}", name);
+            assert!(template.contains("iterations: Infinity,"), "{}", name);
+        }
+        assert!(!js_dir.path().join("v6.d.ts").exists());
+    }
+}
@@ -1,144 +1,589 @@
 use anyhow::Result;
-use deno_core::{extension, op2};
+use base64::{Engine, prelude::BASE64_STANDARD};
+use deno_ast::{MediaType, ParseParams, SourceMapOption};
+use deno_core::{OpState, extension, op2};
+use futures::FutureExt;
 use deno_error::JsErrorBox;
 use std::{
+    borrow::Cow,
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
     rc::Rc,
     sync::{Arc, Mutex, OnceLock},
     time::{Duration, Instant},
 };
-use tokio::time::timeout;
+use tokio::{sync::watch, task::JoinHandle, time::timeout};
+
+use crate::archive::{Archive, ArchiveModuleLoader, ModuleGraphRecording, RecordingModuleLoader};
+use crate::cache::RemoteModuleCache;
+use crate::http_client::HttpClient;
+use crate::import_map::ImportMap;
+use crate::metrics::{HttpRequestSample, MetricContains, MetricKind, MetricsRegistry, SharedMetrics, Tags};
+use crate::node_resolver::{self, RequireResolution, ResolutionMode};
+use crate::outputs::{OutputConfig, OutputWriter, RunInfo};
+use crate::thresholds::{
+    Threshold, ThresholdsConfig, evaluate_thresholds, find_aborting_threshold, print_threshold_results,
+    thresholds_json, track_thresholds,
+};
 
 static RUNTIME_SNAPSHOT: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/V6_SNAPSHOT.bin"));
 
-pub struct TsModuleLoader;
+// Source maps of transpiled modules, keyed by module specifier
+type SourceMapStore = Rc<RefCell<HashMap<String, Vec<u8>>>>;
+
+/// How the module loader of every runtime created for a test should behave
+#[derive(Clone, Default)]
+pub struct LoaderOptions {
+    /// Never download remote modules, only use the local module cache
+    pub cached_only: bool,
+    /// Import map applied to every specifier before regular resolution
+    pub import_map: Option<Arc<ImportMap>>,
+    /// Serve every module from a `v6 bundle` archive instead of the file system
+    pub archive: Option<Arc<Archive>>,
+    /// Directory of the test file, which holds `v6.lock` and the module cache
+    pub root_dir: PathBuf,
+}
+
+pub struct TsModuleLoader {
+    source_maps: SourceMapStore,
+    remote_cache: Rc<RemoteModuleCache>,
+    import_map: Option<Arc<ImportMap>>,
+}
+
+impl TsModuleLoader {
+    pub fn new(options: &LoaderOptions) -> Result<Self> {
+        Ok(Self {
+            source_maps: SourceMapStore::default(),
+            remote_cache: Rc::new(RemoteModuleCache::new(&options.root_dir, options.cached_only)?),
+            import_map: options.import_map.clone(),
+        })
+    }
+}
 
 impl deno_core::ModuleLoader for TsModuleLoader {
     fn resolve(
         &self,
         specifier: &str,
-        _referrer: &str,
+        referrer: &str,
         _kind: deno_core::ResolutionKind,
     ) -> Result<deno_core::ModuleSpecifier, deno_core::error::ModuleLoaderError> {
-        deno_core::resolve_url(specifier).map_err(Into::into)
+        if let Some(mapped) = self
+            .import_map
+            .as_ref()
+            .and_then(|import_map| import_map.resolve(specifier, referrer))
+        {
+            return Ok(mapped);
+        }
+
+        if let Some(builtin) = resolve_builtin_module(specifier) {
+            return builtin;
+        }
+
+        if let Some(package) = resolve_node_package(specifier, referrer)? {
+            return Ok(package);
+        }
+
+        // Relative specifiers ("./helpers.ts") are resolved against the importing module
+        deno_core::resolve_import(specifier, referrer).map_err(Into::into)
     }
 
     fn load(
         &self,
-        _module_specifier: &deno_core::ModuleSpecifier,
+        module_specifier: &deno_core::ModuleSpecifier,
         _maybe_referrer: Option<&deno_core::ModuleSpecifier>,
         _is_dyn_import: bool,
-        _requested_module_type: deno_core::RequestedModuleType,
+        requested_module_type: deno_core::RequestedModuleType,
     ) -> deno_core::ModuleLoadResponse {
-        // Simple implementation for basic functionality
-        todo!("Module loading not implemented")
+        if matches!(module_specifier.scheme(), "https" | "http") {
+            let remote_cache = self.remote_cache.clone();
+            let source_maps = self.source_maps.clone();
+            let module_specifier = module_specifier.clone();
+            return deno_core::ModuleLoadResponse::Async(
+                async move {
+                    let code = remote_cache.load(&module_specifier).await?;
+                    create_module_source(
+                        &module_specifier,
+                        MediaType::from_specifier(&module_specifier),
+                        code,
+                        requested_module_type,
+                        &source_maps,
+                    )
+                }
+                .boxed_local(),
+            );
+        }
+
+        deno_core::ModuleLoadResponse::Sync(load_local_module(
+            module_specifier,
+            requested_module_type,
+            &self.source_maps,
+        ))
+    }
+
+    // Used by deno_core to map exception and op stack traces back to the original .ts positions
+    fn get_source_map(&self, file_name: &str) -> Option<Cow<'_, [u8]>> {
+        self.source_maps
+            .borrow()
+            .get(file_name)
+            .map(|source_map| Cow::Owned(source_map.clone()))
     }
 }
 
-extension!(v6, ops = [op_set_timeout, op_fetch],
+// Resolve bare specifiers imported from local files through node_modules
+fn resolve_node_package(
+    specifier: &str,
+    referrer: &str,
+) -> Result<Option<deno_core::ModuleSpecifier>, JsErrorBox> {
+    if !node_resolver::is_bare_specifier(specifier) {
+        return Ok(None);
+    }
+    let Some(referrer_path) = deno_core::resolve_url(referrer)
+        .ok()
+        .and_then(|referrer| referrer.to_file_path().ok())
+    else {
+        return Ok(None);
+    };
+    let referrer_dir = referrer_path.parent().unwrap_or(&referrer_path);
+
+    node_resolver::resolve_package(specifier, referrer_dir, ResolutionMode::Import)?
+        .map(|path| {
+            deno_core::url::Url::from_file_path(&path)
+                .map_err(|_| JsErrorBox::generic(format!("Invalid module path '{}'", path.display())))
+        })
+        .transpose()
+}
+
+// Read a file:// module from disk
+fn load_local_module(
+    module_specifier: &deno_core::ModuleSpecifier,
+    requested_module_type: deno_core::RequestedModuleType,
+    source_maps: &SourceMapStore,
+) -> Result<deno_core::ModuleSource, deno_core::error::ModuleLoaderError> {
+    let path = module_specifier.to_file_path().map_err(|_| {
+        JsErrorBox::generic(format!(
+            "Only file:// modules are supported, got \"{}\"",
+            module_specifier
+        ))
+    })?;
+
+    let code = std::fs::read(&path).map_err(|e| {
+        JsErrorBox::generic(format!("Failed to read module '{}': {}", path.display(), e))
+    })?;
+
+    // CommonJS packages from node_modules are imported through an ES module facade
+    if requested_module_type == deno_core::RequestedModuleType::None
+        && let Ok(text) = std::str::from_utf8(&code)
+        && node_resolver::is_commonjs(&path, text)
+    {
+        let wrapper = node_resolver::commonjs_module_wrapper(&path, text)?;
+        return create_module_source(
+            module_specifier,
+            MediaType::JavaScript,
+            wrapper.into_bytes(),
+            requested_module_type,
+            source_maps,
+        );
+    }
+
+    create_module_source(
+        module_specifier,
+        MediaType::from_path(&path),
+        code,
+        requested_module_type,
+        source_maps,
+    )
+}
+
+// Turn raw module bytes into a ModuleSource according to the import attributes:
+// `type: "json"`, `type: "text"` and `type: "bytes"` load data, anything else is
+// JS/TS code, transpiled to plain JS when needed
+fn create_module_source(
+    module_specifier: &deno_core::ModuleSpecifier,
+    media_type: MediaType,
+    code: Vec<u8>,
+    requested_module_type: deno_core::RequestedModuleType,
+    source_maps: &SourceMapStore,
+) -> Result<deno_core::ModuleSource, deno_core::error::ModuleLoaderError> {
+    let (module_type, code) = match requested_module_type {
+        deno_core::RequestedModuleType::Json => (
+            deno_core::ModuleType::Json,
+            deno_core::ModuleSourceCode::String(module_text(module_specifier, code)?.into()),
+        ),
+        deno_core::RequestedModuleType::Other(module_type) if module_type == TEXT_MODULE_TYPE => (
+            deno_core::ModuleType::Other(module_type),
+            deno_core::ModuleSourceCode::String(module_text(module_specifier, code)?.into()),
+        ),
+        deno_core::RequestedModuleType::Other(module_type) if module_type == BYTES_MODULE_TYPE => (
+            deno_core::ModuleType::Other(module_type),
+            deno_core::ModuleSourceCode::Bytes(code.into_boxed_slice().into()),
+        ),
+        deno_core::RequestedModuleType::Other(module_type) => {
+            return Err(JsErrorBox::type_error(format!(
+                "Unsupported import type \"{}\" for \"{}\". Expected \"json\", \"text\" or \"bytes\"",
+                module_type, module_specifier
+            ))
+            .into());
+        }
+        deno_core::RequestedModuleType::None => {
+            let should_transpile = match media_type {
+                MediaType::JavaScript | MediaType::Mjs | MediaType::Cjs => false,
+                MediaType::Jsx | MediaType::TypeScript | MediaType::Mts | MediaType::Cts | MediaType::Tsx => true,
+                MediaType::Json => return Err(deno_core::error::ModuleLoaderError::JsonMissingAttribute),
+                _ => {
+                    return Err(JsErrorBox::generic(format!(
+                        "Unsupported module type {} for \"{}\"",
+                        media_type, module_specifier
+                    ))
+                    .into());
+                }
+            };
+
+            let code = module_text(module_specifier, code)?;
+            let code = if should_transpile {
+                let code = transpile(module_specifier, code, media_type)?;
+                if let Some(source_map) = extract_inline_source_map(&code) {
+                    source_maps
+                        .borrow_mut()
+                        .insert(module_specifier.to_string(), source_map);
+                }
+                code
+            } else {
+                code
+            };
+
+            (
+                deno_core::ModuleType::JavaScript,
+                deno_core::ModuleSourceCode::String(code.into()),
+            )
+        }
+    };
+
+    Ok(deno_core::ModuleSource::new(
+        module_type,
+        code,
+        module_specifier,
+        None,
+    ))
+}
+
+fn module_text(
+    module_specifier: &deno_core::ModuleSpecifier,
+    code: Vec<u8>,
+) -> Result<String, JsErrorBox> {
+    String::from_utf8(code).map_err(|_| {
+        JsErrorBox::type_error(format!("Module \"{}\" is not valid UTF-8", module_specifier))
+    })
+}
+
+const TEXT_MODULE_TYPE: &str = "text";
+const BYTES_MODULE_TYPE: &str = "bytes";
+
+// Evaluates `type: "text"` imports to a string and `type: "bytes"` imports to a
+// Uint8Array, both exposed as the module's default export
+fn evaluate_data_module(
+    scope: &mut deno_core::v8::HandleScope,
+    module_type: Cow<'_, str>,
+    module_name: &deno_core::FastString,
+    code: deno_core::ModuleSourceCode,
+) -> Result<deno_core::CustomModuleEvaluationKind, JsErrorBox> {
+    let value: deno_core::v8::Local<deno_core::v8::Value> = match (module_type.as_ref(), code) {
+        (TEXT_MODULE_TYPE, deno_core::ModuleSourceCode::String(text)) => {
+            deno_core::v8::String::new(scope, text.as_str())
+                .ok_or_else(|| {
+                    JsErrorBox::type_error(format!("Text module \"{}\" is too large", module_name.as_str()))
+                })?
+                .into()
+        }
+        (BYTES_MODULE_TYPE, deno_core::ModuleSourceCode::Bytes(bytes)) => {
+            let data = bytes.as_bytes().to_vec();
+            let length = data.len();
+            let backing_store = deno_core::v8::ArrayBuffer::new_backing_store_from_vec(data).make_shared();
+            let buffer = deno_core::v8::ArrayBuffer::with_backing_store(scope, &backing_store);
+            deno_core::v8::Uint8Array::new(scope, buffer, 0, length)
+                .ok_or_else(|| {
+                    JsErrorBox::type_error(format!("Bytes module \"{}\" is too large", module_name.as_str()))
+                })?
+                .into()
+        }
+        (module_type, _) => {
+            return Err(JsErrorBox::type_error(format!(
+                "Unsupported module type \"{}\" for \"{}\"",
+                module_type,
+                module_name.as_str()
+            )));
+        }
+    };
+
+    Ok(deno_core::CustomModuleEvaluationKind::Synthetic(
+        deno_core::v8::Global::new(scope, value),
+    ))
+}
+
+// Strip types from a TypeScript/JSX module using deno_ast (no type checking).
+// The emitted code carries an inline source map pointing at the original source.
+fn transpile(
+    module_specifier: &deno_core::ModuleSpecifier,
+    code: String,
+    media_type: MediaType,
+) -> Result<String, JsErrorBox> {
+    let parsed = deno_ast::parse_module(ParseParams {
+        specifier: module_specifier.clone(),
+        text: code.into(),
+        media_type,
+        capture_tokens: false,
+        scope_analysis: false,
+        maybe_syntax: None,
+    })
+    .map_err(JsErrorBox::from_err)?;
+
+    let transpiled = parsed
+        .transpile(
+            &deno_ast::TranspileOptions {
+                imports_not_used_as_values: deno_ast::ImportsNotUsedAsValues::Remove,
+                ..Default::default()
+            },
+            &deno_ast::TranspileModuleOptions { module_kind: None },
+            &deno_ast::EmitOptions {
+                source_map: SourceMapOption::Inline,
+                inline_sources: true,
+                ..Default::default()
+            },
+        )
+        .map_err(JsErrorBox::from_err)?;
+
+    Ok(transpiled.into_source().text)
+}
+
+const INLINE_SOURCE_MAP_PREFIX: &str = "//# sourceMappingURL=data:application/json;base64,";
+
+// Decode the trailing inline source map comment emitted by `transpile`
+pub(crate) fn extract_inline_source_map(code: &str) -> Option<Vec<u8>> {
+    let start = code.rfind(INLINE_SOURCE_MAP_PREFIX)? + INLINE_SOURCE_MAP_PREFIX.len();
+    BASE64_STANDARD.decode(code[start..].trim_end()).ok()
+}
+
+extension!(v6, ops = [op_set_timeout, op_fetch, op_b64_encode, op_b64_decode, op_require_resolve, op_require_read, op_metric_register, op_metric_add],
     esm_entry_point = "ext:v6/runtime.js",
-    esm = [dir "src", "runtime.js"],);
+    esm = [dir "src", "runtime.js", "modules/index.js", "modules/http.js", "modules/data.js", "modules/encoding.js", "modules/cjs.js", "modules/metrics.js", "modules/execution.js", "modules/checks.js"],);
+
+// Built-in modules scripts can import, mapped to their sources in the snapshot
+const BUILTIN_MODULES: &[(&str, &str)] = &[
+    ("v6", "ext:v6/modules/index.js"),
+    ("v6/http", "ext:v6/modules/http.js"),
+    ("v6/data", "ext:v6/modules/data.js"),
+    ("v6/encoding", "ext:v6/modules/encoding.js"),
+    ("v6/metrics", "ext:v6/modules/metrics.js"),
+];
+
+fn resolve_builtin_module(
+    specifier: &str,
+) -> Option<Result<deno_core::ModuleSpecifier, deno_core::error::ModuleLoaderError>> {
+    if specifier != "v6" && !specifier.starts_with("v6/") {
+        return None;
+    }
+
+    let resolved = BUILTIN_MODULES
+        .iter()
+        .find(|(name, _)| *name == specifier)
+        .map(|(_, ext_specifier)| deno_core::resolve_url(ext_specifier).map_err(Into::into))
+        .unwrap_or_else(|| {
+            Err(JsErrorBox::type_error(format!("Unknown built-in module \"{}\"", specifier)).into())
+        });
+
+    Some(resolved)
+}
 
 #[op2(async, stack_trace)]
 async fn op_set_timeout(delay: f64) {
     tokio::time::sleep(std::time::Duration::from_millis(delay as u64)).await;
 }
 
+fn base64_engine(encoding: &str) -> Result<base64::engine::GeneralPurpose, JsErrorBox> {
+    match encoding {
+        "std" => Ok(base64::engine::general_purpose::STANDARD),
+        "rawstd" => Ok(base64::engine::general_purpose::STANDARD_NO_PAD),
+        "url" => Ok(base64::engine::general_purpose::URL_SAFE),
+        "rawurl" => Ok(base64::engine::general_purpose::URL_SAFE_NO_PAD),
+        _ => Err(JsErrorBox::type_error(format!("Unsupported base64 encoding: {}", encoding))),
+    }
+}
+
+#[op2]
+#[string]
+fn op_b64_encode(#[buffer] data: &[u8], #[string] encoding: &str) -> Result<String, JsErrorBox> {
+    Ok(base64_engine(encoding)?.encode(data))
+}
+
+#[op2]
+#[buffer]
+fn op_b64_decode(#[string] input: &str, #[string] encoding: &str) -> Result<Vec<u8>, JsErrorBox> {
+    base64_engine(encoding)?
+        .decode(input)
+        .map_err(|e| JsErrorBox::type_error(format!("Invalid base64 input: {}", e)))
+}
+
+// Bundled tests resolve and read CommonJS files from their archive
+#[op2]
+#[serde]
+fn op_require_resolve(
+    state: &mut OpState,
+    #[string] id: &str,
+    #[string] referrer: &str,
+) -> Result<RequireResolution, JsErrorBox> {
+    match state.try_borrow::<Arc<Archive>>() {
+        Some(archive) => archive.resolve_require(id, referrer),
+        None => node_resolver::resolve_require(id, std::path::Path::new(referrer)),
+    }
+}
+
+#[op2]
+#[string]
+fn op_require_read(state: &mut OpState, #[string] filename: &str) -> Result<String, JsErrorBox> {
+    if let Some(archive) = state.try_borrow::<Arc<Archive>>() {
+        return archive.read_require(filename);
+    }
+    std::fs::read_to_string(filename)
+        .map_err(|e| JsErrorBox::generic(format!("Failed to read '{}': {}", filename, e)))
+}
+
+#[op2(fast)]
+fn op_metric_register(
+    state: &mut OpState,
+    #[string] name: &str,
+    #[string] kind: &str,
+    is_time: bool,
+) -> Result<(), JsErrorBox> {
+    let kind = MetricKind::parse(kind)
+        .ok_or_else(|| JsErrorBox::type_error(format!("Unknown metric type: {}", kind)))?;
+    let contains = if is_time { MetricContains::Time } else { MetricContains::Default };
+    state
+        .borrow::<SharedMetrics>()
+        .borrow_mut()
+        .register(name, kind, contains)
+        .map_err(JsErrorBox::type_error)
+}
+
+#[op2]
+fn op_metric_add(
+    state: &mut OpState,
+    #[string] name: &str,
+    value: f64,
+    #[serde] tags: Option<Tags>,
+) -> Result<(), JsErrorBox> {
+    state
+        .borrow::<SharedMetrics>()
+        .borrow_mut()
+        .add(name, value, &tags.unwrap_or_default())
+        .map_err(JsErrorBox::type_error)
+}
+
 // Global HTTP client for reuse
-static HTTP_CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
-
-// Create optimized HTTP client for localhost testing with reasonable timeouts
-fn create_optimized_client() -> reqwest::Client {
-    reqwest::Client::builder()
-        .pool_max_idle_per_host(100)
-        .pool_idle_timeout(std::time::Duration::from_secs(30))
-        .tcp_keepalive(std::time::Duration::from_secs(60))
-        .timeout(std::time::Duration::from_secs(10))  // Reasonable timeout for localhost
-        .user_agent("V6-LoadTest/1.0")
-        .connect_timeout(std::time::Duration::from_secs(5))  // Reasonable connect timeout
-        .tcp_nodelay(true)
-        .http2_keep_alive_interval(Some(std::time::Duration::from_secs(30)))
-        .http2_keep_alive_timeout(std::time::Duration::from_secs(10))
-        .http2_keep_alive_while_idle(true)
-        .local_address(None)
-        .build()
-        .expect("Failed to create HTTP client")
+static HTTP_CLIENT: OnceLock<HttpClient> = OnceLock::new();
+
+// Whole-request timeout, connection setup included
+const HTTP_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Create optimized HTTP client for localhost testing with a large keep-alive pool
+fn create_optimized_client() -> HttpClient {
+    HttpClient::new(Duration::from_secs(30), 100).expect("Failed to create HTTP client")
 }
 
 #[op2(async, stack_trace)]
 #[serde]
 async fn op_fetch(
+    state: Rc<RefCell<OpState>>,
     #[string] url: String,
     #[serde] options: Option<serde_json::Value>,
 ) -> Result<serde_json::Value, JsErrorBox> {
-    // Use tokio::spawn to run the fetch operation in parallel
-    let fetch_task = tokio::spawn(async move {
-        let client = HTTP_CLIENT.get_or_init(create_optimized_client);
-        let mut request = client.get(&url);
-
-        // Parse options if provided
-        if let Some(opts) = options {
-            // Set HTTP method
-            if let Some(method) = opts.get("method").and_then(|v| v.as_str()) {
-                request = match method.to_uppercase().as_str() {
-                    "GET" => client.get(&url),
-                    "POST" => client.post(&url),
-                    "PUT" => client.put(&url),
-                    "DELETE" => client.delete(&url),
-                    "PATCH" => client.patch(&url),
-                    "HEAD" => client.head(&url),
-                    _ => return Err(format!("Unsupported HTTP method: {}", method)),
-                };
-            }
-
-            // Set headers
-            if let Some(headers) = opts.get("headers").and_then(|v| v.as_object()) {
-                for (key, value) in headers {
-                    if let Some(value_str) = value.as_str() {
-                        request = request.header(key, value_str);
-                    }
+    let mut method = "GET".to_string();
+    let mut headers = Vec::new();
+    let mut body = None;
+    let mut tags = Tags::new();
+    let mut name = None;
+
+    // Parse options if provided
+    if let Some(opts) = options {
+        if let Some(value) = opts.get("method").and_then(|v| v.as_str()) {
+            method = value.to_string();
+        }
+        if let Some(values) = opts.get("headers").and_then(|v| v.as_object()) {
+            for (key, value) in values {
+                if let Some(value_str) = value.as_str() {
+                    headers.push((key.clone(), value_str.to_string()));
                 }
             }
-
-            // Set body
-            if let Some(body) = opts.get("body").and_then(|v| v.as_str()) {
-                request = request.body(body.to_string());
-            }
         }
-
-        let response_result = request.send().await;
-
-        match response_result {
-            Ok(response) => {
-                let status = response.status().as_u16();
-                let is_success = (200..300).contains(&status);
-
-                let headers: std::collections::HashMap<String, String> = response
-                    .headers()
-                    .iter()
-                    .map(|(k, v)| (k.to_string(), v.to_str().unwrap_or("").to_string()))
-                    .collect();
-
-                match response.text().await {
-                    Ok(text) => Ok(serde_json::json!({
-                        "status": status,
-                        "ok": is_success,
-                        "statusText": reqwest::StatusCode::from_u16(status).unwrap().canonical_reason().unwrap_or(""),
-                        "headers": headers,
-                        "body": text
-                    })),
-                    Err(e) => Err(format!("Failed to read response body: {}", e)),
+        if let Some(value) = opts.get("body").and_then(|v| v.as_str()) {
+            body = Some(value.to_string());
+        }
+        if let Some(values) = opts.get("tags").and_then(|v| v.as_object()) {
+            for (key, value) in values {
+                if let Some(value_str) = value.as_str() {
+                    tags.insert(key.clone(), value_str.to_string());
                 }
             }
-            Err(e) => Err(format!("Request failed: {}", e)),
         }
+        if let Some(value) = opts.get("name").and_then(|v| v.as_str()) {
+            name = Some(value.to_string());
+        }
+    }
+    let bytes_sent = body.as_ref().map_or(0, String::len);
+
+    // Default tags. A name replaces the URL, so dynamic URLs don't each become their own series
+    tags.insert("method".to_string(), method.to_uppercase());
+    tags.insert("url".to_string(), name.clone().unwrap_or_else(|| url.clone()));
+    if let Some(name) = name {
+        tags.insert("name".to_string(), name);
+    }
+
+    // Use tokio::spawn to run the fetch operation in parallel
+    let fetch_task = tokio::spawn(async move {
+        let client = HTTP_CLIENT.get_or_init(create_optimized_client);
+        let started = Instant::now();
+        let result = timeout(HTTP_REQUEST_TIMEOUT, client.execute(&method, &url, &headers, body))
+            .await
+            .unwrap_or_else(|_| Err(format!("Request failed: timed out after {:?}", HTTP_REQUEST_TIMEOUT)));
+        (result, started.elapsed())
     });
 
     // Await the spawned task
-    match fetch_task.await {
-        Ok(Ok(response)) => Ok(response),
-        Ok(Err(error_msg)) => Err(JsErrorBox::type_error(error_msg)),
-        Err(join_error) => Err(JsErrorBox::type_error(format!("Task join error: {}", join_error))),
-    }
+    let (result, elapsed) = fetch_task
+        .await
+        .map_err(|join_error| JsErrorBox::type_error(format!("Task join error: {}", join_error)))?;
+
+    // Every request is recorded, failed ones included; "0" is the status of requests without response
+    let status = result.as_ref().map_or(0, |response| response.status);
+    tags.insert("status".to_string(), status.to_string());
+    let sample = match &result {
+        Ok(response) => HttpRequestSample {
+            duration: response.timings.duration(),
+            status: Some(response.status),
+            bytes_sent,
+            bytes_received: response.body.len(),
+            timings: Some(response.timings),
+            tags,
+        },
+        Err(_) => HttpRequestSample {
+            duration: elapsed,
+            status: None,
+            bytes_sent,
+            bytes_received: 0,
+            timings: None,
+            tags,
+        },
+    };
+    state.borrow().borrow::<SharedMetrics>().borrow_mut().record_http_request(&sample);
+
+    let response = result.map_err(JsErrorBox::type_error)?;
+    Ok(serde_json::json!({
+        "status": response.status,
+        "ok": (200..300).contains(&response.status),
+        "statusText": reqwest::StatusCode::from_u16(response.status).ok().and_then(|s| s.canonical_reason()).unwrap_or(""),
+        "headers": response.headers,
+        "body": response.body,
+        "timings": response.timings.to_json(),
+    }))
 }
 
 pub fn extract_iterations(js_runtime: Arc<Mutex<deno_core::JsRuntime>>) -> Result<f64> {
@@ -151,15 +596,52 @@ pub fn extract_iterations(js_runtime: Arc<Mutex<deno_core::JsRuntime>>) -> Resul
     let compiled_code =
         deno_core::v8::Script::compile(&mut scope, iterations_script, None).unwrap();
 
-    if let Some(result) = compiled_code.run(&mut scope) {
-        if let Some(number) = result.number_value(&mut scope) {
-            return Ok(number);
-        }
+    if let Some(result) = compiled_code.run(&mut scope)
+        && let Some(number) = result.number_value(&mut scope)
+    {
+        return Ok(number);
     }
 
     Ok(1.0) // Default to 1 iteration if not found
 }
 
+pub fn extract_thresholds(js_runtime: Arc<Mutex<deno_core::JsRuntime>>) -> Result<ThresholdsConfig> {
+    let mut runtime = js_runtime.lock().unwrap();
+    let mut scope = runtime.handle_scope();
+
+    let thresholds_script = deno_core::v8::String::new(
+        &mut scope,
+        "JSON.stringify(globalThis.currentConfig.thresholds || {})",
+    )
+    .unwrap();
+
+    let compiled_code = deno_core::v8::Script::compile(&mut scope, thresholds_script, None).unwrap();
+
+    let json = compiled_code
+        .run(&mut scope)
+        .map(|result| result.to_rust_string_lossy(&mut scope))
+        .unwrap_or_else(|| "{}".to_string());
+
+    // A single definition may be given instead of a list
+    let raw: BTreeMap<String, serde_json::Value> = serde_json::from_str(&json)
+        .map_err(|e| anyhow::anyhow!("Invalid thresholds: {}", e))?;
+    raw.into_iter()
+        .map(|(key, value)| {
+            let value = match value {
+                serde_json::Value::Array(_) => value,
+                value => serde_json::Value::Array(vec![value]),
+            };
+            let definitions = serde_json::from_value(value).map_err(|_| {
+                anyhow::anyhow!(
+                    "Thresholds of \"{}\" must be expressions or {{ threshold, abortOnFail, delayAbortEval }} objects",
+                    key
+                )
+            })?;
+            Ok((key, definitions))
+        })
+        .collect()
+}
+
 pub fn extract_duration(js_runtime: Arc<Mutex<deno_core::JsRuntime>>) -> Result<f64> {
     let mut runtime = js_runtime.lock().unwrap();
     let mut scope = runtime.handle_scope();
@@ -169,10 +651,10 @@ pub fn extract_duration(js_runtime: Arc<Mutex<deno_core::JsRuntime>>) -> Result<
 
     let compiled_code = deno_core::v8::Script::compile(&mut scope, duration_script, None).unwrap();
 
-    if let Some(result) = compiled_code.run(&mut scope) {
-        if let Some(number) = result.number_value(&mut scope) {
-            return Ok(number);
-        }
+    if let Some(result) = compiled_code.run(&mut scope)
+        && let Some(number) = result.number_value(&mut scope)
+    {
+        return Ok(number);
     }
 
     Ok(10.0) // Default to 10 seconds if not found
@@ -187,10 +669,10 @@ pub fn extract_timeout(js_runtime: Arc<Mutex<deno_core::JsRuntime>>) -> Result<f
 
     let compiled_code = deno_core::v8::Script::compile(&mut scope, timeout_script, None).unwrap();
 
-    if let Some(result) = compiled_code.run(&mut scope) {
-        if let Some(number) = result.number_value(&mut scope) {
-            return Ok(number);
-        }
+    if let Some(result) = compiled_code.run(&mut scope)
+        && let Some(number) = result.number_value(&mut scope)
+    {
+        return Ok(number);
     }
 
     Ok(30.0) // Default to 30 seconds if not found
@@ -205,71 +687,139 @@ pub fn extract_vus(js_runtime: Arc<Mutex<deno_core::JsRuntime>>) -> Result<usize
 
     let compiled_code = deno_core::v8::Script::compile(&mut scope, vus_script, None).unwrap();
 
-    if let Some(result) = compiled_code.run(&mut scope) {
-        if let Some(number) = result.number_value(&mut scope) {
-            return Ok(number as usize);
-        }
+    if let Some(result) = compiled_code.run(&mut scope)
+        && let Some(number) = result.number_value(&mut scope)
+    {
+        return Ok(number as usize);
     }
 
     Ok(1) // Default to 1 VU if not found
 }
 
-pub fn create_fresh_runtime() -> Result<Arc<Mutex<deno_core::JsRuntime>>> {
-    let js_runtime = Arc::new(Mutex::new(deno_core::JsRuntime::new(
-        deno_core::RuntimeOptions {
-            module_loader: Some(Rc::new(TsModuleLoader)),
-            startup_snapshot: Some(RUNTIME_SNAPSHOT),
-            extensions: vec![v6::init_ops_and_esm()],
-            ..Default::default()
-        },
-    )));
-    Ok(js_runtime)
+fn create_module_loader(loader_options: &LoaderOptions) -> Result<Rc<dyn deno_core::ModuleLoader>> {
+    Ok(match &loader_options.archive {
+        Some(archive) => Rc::new(ArchiveModuleLoader::new(archive.clone())),
+        None => Rc::new(TsModuleLoader::new(loader_options)?),
+    })
 }
 
-pub fn extract_iteration_function(js_runtime: Arc<Mutex<deno_core::JsRuntime>>) -> Result<String> {
-    let mut runtime = js_runtime.lock().unwrap();
-    let mut scope = runtime.handle_scope();
+fn create_js_runtime(module_loader: Rc<dyn deno_core::ModuleLoader>, metrics: SharedMetrics) -> deno_core::JsRuntime {
+    let mut js_runtime = deno_core::JsRuntime::new(deno_core::RuntimeOptions {
+        module_loader: Some(module_loader),
+        startup_snapshot: Some(RUNTIME_SNAPSHOT),
+        extensions: vec![v6::init_ops_and_esm()],
+        custom_module_evaluation_cb: Some(Box::new(evaluate_data_module)),
+        ..Default::default()
+    });
 
-    let iteration_script =
-        deno_core::v8::String::new(&mut scope, "globalThis.currentConfig.iteration.toString()")
-            .unwrap();
+    js_runtime.op_state().borrow_mut().put(metrics);
+    js_runtime
+}
 
-    let compiled_code = deno_core::v8::Script::compile(&mut scope, iteration_script, None).unwrap();
+// Evaluate the entry file as an ES module (imports and top-level await included)
+async fn evaluate_main_module(
+    js_runtime: &mut deno_core::JsRuntime,
+    main_module: &deno_core::ModuleSpecifier,
+) -> Result<()> {
+    let module_id = js_runtime.load_main_es_module(main_module).await?;
+    let evaluation = js_runtime.mod_evaluate(module_id);
+    js_runtime.run_event_loop(Default::default()).await?;
+    evaluation.await?;
 
-    if let Some(result) = compiled_code.run(&mut scope) {
-        if let Some(string_val) = result.to_string(&mut scope) {
-            return Ok(string_val.to_rust_string_lossy(&mut scope));
-        }
+    resolve_module_config(js_runtime, module_id)
+}
+
+/// Evaluate the entry module once; the config is read from this runtime and the
+/// test then runs in it, so top-level code isn't run a second time
+pub async fn create_fresh_runtime(
+    main_module: &deno_core::ModuleSpecifier,
+    loader_options: &LoaderOptions,
+) -> Result<Arc<Mutex<deno_core::JsRuntime>>> {
+    let mut js_runtime = create_js_runtime(create_module_loader(loader_options)?, MetricsRegistry::shared());
+    if let Some(archive) = &loader_options.archive {
+        js_runtime.op_state().borrow_mut().put(archive.clone());
     }
+    evaluate_main_module(&mut js_runtime, main_module).await?;
+
+    Ok(Arc::new(Mutex::new(js_runtime)))
+}
+
+/// Like `create_fresh_runtime`, but records every module it resolves and loads for `v6 bundle`,
+/// then the parts of the module graph evaluation didn't reach
+pub async fn create_recording_runtime(
+    main_module: &deno_core::ModuleSpecifier,
+    loader_options: &LoaderOptions,
+) -> Result<(Arc<Mutex<deno_core::JsRuntime>>, Rc<RefCell<ModuleGraphRecording>>)> {
+    let recording = Rc::new(RefCell::new(ModuleGraphRecording::default()));
+    let module_loader = Rc::new(RecordingModuleLoader::new(create_module_loader(loader_options)?, recording.clone()));
 
-    Ok("function() {}".to_string()) // Default empty function if not found
+    let mut js_runtime = create_js_runtime(module_loader.clone(), MetricsRegistry::shared());
+    evaluate_main_module(&mut js_runtime, main_module).await?;
+    module_loader.record_full_graph(main_module).await?;
+
+    Ok((Arc::new(Mutex::new(js_runtime)), recording))
+}
+
+/// Load the whole module graph of `main_module` without evaluating it, which
+/// downloads every remote import into the module cache and pins it in `v6.lock`.
+/// Returns the number of pinned remote modules.
+pub async fn vendor_modules(
+    main_module: &deno_core::ModuleSpecifier,
+    loader_options: &LoaderOptions,
+) -> Result<usize> {
+    let mut js_runtime = create_js_runtime(create_module_loader(loader_options)?, MetricsRegistry::shared());
+    js_runtime.load_main_es_module(main_module).await?;
+
+    Ok(RemoteModuleCache::new(&loader_options.root_dir, false)?.locked_count())
 }
 
+// Fill globalThis.currentConfig from the module exports if defineConfig wasn't called as a global
+fn resolve_module_config(
+    js_runtime: &mut deno_core::JsRuntime,
+    module_id: deno_core::ModuleId,
+) -> Result<()> {
+    let namespace = js_runtime.get_module_namespace(module_id)?;
+    let mut scope = js_runtime.handle_scope();
+
+    let context = scope.get_current_context();
+    let global = context.global(&mut scope);
+    let resolver_key = deno_core::v8::String::new(&mut scope, "__v6ResolveModuleConfig").unwrap();
+    let resolver = global
+        .get(&mut scope, resolver_key.into())
+        .and_then(|value| deno_core::v8::Local::<deno_core::v8::Function>::try_from(value).ok())
+        .ok_or_else(|| anyhow::anyhow!("Runtime is missing the module config resolver"))?;
+
+    let namespace = deno_core::v8::Local::new(&mut scope, namespace);
+    let mut try_catch = deno_core::v8::TryCatch::new(&mut scope);
+    resolver.call(&mut try_catch, global.into(), &[namespace.into()]);
+
+    if let Some(exception) = try_catch.exception() {
+        let message = exception.to_rust_string_lossy(&mut try_catch);
+        return Err(anyhow::anyhow!("Failed to resolve config: {}", message));
+    }
+
+    Ok(())
+}
+
+// Apply the (possibly CLI-overridden) settings on top of the config the module defined
 pub fn setup_runtime_config(
     js_runtime: Arc<Mutex<deno_core::JsRuntime>>,
     iterations: f64,
     duration: f64,
     timeout: f64,
     vus: usize,
-    iteration_fn: &str,
 ) -> Result<()> {
     let mut runtime = js_runtime.lock().unwrap();
     let mut scope = runtime.handle_scope();
 
     let setup_script = format!(
         r#"
-        globalThis.currentConfig = {{
+        Object.assign(globalThis.currentConfig, {{
             iterations: {},
             duration: {},
             timeout: {},
-            vus: {},
-            iteration: {}
-        }};
-
-        // Add global sleep function
-        globalThis.sleep = function(ms) {{
-            return Deno.core.opAsync("op_set_timeout", ms);
-        }};
+            vus: {}
+        }});
         "#,
         if iterations.is_infinite() {
             "Infinity".to_string()
@@ -278,8 +828,7 @@ pub fn setup_runtime_config(
         },
         duration,
         timeout,
-        vus,
-        iteration_fn
+        vus
     );
 
     let v8_string = deno_core::v8::String::new(&mut scope, &setup_script).unwrap();
@@ -293,16 +842,19 @@ pub async fn run_iteration(
     i: usize,
     vu_id: usize,
     js_runtime: Arc<Mutex<deno_core::JsRuntime>>,
-    compiled_script: Arc<deno_core::v8::Global<deno_core::v8::Script>>,
+    iteration_fn: Arc<deno_core::v8::Global<deno_core::v8::Function>>,
     iteration_timeout: Duration,
 ) {
     let iteration_future = async {
-        // Execute the script once
+        // Start the iteration as VU `vu_id + 1` (VU ids are 1-based in scripts)
         {
             let mut runtime = js_runtime.lock().unwrap();
             let mut scope = runtime.handle_scope();
-            let local_script = deno_core::v8::Local::new(&mut scope, &*compiled_script);
-            local_script.run(&mut scope);
+            let local_fn = deno_core::v8::Local::new(&mut scope, &*iteration_fn);
+            let receiver = deno_core::v8::undefined(&mut scope).into();
+            let vu = deno_core::v8::Number::new(&mut scope, (vu_id + 1) as f64).into();
+            let iteration = deno_core::v8::Number::new(&mut scope, i as f64).into();
+            local_fn.call(&mut scope, receiver, &[vu, iteration]);
             drop(scope);
         }
 
@@ -312,7 +864,7 @@ pub async fn run_iteration(
                 let mut runtime = js_runtime.lock().unwrap();
 
                 // Minimal memory cleanup for maximum performance
-                if i % 100000 == 0 {
+                if i.is_multiple_of(100000) {
                     let mut scope = runtime.handle_scope();
                     scope.low_memory_notification();
                 }
@@ -352,46 +904,226 @@ pub async fn run_iteration(
     }
 }
 
-pub fn create_shared_runtime(
+// The registry the runtime's ops record into
+fn runtime_metrics(js_runtime: &Arc<Mutex<deno_core::JsRuntime>>) -> SharedMetrics {
+    js_runtime.lock().unwrap().op_state().borrow().borrow::<SharedMetrics>().clone()
+}
+
+pub fn prepare_shared_runtime(
+    runtime: &Arc<Mutex<deno_core::JsRuntime>>,
     iterations: f64,
     duration: f64,
     timeout: f64,
     vus: usize,
-    iteration_fn: &str,
-) -> Result<(
-    Arc<Mutex<deno_core::JsRuntime>>,
-    Arc<deno_core::v8::Global<deno_core::v8::Script>>,
-)> {
-    let runtime = create_fresh_runtime()?;
+) -> Result<Arc<deno_core::v8::Global<deno_core::v8::Function>>> {
+    // Custom metrics were declared while the module was evaluated and stay in the
+    // registry; values recorded during evaluation are not part of the test
+    runtime_metrics(runtime).borrow_mut().reset();
     setup_runtime_config(
         runtime.clone(),
         iterations,
         duration,
         timeout,
         vus,
-        iteration_fn,
     )?;
 
-    // Pre-compile the script for maximum performance
-    let compiled_script = {
+    // Look up the iteration runner once; it runs currentConfig.iteration in the VU's context
+    let iteration_fn = {
         let mut rt = runtime.lock().unwrap();
         let mut scope = rt.handle_scope();
-        let script_source = "globalThis.currentConfig.iteration();";
-        let v8_string = deno_core::v8::String::new(&mut scope, script_source).unwrap();
-        let script = deno_core::v8::Script::compile(&mut scope, v8_string, None).unwrap();
-        Arc::new(deno_core::v8::Global::new(&mut scope, script))
+        let context = scope.get_current_context();
+        let global = context.global(&mut scope);
+        let key = deno_core::v8::String::new(&mut scope, "__v6RunIteration").unwrap();
+        let function = global
+            .get(&mut scope, key.into())
+            .and_then(|value| deno_core::v8::Local::<deno_core::v8::Function>::try_from(value).ok())
+            .ok_or_else(|| anyhow::anyhow!("Runtime is missing the iteration runner"))?;
+        Arc::new(deno_core::v8::Global::new(&mut scope, function))
     };
 
-    Ok((runtime, compiled_script))
+    Ok(iteration_fn)
+}
+
+// How often `abortOnFail` thresholds are evaluated while the test runs
+const ABORT_EVAL_INTERVAL: Duration = Duration::from_secs(1);
+// Time iterations in flight get to finish once the test is stopped early
+const GRACEFUL_STOP: Duration = Duration::from_secs(30);
+
+// Evaluate `abortOnFail` thresholds until one fails, then signal the test to stop
+async fn watch_abort_thresholds(
+    metrics: SharedMetrics,
+    thresholds: Vec<Threshold>,
+    start_time: Instant,
+    stop: watch::Sender<bool>,
+) {
+    let mut interval = tokio::time::interval(ABORT_EVAL_INTERVAL);
+    loop {
+        interval.tick().await;
+        let registry = metrics.borrow();
+        if let Some(threshold) = find_aborting_threshold(&registry, &thresholds, start_time.elapsed()) {
+            println!(
+                "⛔ Threshold \"{}\" on \"{}\" failed, stopping the test",
+                threshold.source, threshold.key
+            );
+            let _ = stop.send(true);
+            return;
+        }
+    }
+}
+
+// Wait for iterations to complete. Once the test is stopped, iterations still
+// running get `GRACEFUL_STOP` to finish before they are cancelled.
+async fn wait_for_iterations(handles: Vec<JoinHandle<()>>, mut stop: watch::Receiver<bool>) {
+    let abort_handles: Vec<_> = handles.iter().map(JoinHandle::abort_handle).collect();
+    let all = futures::future::join_all(handles);
+    tokio::pin!(all);
+
+    tokio::select! {
+        _ = &mut all => return,
+        Ok(_) = stop.wait_for(|stopped| *stopped) => {}
+    }
+
+    if timeout(GRACEFUL_STOP, &mut all).await.is_err() {
+        let running: Vec<_> = abort_handles.iter().filter(|handle| !handle.is_finished()).collect();
+        println!("Cancelling {} iterations still running after {:?}", running.len(), GRACEFUL_STOP);
+        running.iter().for_each(|handle| handle.abort());
+        all.await;
+    }
+}
+
+// Called with the summary JSON; resolves to the JSON of the config's handleSummary outputs, or null
+async fn handle_summary(
+    js_runtime: &Arc<Mutex<deno_core::JsRuntime>>,
+    summary: &serde_json::Value,
+) -> Result<Option<BTreeMap<String, String>>> {
+    let call = {
+        let mut runtime = js_runtime.lock().unwrap();
+        let (function, data) = {
+            let mut scope = runtime.handle_scope();
+            let context = scope.get_current_context();
+            let global = context.global(&mut scope);
+            let key = deno_core::v8::String::new(&mut scope, "__v6HandleSummary").unwrap();
+            let function = global
+                .get(&mut scope, key.into())
+                .and_then(|value| deno_core::v8::Local::<deno_core::v8::Function>::try_from(value).ok())
+                .ok_or_else(|| anyhow::anyhow!("Runtime is missing the summary handler"))?;
+            let data = deno_core::v8::String::new(&mut scope, &summary.to_string())
+                .ok_or_else(|| anyhow::anyhow!("Summary is too large to pass to handleSummary"))?;
+            (
+                deno_core::v8::Global::new(&mut scope, function),
+                deno_core::v8::Global::new(&mut scope, deno_core::v8::Local::<deno_core::v8::Value>::from(data)),
+            )
+        };
+        runtime.call_with_args(&function, &[data])
+    };
+    let mut call = std::pin::pin!(call);
+
+    // Pump the event loop until handleSummary settles, locking the runtime for each poll only
+    let result = std::future::poll_fn(|cx| {
+        let event_loop = js_runtime
+            .lock()
+            .unwrap()
+            .poll_event_loop(cx, deno_core::PollEventLoopOptions::default());
+        if let std::task::Poll::Ready(result) = call.as_mut().poll(cx) {
+            return std::task::Poll::Ready(result.map_err(anyhow::Error::from));
+        }
+        match event_loop {
+            std::task::Poll::Ready(Err(e)) => std::task::Poll::Ready(Err(e.into())),
+            std::task::Poll::Ready(Ok(())) => {
+                std::task::Poll::Ready(Err(anyhow::anyhow!("the returned promise never settled")))
+            }
+            std::task::Poll::Pending => std::task::Poll::Pending,
+        }
+    })
+    .await
+    .map_err(|e| anyhow::anyhow!("handleSummary failed: {}", e))?;
+
+    let mut runtime = js_runtime.lock().unwrap();
+    let mut scope = runtime.handle_scope();
+    let outputs = deno_core::v8::Local::new(&mut scope, result);
+    if outputs.is_null_or_undefined() {
+        return Ok(None);
+    }
+    let json = outputs.to_rust_string_lossy(&mut scope);
+    serde_json::from_str(&json)
+        .map(Some)
+        .map_err(|e| anyhow::anyhow!("Invalid handleSummary outputs: {}", e))
+}
+
+// Print or write each output of handleSummary; `stdout` and `stderr` are the console
+fn write_summary_outputs(outputs: &BTreeMap<String, String>) -> Result<()> {
+    for (path, content) in outputs {
+        match path.as_str() {
+            "stdout" => print!("{}", content),
+            "stderr" => eprint!("{}", content),
+            _ => {
+                std::fs::write(path, content)
+                    .map_err(|e| anyhow::anyhow!("Failed to write summary '{}': {}", path, e))?;
+                println!("📝 Wrote summary: {}", path);
+            }
+        }
+    }
+    Ok(())
+}
+
+// How often batched samples are sent to the `--out` outputs
+const SAMPLE_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+// Length of the intervals of the timeline, see `MetricsRegistry::record_timeline`
+const TIMELINE_INTERVAL: Duration = Duration::from_secs(1);
+
+// Send samples to the outputs at least every `SAMPLE_FLUSH_INTERVAL`, on top of full batches
+async fn flush_samples_periodically(metrics: SharedMetrics) {
+    let mut interval = tokio::time::interval(SAMPLE_FLUSH_INTERVAL);
+    loop {
+        interval.tick().await;
+        metrics.borrow_mut().flush_samples();
+    }
 }
 
+// Snapshot the metrics every `TIMELINE_INTERVAL`
+async fn snapshot_timeline_periodically(metrics: SharedMetrics) {
+    let mut interval = tokio::time::interval(TIMELINE_INTERVAL);
+    // The first tick completes immediately
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        metrics.borrow_mut().snapshot_timeline();
+    }
+}
+
+/// What to evaluate and where to stream samples while the test runs
+pub struct RunOptions {
+    pub thresholds: Vec<Threshold>,
+    pub outputs: Vec<OutputConfig>,
+    /// Record the metrics over time into `TestRunResult::timeline`
+    pub record_timeline: bool,
+}
+
+/// Outcome of `run_load_test`
+pub struct TestRunResult {
+    pub thresholds_passed: bool,
+    /// Aggregated metrics, checks, thresholds and run metadata, as passed to `handleSummary`
+    pub summary: serde_json::Value,
+    /// Metric snapshots over time, when `RunOptions::record_timeline` is set
+    pub timeline: Option<serde_json::Value>,
+}
+
+/// Run the test in `runtime`, the one the config was read from (see `create_fresh_runtime`),
+/// print its summary and run the config's `handleSummary`, if any
 pub async fn run_load_test(
     iterations: f64,
     duration: f64,
     iteration_timeout_secs: f64,
     vus: usize,
-    iteration_fn: &str,
-) -> Result<()> {
+    main_module: &deno_core::ModuleSpecifier,
+    runtime: Arc<Mutex<deno_core::JsRuntime>>,
+    options: &RunOptions,
+) -> Result<TestRunResult> {
+    let thresholds = options.thresholds.as_slice();
+    let record_timeline = options.record_timeline;
+    let module_name = main_module.to_string();
+    let metrics = runtime_metrics(&runtime);
+    let test_metrics = metrics.clone();
     let iteration_timeout = Duration::from_secs_f64(iteration_timeout_secs);
 
     let is_infinite = iterations.is_infinite();
@@ -407,13 +1139,24 @@ pub async fn run_load_test(
         Duration::from_secs(60) // Use 60s timeout for finite iterations as fallback
     };
 
+    let started_at = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+    let run = RunInfo {
+        id: format!("{:x}-{:x}", started_at.as_millis(), std::process::id()),
+        main_module: module_name.clone(),
+    };
+
+    // Opened before the test starts so a bad output fails fast
+    let output_writer = OutputWriter::start(&options.outputs, &run)?;
+    let output_sender = output_writer.as_ref().map(OutputWriter::sender);
+
     // Use LocalSet for task-local execution
     let local = tokio::task::LocalSet::new();
+    // Kept after the test to run handleSummary, even when the run is cut short
+    let summary_runtime = runtime.clone();
 
     let task_future = local.run_until(async move {
-        // Prepare compiled script source once inside run_until block scope
-        let _script_source = "globalThis.currentConfig.iteration();".to_string();
-
         let mut handles = Vec::new();
         // Ultra-high concurrent tasks for maximum localhost throughput
         let max_concurrent_tasks = (vus * 1000).min(10000); // Extreme limits for localhost testing
@@ -426,15 +1169,39 @@ pub async fn run_load_test(
         let start_time = Instant::now();
         let mut task_counter = 0usize;
 
-        // Create single shared runtime and pre-compiled script
-        let (shared_runtime, shared_script) = match create_shared_runtime(iterations, duration, iteration_timeout_secs, vus, &iteration_fn) {
-            Ok((runtime, script)) => (runtime, script),
-            Err(e) => {
-                println!("Failed to create shared runtime: {}", e);
-                return;
-            }
+        // Apply the settings to the shared runtime and look up its iteration runner
+        let shared_runtime = runtime;
+        let shared_iteration = match prepare_shared_runtime(&shared_runtime, iterations, duration, iteration_timeout_secs, vus) {
+            Ok(iteration) => iteration,
+            Err(e) => return Err(anyhow::anyhow!("Failed to prepare shared runtime: {}", e)),
         };
 
+        // Custom metrics exist once the module is evaluated, so thresholds are checked here
+        track_thresholds(&mut test_metrics.borrow_mut(), thresholds)?;
+
+        // Stream every sample of the test itself to the outputs
+        let sample_flusher = output_sender.map(|sender| {
+            test_metrics.borrow_mut().stream_samples(sender);
+            tokio::task::spawn_local(flush_samples_periodically(test_metrics.clone()))
+        });
+        let timeline_snapshotter = record_timeline.then(|| {
+            test_metrics.borrow_mut().record_timeline();
+            tokio::task::spawn_local(snapshot_timeline_periodically(test_metrics.clone()))
+        });
+
+        // Set once an `abortOnFail` threshold fails; no new iterations start after that
+        let (stop_sender, stop) = watch::channel(false);
+        let abort_thresholds: Vec<Threshold> =
+            thresholds.iter().filter(|threshold| threshold.abort_on_fail).cloned().collect();
+        let abort_monitor = (!abort_thresholds.is_empty()).then(|| {
+            tokio::task::spawn_local(watch_abort_thresholds(
+                test_metrics.clone(),
+                abort_thresholds,
+                start_time,
+                stop_sender,
+            ))
+        });
+
         let mut active_handles = Vec::new();
 
         if is_infinite {
@@ -444,17 +1211,24 @@ pub async fn run_load_test(
                     println!("{} second timeout reached for infinite iterations", duration);
                     break;
                 }
+                if *stop.borrow() {
+                    break;
+                }
 
                 // Spawn tasks across all VUs using shared runtime
                 for vu_id in 0..vus {
                     let runtime_clone = shared_runtime.clone();
-                    let script_clone = shared_script.clone();
+                    let iteration_clone = shared_iteration.clone();
                     let timeout_clone = iteration_timeout;
                     let task_id = task_counter;
                     task_counter += 1;
+                    let stop = stop.clone();
 
                     let handle = tokio::task::spawn_local(async move {
-                        run_iteration(task_id, vu_id, runtime_clone, script_clone, timeout_clone).await;
+                        if *stop.borrow() {
+                            return;
+                        }
+                        run_iteration(task_id, vu_id, runtime_clone, iteration_clone, timeout_clone).await;
                     });
                     active_handles.push(handle);
                 }
@@ -463,11 +1237,11 @@ pub async fn run_load_test(
                 active_handles.retain(|handle| !handle.is_finished());
 
                 // Ultra-minimal memory cleanup for maximum throughput
-                if task_counter % 200000 == 0 {
-                    if let Ok(mut runtime) = shared_runtime.try_lock() {
-                        let mut scope = runtime.handle_scope();
-                        scope.low_memory_notification();
-                    }
+                if task_counter.is_multiple_of(200000)
+                    && let Ok(mut runtime) = shared_runtime.try_lock()
+                {
+                    let mut scope = runtime.handle_scope();
+                    scope.low_memory_notification();
                 }
 
                 // Use yield_now for better performance
@@ -493,10 +1267,15 @@ pub async fn run_load_test(
                     }
 
                     let runtime_clone = shared_runtime.clone();
-                    let script_clone = shared_script.clone();
+                    let iteration_clone = shared_iteration.clone();
                     let timeout_clone = iteration_timeout;
+                    let stop = stop.clone();
+                    // All iterations are queued up front, so each checks the stop signal when it gets to run
                     let handle = tokio::task::spawn_local(async move {
-                        run_iteration(global_task_id, vu_id, runtime_clone, script_clone, timeout_clone).await;
+                        if *stop.borrow() {
+                            return;
+                        }
+                        run_iteration(global_task_id, vu_id, runtime_clone, iteration_clone, timeout_clone).await;
                     });
                     handles.push(handle);
                 }
@@ -506,9 +1285,7 @@ pub async fn run_load_test(
         // Wait for all active tasks to complete before cleanup
         if is_infinite {
             println!("Waiting for {} active tasks to complete...", active_handles.len());
-            for handle in active_handles {
-                let _ = handle.await;
-            }
+            wait_for_iterations(active_handles, stop.clone()).await;
             let rate = task_counter as f64 / execution_duration.as_secs_f64();
             println!(
                 "Completed {} tasks across {} VUs (infinite iterations with {}s timeout) - Rate: {:.2} iterations/sec",
@@ -516,12 +1293,18 @@ pub async fn run_load_test(
             );
         } else {
             let completed_tasks = handles.len();
-            for task in handles {
-                let _ = task.await;
-            }
+            wait_for_iterations(handles, stop.clone()).await;
             println!("All {} tasks completed across {} VUs", completed_tasks, vus);
         }
 
+        for task in [abort_monitor, sample_flusher, timeline_snapshotter].into_iter().flatten() {
+            task.abort();
+        }
+        let aborted = *stop.borrow();
+        if aborted {
+            println!("⛔ Test aborted by a threshold");
+        }
+
         // Final cleanup for shared runtime
         if let Ok(mut runtime) = shared_runtime.try_lock() {
             let mut scope = runtime.handle_scope();
@@ -529,22 +1312,517 @@ pub async fn run_load_test(
         }
 
         // Clean up shared resources
-        drop(shared_script);
+        drop(shared_iteration);
         drop(shared_runtime);
+
+        Ok(aborted)
         });
 
+    let test_start = Instant::now();
+
     // Apply timeout for infinite iterations
-    if is_infinite {
+    let aborted = if is_infinite {
         match timeout(execution_duration, task_future).await {
-            Ok(_) => println!("Tasks completed within timeout"),
-            Err(_) => println!(
-                "{} second timeout reached for infinite iterations",
-                duration
-            ),
+            Ok(result) => {
+                let aborted = result?;
+                println!("Tasks completed within timeout");
+                aborted
+            }
+            Err(_) => {
+                println!("{} second timeout reached for infinite iterations", duration);
+                false
+            }
         }
     } else {
-        task_future.await;
+        task_future.await?
+    };
+
+    let elapsed = test_start.elapsed();
+    metrics.borrow_mut().stop_streaming();
+    metrics.borrow_mut().snapshot_timeline();
+    if let Some(writer) = output_writer {
+        writer.finish()?;
     }
 
-    Ok(())
+    let summary = {
+        let metrics = metrics.borrow();
+        metrics.print_summary(elapsed);
+
+        let results = evaluate_thresholds(&metrics, thresholds, elapsed.as_secs_f64());
+        let thresholds_passed = print_threshold_results(&results);
+
+        let mut summary = metrics.summary_json(elapsed);
+        summary["thresholds"] = thresholds_json(&results);
+        summary["metadata"] = serde_json::json!({
+            "v6Version": env!("CARGO_PKG_VERSION"),
+            "runId": run.id,
+            "mainModule": module_name,
+            "startTime": started_at.as_millis() as u64,
+            "testRunDurationMs": elapsed.as_secs_f64() * 1000.0,
+            "iterations": if is_infinite { None } else { Some(iterations) },
+            "duration": duration,
+            "vus": vus,
+            "aborted": aborted,
+            "thresholdsPassed": thresholds_passed,
+        });
+        TestRunResult {
+            thresholds_passed,
+            summary,
+            timeline: metrics.timeline_json(),
+        }
+    };
+
+    // Iterations cut short still hold the runtime until the LocalSet is gone
+    drop(local);
+    if let Some(outputs) = handle_summary(&summary_runtime, &summary.summary).await? {
+        write_summary_outputs(&outputs)?;
+    }
+
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::Aggregation;
+    use deno_core::{
+        ModuleLoader, ModuleSourceCode, ModuleType, RequestedModuleType, ResolutionKind,
+    };
+
+    fn specifier(url: &str) -> deno_core::ModuleSpecifier {
+        deno_core::resolve_url(url).unwrap()
+    }
+
+    fn source_text(code: &ModuleSourceCode) -> &str {
+        std::str::from_utf8(code.as_bytes()).unwrap()
+    }
+
+    fn load(
+        url: &str,
+        media_type: MediaType,
+        code: &[u8],
+        requested_module_type: RequestedModuleType,
+    ) -> (
+        Result<deno_core::ModuleSource, deno_core::error::ModuleLoaderError>,
+        SourceMapStore,
+    ) {
+        let source_maps = SourceMapStore::default();
+        let source = create_module_source(
+            &specifier(url),
+            media_type,
+            code.to_vec(),
+            requested_module_type,
+            &source_maps,
+        );
+        (source, source_maps)
+    }
+
+    #[test]
+    fn transpiles_typescript_flavors() {
+        let cases = [
+            (
+                "file:///test.ts",
+                MediaType::TypeScript,
+                "const n: number = 1;\nexport default n;\n",
+                "const n = 1;",
+            ),
+            (
+                "file:///test.mts",
+                MediaType::Mts,
+                "export type Id = string;\nexport const id: Id = \"a\";\n",
+                "export const id = \"a\";",
+            ),
+            (
+                "file:///test.tsx",
+                MediaType::Tsx,
+                "export const el = <b>{1 as number}</b>;\n",
+                "React.createElement(\"b\", null, 1)",
+            ),
+        ];
+        for (url, media_type, code, expected) in cases {
+            let transpiled = transpile(&specifier(url), code.to_string(), media_type).unwrap();
+            assert!(transpiled.contains(expected), "{}:\n{}", url, transpiled);
+            assert!(
+                !transpiled.contains(": number") && !transpiled.contains("type Id"),
+                "{}:\n{}",
+                url,
+                transpiled
+            );
+        }
+
+        let error = transpile(
+            &specifier("file:///broken.ts"),
+            "const = ;".to_string(),
+            MediaType::TypeScript,
+        );
+        assert!(error.is_err());
+    }
+
+    #[test]
+    fn extracts_the_inline_source_map() {
+        let code = "const n: number = 1;\nexport default n;\n";
+        let transpiled = transpile(
+            &specifier("file:///test.ts"),
+            code.to_string(),
+            MediaType::TypeScript,
+        )
+        .unwrap();
+
+        let source_map: serde_json::Value =
+            serde_json::from_slice(&extract_inline_source_map(&transpiled).unwrap()).unwrap();
+        assert_eq!(source_map["sources"][0], "file:///test.ts");
+        assert_eq!(source_map["sourcesContent"][0], code);
+        assert!(
+            source_map["mappings"]
+                .as_str()
+                .is_some_and(|mappings| !mappings.is_empty())
+        );
+
+        assert_eq!(extract_inline_source_map("export default 1;\n"), None);
+        assert_eq!(
+            extract_inline_source_map("//# sourceMappingURL=data:application/json;base64,e30=\n"),
+            Some(b"{}".to_vec())
+        );
+        assert_eq!(
+            extract_inline_source_map("//# sourceMappingURL=data:application/json;base64,!!!"),
+            None
+        );
+    }
+
+    #[test]
+    fn typescript_modules_are_transpiled_with_a_source_map() {
+        let (source, source_maps) = load(
+            "file:///test.ts",
+            MediaType::TypeScript,
+            b"export const n: number = 1;",
+            RequestedModuleType::None,
+        );
+        let source = source.unwrap();
+        assert_eq!(source.module_type, ModuleType::JavaScript);
+        assert!(source_text(&source.code).starts_with("export const n = 1;"));
+        assert!(source_maps.borrow().contains_key("file:///test.ts"));
+
+        // JavaScript is passed through as is
+        let (source, source_maps) = load(
+            "file:///test.js",
+            MediaType::JavaScript,
+            b"export const n = 1;",
+            RequestedModuleType::None,
+        );
+        assert_eq!(source_text(&source.unwrap().code), "export const n = 1;");
+        assert!(source_maps.borrow().is_empty());
+    }
+
+    #[test]
+    fn import_attributes_select_data_modules() {
+        let (source, _) = load(
+            "file:///data.json",
+            MediaType::Json,
+            br#"{"users": 3}"#,
+            RequestedModuleType::Json,
+        );
+        let source = source.unwrap();
+        assert_eq!(source.module_type, ModuleType::Json);
+        assert_eq!(source_text(&source.code), r#"{"users": 3}"#);
+
+        // Any file can be imported as text or bytes, whatever its extension
+        let (source, _) = load(
+            "file:///query.ts",
+            MediaType::TypeScript,
+            b"let x: number",
+            RequestedModuleType::Other(TEXT_MODULE_TYPE.into()),
+        );
+        let source = source.unwrap();
+        assert_eq!(
+            source.module_type,
+            ModuleType::Other(TEXT_MODULE_TYPE.into())
+        );
+        assert!(matches!(&source.code, ModuleSourceCode::String(_)));
+        assert_eq!(source_text(&source.code), "let x: number");
+
+        let (source, _) = load(
+            "file:///image.png",
+            MediaType::Unknown,
+            &[0x89, 0x50, 0xff, 0x00],
+            RequestedModuleType::Other(BYTES_MODULE_TYPE.into()),
+        );
+        let source = source.unwrap();
+        assert_eq!(
+            source.module_type,
+            ModuleType::Other(BYTES_MODULE_TYPE.into())
+        );
+        assert!(matches!(&source.code, ModuleSourceCode::Bytes(_)));
+        assert_eq!(source.code.as_bytes(), &[0x89, 0x50, 0xff, 0x00]);
+    }
+
+    #[test]
+    fn rejects_mismatched_import_attributes() {
+        let (source, _) = load(
+            "file:///data.json",
+            MediaType::Json,
+            b"{}",
+            RequestedModuleType::None,
+        );
+        assert!(matches!(
+            source,
+            Err(deno_core::error::ModuleLoaderError::JsonMissingAttribute)
+        ));
+
+        let (source, _) = load(
+            "file:///data.bin",
+            MediaType::Unknown,
+            &[0xff, 0xfe],
+            RequestedModuleType::Other(TEXT_MODULE_TYPE.into()),
+        );
+        assert!(source.unwrap_err().to_string().contains("not valid UTF-8"));
+
+        let (source, _) = load(
+            "file:///data.bin",
+            MediaType::Unknown,
+            b"",
+            RequestedModuleType::Other("wasm".into()),
+        );
+        assert!(
+            source
+                .unwrap_err()
+                .to_string()
+                .contains("Unsupported import type \"wasm\"")
+        );
+
+        let (source, _) = load(
+            "file:///style.css",
+            MediaType::Css,
+            b"",
+            RequestedModuleType::None,
+        );
+        assert!(
+            source
+                .unwrap_err()
+                .to_string()
+                .contains("Unsupported module type")
+        );
+    }
+
+    #[test]
+    fn resolves_through_the_import_map_then_builtins() {
+        let project = tempfile::tempdir().unwrap();
+        let main = deno_core::url::Url::from_file_path(project.path().join("main.ts")).unwrap();
+        let import_map = ImportMap::parse(
+            r#"{ "imports": { "v6/http": "./mocks/http.ts", "lodash-es": "https://esm.sh/lodash-es" } }"#,
+            &main,
+        )
+        .unwrap();
+        let loader = TsModuleLoader::new(&LoaderOptions {
+            import_map: Some(Arc::new(import_map)),
+            root_dir: project.path().to_path_buf(),
+            ..Default::default()
+        })
+        .unwrap();
+        let resolve = |specifier: &str| {
+            loader
+                .resolve(specifier, main.as_str(), ResolutionKind::Import)
+                .map(|url| url.to_string())
+                .map_err(|e| e.to_string())
+        };
+
+        // The import map comes first, even for built-in names
+        assert_eq!(
+            resolve("v6/http"),
+            Ok(main.join("./mocks/http.ts").unwrap().to_string())
+        );
+        assert_eq!(
+            resolve("lodash-es"),
+            Ok("https://esm.sh/lodash-es".to_string())
+        );
+        // Then built-ins, and paths and URLs relative to the referrer
+        assert_eq!(resolve("v6"), Ok("ext:v6/modules/index.js".to_string()));
+        assert_eq!(
+            resolve("v6/metrics"),
+            Ok("ext:v6/modules/metrics.js".to_string())
+        );
+        assert!(
+            resolve("v6/missing")
+                .unwrap_err()
+                .contains("Unknown built-in module")
+        );
+        assert_eq!(
+            resolve("./helpers.ts"),
+            Ok(main.join("./helpers.ts").unwrap().to_string())
+        );
+        assert_eq!(
+            resolve("https://example.com/a.js"),
+            Ok("https://example.com/a.js".to_string())
+        );
+    }
+
+    #[test]
+    fn commonjs_files_in_node_modules_load_through_the_wrapper() {
+        let project = tempfile::tempdir().unwrap();
+        let package_dir = project.path().join("node_modules/counter");
+        std::fs::create_dir_all(&package_dir).unwrap();
+        std::fs::write(package_dir.join("index.js"), "exports.count = 1;").unwrap();
+        let url = deno_core::url::Url::from_file_path(package_dir.join("index.js")).unwrap();
+
+        let source =
+            load_local_module(&url, RequestedModuleType::None, &SourceMapStore::default()).unwrap();
+        let code = source_text(&source.code);
+        assert!(code.contains("createRequire"), "{}", code);
+        assert!(
+            code.contains("export { __v6_export_0 as \"count\" };"),
+            "{}",
+            code
+        );
+    }
+
+    #[test]
+    fn resolves_bare_specifiers_through_node_modules_after_builtins() {
+        let project = tempfile::tempdir().unwrap();
+        let package_dir = project.path().join("node_modules/lodash");
+        std::fs::create_dir_all(&package_dir).unwrap();
+        std::fs::write(package_dir.join("index.js"), "").unwrap();
+        // A package shadowing a built-in module name
+        std::fs::create_dir_all(project.path().join("node_modules/v6")).unwrap();
+        std::fs::write(project.path().join("node_modules/v6/index.js"), "").unwrap();
+
+        let main = deno_core::url::Url::from_file_path(project.path().join("main.ts")).unwrap();
+        let loader = TsModuleLoader::new(&LoaderOptions {
+            root_dir: project.path().to_path_buf(),
+            ..Default::default()
+        })
+        .unwrap();
+        let resolve = |specifier: &str| {
+            loader
+                .resolve(specifier, main.as_str(), ResolutionKind::Import)
+                .map(|url| url.to_string())
+                .map_err(|e| e.to_string())
+        };
+
+        assert_eq!(resolve("v6"), Ok("ext:v6/modules/index.js".to_string()));
+        assert_eq!(
+            resolve("lodash"),
+            Ok(
+                deno_core::url::Url::from_file_path(package_dir.join("index.js"))
+                    .unwrap()
+                    .to_string()
+            )
+        );
+        assert!(resolve("left-pad").is_err());
+    }
+
+    // Evaluate `script` as the main module and run one iteration of it as VU 1. Values
+    // carrying all the tags of a `tracked` filter are also aggregated apart.
+    async fn run_single_iteration(script: &str, tracked: &[(&str, Tags)]) -> SharedMetrics {
+        let project = tempfile::tempdir().unwrap();
+        std::fs::write(project.path().join("main.js"), script).unwrap();
+        let main = deno_core::url::Url::from_file_path(project.path().join("main.js")).unwrap();
+        let runtime = create_fresh_runtime(
+            &main,
+            &LoaderOptions {
+                root_dir: project.path().to_path_buf(),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        let iteration = prepare_shared_runtime(&runtime, 1.0, 0.0, 10.0, 1).unwrap();
+        let metrics = runtime_metrics(&runtime);
+        for (name, filter) in tracked {
+            metrics.borrow_mut().track_submetric(name, filter).unwrap();
+        }
+        run_iteration(0, 0, runtime.clone(), iteration, Duration::from_secs(10)).await;
+        metrics
+    }
+
+    #[tokio::test]
+    async fn rate_counts_truthy_values_of_any_type_as_passes() {
+        let metrics = run_single_iteration(
+            r#"
+            import { Rate } from "v6/metrics";
+            const flags = new Rate("flags");
+            defineConfig({
+              iteration() {
+                for (const value of ["yes", {}, true, 0, "", null, undefined, NaN]) {
+                  flags.add(value);
+                }
+              },
+            });
+            "#,
+            &[],
+        )
+        .await;
+
+        let summary = metrics.borrow().summary_json(Duration::from_secs(1));
+        let values = &summary["metrics"]["flags"]["values"];
+        assert_eq!(
+            (&values["passes"], &values["fails"]),
+            (&3.into(), &5.into())
+        );
+    }
+
+    #[tokio::test]
+    async fn groups_tag_what_is_recorded_inside_them() {
+        let vu_tags: Tags = [("vu", "1"), ("scenario", "default")]
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        let metrics = run_single_iteration(
+            r#"
+            import { Counter } from "v6/metrics";
+            const hits = new Counter("hits");
+            defineConfig({
+              async iteration() {
+                hits.add(1);
+                group("login", () => {
+                  hits.add(1);
+                  check(true, { "signed in": (ok) => ok });
+                  group("mfa", () => check(true, { "code accepted": (ok) => ok }));
+                });
+                await group("checkout", async () => {
+                  await setTimeout(20);
+                  hits.add(1, { step: "pay" });
+                  check(true, { paid: (ok) => ok });
+                });
+                check(true, { done: (ok) => ok });
+              },
+            });
+            "#,
+            &[("hits", vu_tags.clone())],
+        )
+        .await;
+
+        let metrics = metrics.borrow();
+        // Custom metrics get the VU and group tags like built-in ones
+        let count = |filter: &Tags| metrics.aggregate("hits", filter, Aggregation::Count, 1.0);
+        assert_eq!(count(&Tags::new()), Some(3.0));
+        assert_eq!(count(&vu_tags), Some(3.0));
+        let summary = metrics.summary_json(Duration::from_secs(1));
+        let hits = &summary["metrics"]["hits"]["submetrics"];
+        assert_eq!(hits["{group:::login}"]["count"], 1.0);
+        assert_eq!(hits["{group:::checkout}"]["count"], 1.0);
+        assert_eq!(hits["{step:pay}"]["count"], 1.0);
+
+        // Nested groups, and an async group keeping its tag after an await
+        let root = &summary["rootGroup"];
+        let names = |group: &serde_json::Value, key: &str| -> Vec<String> {
+            group[key]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|item| item["name"].as_str().unwrap().to_string())
+                .collect()
+        };
+        assert_eq!(names(root, "checks"), ["done"]);
+        assert_eq!(names(root, "groups"), ["checkout", "login"]);
+        let (checkout, login) = (&root["groups"][0], &root["groups"][1]);
+        assert_eq!(names(checkout, "checks"), ["paid"]);
+        assert_eq!(names(login, "checks"), ["signed in"]);
+        assert_eq!(login["groups"][0]["path"], "::login::mfa");
+        assert_eq!(names(&login["groups"][0], "checks"), ["code accepted"]);
+
+        // group_duration lasts until the async group's promise settles
+        assert!(checkout["duration"]["min"].as_f64().unwrap() >= 20.0);
+        assert!(login["duration"].is_object());
+        assert!(login["groups"][0]["duration"].is_object());
+    }
 }
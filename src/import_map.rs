@@ -0,0 +1,331 @@
+use anyhow::Result;
+use serde::Deserialize;
+use std::{collections::HashMap, path::Path};
+
+pub const CONFIG_FILE_NAME: &str = "v6.json";
+
+#[derive(Deserialize, Default)]
+struct ImportMapJson {
+    #[serde(default)]
+    imports: HashMap<String, String>,
+    #[serde(default)]
+    scopes: HashMap<String, HashMap<String, String>>,
+}
+
+/// `v6.json`: either points at an import map file or inlines `imports`/`scopes`
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ConfigJson {
+    import_map: Option<String>,
+    #[serde(flatten)]
+    inline: ImportMapJson,
+}
+
+// Specifier map with keys sorted longest first, so the most specific prefix wins
+type SpecifierMap = Vec<(String, deno_core::ModuleSpecifier)>;
+
+/// A standard (WHATWG) import map, applied before regular module resolution
+#[derive(Debug)]
+pub struct ImportMap {
+    imports: SpecifierMap,
+    scopes: Vec<(String, SpecifierMap)>,
+}
+
+impl ImportMap {
+    /// Parse an import map; relative keys and addresses are resolved against `base_url`
+    pub fn parse(json: &str, base_url: &deno_core::ModuleSpecifier) -> Result<Self> {
+        let raw: ImportMapJson = serde_json::from_str(json)
+            .map_err(|e| anyhow::anyhow!("Invalid import map '{}': {}", base_url, e))?;
+        Self::from_json(raw, base_url)
+    }
+
+    fn from_json(raw: ImportMapJson, base_url: &deno_core::ModuleSpecifier) -> Result<Self> {
+        let imports = parse_specifier_map(raw.imports, base_url)?;
+
+        let mut scopes = raw
+            .scopes
+            .into_iter()
+            .map(|(prefix, map)| {
+                let prefix = base_url
+                    .join(&prefix)
+                    .map_err(|e| anyhow::anyhow!("Invalid import map scope \"{}\": {}", prefix, e))?;
+                Ok((prefix.to_string(), parse_specifier_map(map, base_url)?))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        scopes.sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+
+        Ok(Self { imports, scopes })
+    }
+
+    /// Map `specifier` imported from `referrer`, or `None` when no entry matches
+    pub fn resolve(&self, specifier: &str, referrer: &str) -> Option<deno_core::ModuleSpecifier> {
+        // URL-like specifiers are matched in their resolved form
+        let normalized = if is_url_like(specifier) {
+            deno_core::resolve_import(specifier, referrer).ok()?.to_string()
+        } else {
+            specifier.to_string()
+        };
+
+        self.scopes
+            .iter()
+            .filter(|(prefix, _)| referrer.starts_with(prefix.as_str()))
+            .find_map(|(_, map)| resolve_in_map(map, &normalized))
+            .or_else(|| resolve_in_map(&self.imports, &normalized))
+    }
+}
+
+/// Load the import map given with `--import-map`, falling back to `v6.json` in `root_dir`,
+/// the directory of the test file
+pub fn load_import_map(path: Option<&str>, root_dir: &Path) -> Result<Option<ImportMap>> {
+    if let Some(path) = path {
+        let (json, base_url) = read_json_file(Path::new(path))?;
+        return ImportMap::parse(&json, &base_url).map(Some);
+    }
+
+    let config_path = root_dir.join(CONFIG_FILE_NAME);
+    if !config_path.exists() {
+        return Ok(None);
+    }
+
+    let (json, config_url) = read_json_file(&config_path)?;
+    let config: ConfigJson = serde_json::from_str(&json)
+        .map_err(|e| anyhow::anyhow!("Invalid config '{}': {}", config_path.display(), e))?;
+
+    match config.import_map {
+        Some(import_map_path) => {
+            let import_map_url = config_url
+                .join(&import_map_path)
+                .map_err(|e| anyhow::anyhow!("Invalid importMap \"{}\": {}", import_map_path, e))?;
+            let import_map_path = import_map_url
+                .to_file_path()
+                .map_err(|_| anyhow::anyhow!("importMap must be a local file, got \"{}\"", import_map_url))?;
+            let (json, base_url) = read_json_file(&import_map_path)?;
+            ImportMap::parse(&json, &base_url).map(Some)
+        }
+        None if config.inline.imports.is_empty() && config.inline.scopes.is_empty() => Ok(None),
+        None => ImportMap::from_json(config.inline, &config_url).map(Some),
+    }
+}
+
+fn read_json_file(path: &Path) -> Result<(String, deno_core::ModuleSpecifier)> {
+    let json = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read '{}': {}", path.display(), e))?;
+    let url = deno_core::resolve_path(&path.to_string_lossy(), &std::env::current_dir()?)
+        .map_err(|e| anyhow::anyhow!("Failed to resolve '{}': {}", path.display(), e))?;
+    Ok((json, url))
+}
+
+fn parse_specifier_map(
+    map: HashMap<String, String>,
+    base_url: &deno_core::ModuleSpecifier,
+) -> Result<SpecifierMap> {
+    let mut entries = map
+        .into_iter()
+        .map(|(key, address)| {
+            let key = if is_url_like(&key) {
+                deno_core::resolve_import(&key, base_url.as_str())
+                    .map_err(|e| anyhow::anyhow!("Invalid import map key \"{}\": {}", key, e))?
+                    .to_string()
+            } else {
+                key
+            };
+            if key.ends_with('/') != address.ends_with('/') {
+                return Err(anyhow::anyhow!(
+                    "Import map entry \"{}\" must map to an address ending with \"/\"",
+                    key
+                ));
+            }
+            let address = deno_core::resolve_import(&address, base_url.as_str())
+                .map_err(|e| anyhow::anyhow!("Invalid import map address for \"{}\": {}", key, e))?;
+            Ok((key, address))
+        })
+        .collect::<Result<SpecifierMap>>()?;
+    entries.sort_by_key(|(key, _)| std::cmp::Reverse(key.len()));
+    Ok(entries)
+}
+
+fn resolve_in_map(map: &SpecifierMap, specifier: &str) -> Option<deno_core::ModuleSpecifier> {
+    map.iter().find_map(|(key, address)| {
+        if key == specifier {
+            Some(address.clone())
+        } else if key.ends_with('/') && specifier.starts_with(key.as_str()) {
+            address.join(&specifier[key.len()..]).ok()
+        } else {
+            None
+        }
+    })
+}
+
+fn is_url_like(specifier: &str) -> bool {
+    specifier.starts_with('/')
+        || specifier.starts_with("./")
+        || specifier.starts_with("../")
+        || deno_core::resolve_url(specifier).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAP_URL: &str = "file:///project/import_map.json";
+    const MAIN: &str = "file:///project/main.ts";
+
+    fn import_map(json: &str) -> ImportMap {
+        ImportMap::parse(json, &deno_core::resolve_url(MAP_URL).unwrap()).unwrap()
+    }
+
+    fn resolve(import_map: &ImportMap, specifier: &str, referrer: &str) -> Option<String> {
+        import_map
+            .resolve(specifier, referrer)
+            .map(|url| url.to_string())
+    }
+
+    #[test]
+    fn exact_matches() {
+        let map = import_map(r#"{ "imports": { "lodash": "https://esm.sh/lodash@4.17.21" } }"#);
+        assert_eq!(
+            resolve(&map, "lodash", MAIN).as_deref(),
+            Some("https://esm.sh/lodash@4.17.21")
+        );
+        // Without a trailing slash, a key only matches the specifier itself
+        assert_eq!(resolve(&map, "lodash/fp", MAIN), None);
+        assert_eq!(resolve(&map, "underscore", MAIN), None);
+    }
+
+    #[test]
+    fn trailing_slash_prefix_matches() {
+        let map = import_map(
+            r#"{ "imports": {
+                "std/": "https://deno.land/std@0.224.0/",
+                "std/assert/": "https://deno.land/std@0.200.0/assert/"
+            } }"#,
+        );
+        assert_eq!(
+            resolve(&map, "std/path/mod.ts", MAIN).as_deref(),
+            Some("https://deno.land/std@0.224.0/path/mod.ts")
+        );
+        // The longest matching prefix wins
+        assert_eq!(
+            resolve(&map, "std/assert/mod.ts", MAIN).as_deref(),
+            Some("https://deno.land/std@0.200.0/assert/mod.ts")
+        );
+        assert_eq!(resolve(&map, "std", MAIN), None);
+    }
+
+    #[test]
+    fn prefix_keys_need_prefix_addresses() {
+        let error = ImportMap::parse(
+            r#"{ "imports": { "std/": "https://deno.land/std/mod.ts" } }"#,
+            &deno_core::resolve_url(MAP_URL).unwrap(),
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("ending with \"/\""), "{}", error);
+    }
+
+    #[test]
+    fn scopes_apply_to_referrers_under_their_prefix() {
+        let map = import_map(
+            r#"{
+                "imports": { "config": "./config/default.ts" },
+                "scopes": {
+                    "./staging/": { "config": "./config/staging.ts" },
+                    "./staging/eu/": { "config": "./config/staging-eu.ts" }
+                }
+            }"#,
+        );
+        assert_eq!(
+            resolve(&map, "config", MAIN).as_deref(),
+            Some("file:///project/config/default.ts")
+        );
+        assert_eq!(
+            resolve(&map, "config", "file:///project/staging/test.ts").as_deref(),
+            Some("file:///project/config/staging.ts")
+        );
+        assert_eq!(
+            resolve(&map, "config", "file:///project/staging/eu/test.ts").as_deref(),
+            Some("file:///project/config/staging-eu.ts")
+        );
+        // Scopes fall back to the top-level imports
+        let map = import_map(
+            r#"{
+                "imports": { "utils": "./utils.ts" },
+                "scopes": { "./staging/": { "config": "./config/staging.ts" } }
+            }"#,
+        );
+        assert_eq!(
+            resolve(&map, "utils", "file:///project/staging/test.ts").as_deref(),
+            Some("file:///project/utils.ts")
+        );
+    }
+
+    #[test]
+    fn relative_keys_and_addresses_resolve_against_the_map_url() {
+        let map = import_map(
+            r#"{ "imports": {
+                "helpers": "./lib/helpers.ts",
+                "shared/": "../shared/",
+                "./legacy.ts": "./lib/modern.ts"
+            } }"#,
+        );
+        assert_eq!(
+            resolve(&map, "helpers", MAIN).as_deref(),
+            Some("file:///project/lib/helpers.ts")
+        );
+        assert_eq!(
+            resolve(&map, "shared/a.ts", MAIN).as_deref(),
+            Some("file:///shared/a.ts")
+        );
+        // URL-like specifiers match keys in their resolved form, whatever the referrer
+        assert_eq!(
+            resolve(&map, "./legacy.ts", MAIN).as_deref(),
+            Some("file:///project/lib/modern.ts")
+        );
+        assert_eq!(
+            resolve(&map, "../legacy.ts", "file:///project/tests/main.ts").as_deref(),
+            Some("file:///project/lib/modern.ts")
+        );
+    }
+
+    #[test]
+    fn config_is_read_next_to_the_test_file() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join(CONFIG_FILE_NAME),
+            r#"{ "imports": { "helpers": "./lib/helpers.ts" } }"#,
+        )
+        .unwrap();
+
+        let map = load_import_map(None, dir.path()).unwrap().unwrap();
+        let resolved = map.resolve("helpers", MAIN).unwrap();
+        assert_eq!(
+            resolved.to_file_path().unwrap(),
+            dir.path().join("lib/helpers.ts")
+        );
+
+        let other_dir = tempfile::tempdir().unwrap();
+        assert!(load_import_map(None, other_dir.path()).unwrap().is_none());
+    }
+
+    #[test]
+    fn config_can_point_at_an_import_map_file() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("maps")).unwrap();
+        std::fs::write(
+            dir.path().join(CONFIG_FILE_NAME),
+            r#"{ "importMap": "./maps/imports.json" }"#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("maps/imports.json"),
+            r#"{ "imports": { "helpers": "../lib/helpers.ts" } }"#,
+        )
+        .unwrap();
+
+        let map = load_import_map(None, dir.path()).unwrap().unwrap();
+        let resolved = map.resolve("helpers", MAIN).unwrap();
+        assert_eq!(
+            resolved.to_file_path().unwrap(),
+            dir.path().join("lib/helpers.ts")
+        );
+    }
+}
@@ -0,0 +1,210 @@
+use anyhow::Result;
+use std::{
+    fmt::Write as _,
+    time::{Duration, Instant},
+};
+
+use crate::outputs::{BlockingRuntime, Output, Sample};
+
+pub const DEFAULT_PUSH_INTERVAL: Duration = Duration::from_secs(1);
+const DEFAULT_DATABASE: &str = "v6";
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+// Lines per write request, below the point where InfluxDB recommends splitting batches
+const MAX_BATCH_LINES: usize = 5000;
+
+/// `--out influxdb=<url>`: writes every sample as a line-protocol point, the metric as
+/// measurement and its tags as Influx tags, in batches sent every push interval and at the end.
+///
+/// `http://host:8086/<db>` writes to the InfluxDB 1.x database `<db>` (`v6` by default);
+/// a URL ending with `/write` or `/api/v2/write` is used as is.
+pub struct InfluxDbOutput {
+    url: String,
+    token: Option<String>,
+    push_interval: Duration,
+    lines: String,
+    line_count: usize,
+    last_push: Instant,
+    client: reqwest::Client,
+    runtime: BlockingRuntime,
+}
+
+impl InfluxDbOutput {
+    pub fn new(url: &str, token: Option<&str>, push_interval: Duration) -> Result<Self> {
+        let mut parsed = reqwest::Url::parse(url).map_err(|e| anyhow::anyhow!("Invalid InfluxDB URL '{}': {}", url, e))?;
+        // `localhost:8086` parses too, with `localhost` as scheme
+        if !matches!(parsed.scheme(), "http" | "https") {
+            return Err(anyhow::anyhow!("Invalid InfluxDB URL '{}': expected http:// or https://", url));
+        }
+        if !parsed.path().ends_with("/write") {
+            let database = match parsed.path().trim_matches('/') {
+                "" => DEFAULT_DATABASE.to_string(),
+                database => database.to_string(),
+            };
+            parsed.set_path("/write");
+            parsed.query_pairs_mut().append_pair("db", &database);
+        }
+        // Timestamps are written in milliseconds
+        parsed.query_pairs_mut().append_pair("precision", "ms");
+
+        Ok(Self {
+            url: parsed.to_string(),
+            token: token.map(str::to_string),
+            push_interval,
+            lines: String::new(),
+            line_count: 0,
+            last_push: Instant::now(),
+            client: reqwest::Client::new(),
+            runtime: BlockingRuntime::default(),
+        })
+    }
+
+    // Failed batches are reported and dropped
+    fn push(&mut self) -> Result<()> {
+        self.last_push = Instant::now();
+        if self.line_count == 0 {
+            return Ok(());
+        }
+        let body = std::mem::take(&mut self.lines);
+        self.line_count = 0;
+
+        let mut request = self
+            .client
+            .post(&self.url)
+            .header("Content-Type", "text/plain; charset=utf-8")
+            .header("User-Agent", concat!("v6/", env!("CARGO_PKG_VERSION")))
+            .timeout(WRITE_TIMEOUT)
+            .body(body);
+        if let Some(token) = &self.token {
+            request = request.header("Authorization", format!("Token {}", token));
+        }
+        match self.runtime.send(request)? {
+            Ok(response) if response.status().is_success() => {}
+            Ok(response) => eprintln!("⚠️  InfluxDB write to {} failed: HTTP {}", self.url, response.status()),
+            Err(e) => eprintln!("⚠️  InfluxDB write to {} failed: {}", self.url, e),
+        }
+        Ok(())
+    }
+}
+
+impl Output for InfluxDbOutput {
+    fn write(&mut self, samples: &[Sample]) -> Result<()> {
+        for sample in samples {
+            // <measurement>[,<tag>=<value>...] value=<value> <timestamp>
+            self.lines.push_str(&escape(&sample.metric, &[',', ' ']));
+            for (tag, value) in sample.series_tags() {
+                // Influx rejects empty tag values
+                if !value.is_empty() {
+                    let _ = write!(self.lines, ",{}={}", escape(&tag, &[',', '=', ' ']), escape(&value, &[',', '=', ' ']));
+                }
+            }
+            let _ = writeln!(self.lines, " value={} {}", sample.value, sample.unix_millis());
+
+            self.line_count += 1;
+            if self.line_count >= MAX_BATCH_LINES {
+                self.push()?;
+            }
+        }
+        if self.last_push.elapsed() >= self.push_interval {
+            self.push()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.push()
+    }
+}
+
+// Backslash-escape the characters the line protocol gives a meaning to; line breaks can't be escaped
+fn escape(value: &str, special: &[char]) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        let c = if c == '\n' || c == '\r' { ' ' } else { c };
+        if special.contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::MetricKind;
+    use std::time::UNIX_EPOCH;
+
+    #[test]
+    fn escapes_special_characters_and_line_breaks() {
+        assert_eq!(escape("http_reqs", &[',', ' ']), "http_reqs");
+        assert_eq!(escape("a,b c", &[',', ' ']), "a\\,b\\ c");
+        // `=` only needs escaping in tags
+        assert_eq!(escape("a=b", &[',', ' ']), "a=b");
+        assert_eq!(escape("a=b", &[',', '=', ' ']), "a\\=b");
+        assert_eq!(escape("line\r\nbreak", &[',', '=', ' ']), "line\\ \\ break");
+        assert_eq!(escape("", &[',', ' ']), "");
+    }
+
+    #[test]
+    fn resolves_the_write_url() {
+        let url = |url: &str| {
+            InfluxDbOutput::new(url, None, DEFAULT_PUSH_INTERVAL)
+                .unwrap()
+                .url
+        };
+        assert_eq!(
+            url("http://localhost:8086"),
+            "http://localhost:8086/write?db=v6&precision=ms"
+        );
+        assert_eq!(
+            url("http://localhost:8086/k6"),
+            "http://localhost:8086/write?db=k6&precision=ms"
+        );
+        assert_eq!(
+            url("http://localhost:8086/api/v2/write?org=o&bucket=b"),
+            "http://localhost:8086/api/v2/write?org=o&bucket=b&precision=ms"
+        );
+        assert!(InfluxDbOutput::new("localhost:8086", None, DEFAULT_PUSH_INTERVAL).is_err());
+    }
+
+    #[test]
+    fn writes_one_line_per_sample() {
+        // Nothing is pushed before the interval, so the lines stay in the batch
+        let mut output =
+            InfluxDbOutput::new("http://localhost:8086", None, Duration::from_secs(3600)).unwrap();
+        let sample = |metric: &str, value: f64, tags: &[(&str, &str)]| Sample {
+            timestamp: UNIX_EPOCH + Duration::from_millis(1_700_000_000_123),
+            metric: metric.to_string(),
+            kind: MetricKind::Trend,
+            value,
+            tags: tags
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+        };
+        output
+            .write(&[
+                sample(
+                    "http_req_duration",
+                    12.5,
+                    &[
+                        ("name", "get item"),
+                        ("status", "200"),
+                        ("url", "/items/1"),
+                        ("vu", "1"),
+                    ],
+                ),
+                sample("my metric", 1.0, &[("a=b", "c,d"), ("empty", "")]),
+            ])
+            .unwrap();
+
+        assert_eq!(output.line_count, 2);
+        assert_eq!(
+            output.lines,
+            concat!(
+                "http_req_duration,name=get\\ item,status=200 value=12.5 1700000000123\n",
+                "my\\ metric,a\\=b=c\\,d value=1 1700000000123\n",
+            )
+        );
+    }
+}
@@ -1,12 +1,42 @@
+// VU tasks of one thread share the runtime as Arc<Mutex<_>>, and module loaders
+// have to return deno_core's ModuleLoaderError
+#![allow(clippy::arc_with_non_send_sync, clippy::result_large_err)]
+
 use anyhow::Result;
 use clap::Parser;
+use std::{path::Path, process::ExitCode, sync::Arc};
 
-
+mod archive;
+mod cache;
 mod cli;
 mod engine;
+mod http_client;
+mod import_map;
+mod influxdb;
+mod metrics;
+mod node_resolver;
+mod otlp;
+mod outputs;
+mod prometheus;
+mod protobuf;
+mod reports;
+mod statsd;
+mod thresholds;
 
-use cli::{Cli, Commands, init_command, parse_iterations_override, validate_file_exists, display_test_config};
-use engine::{create_fresh_runtime, extract_iterations, extract_duration, extract_timeout, extract_vus, extract_iteration_function, run_load_test};
+use cli::{Cli, Commands, init_command, types_command, parse_iterations_override, validate_file_exists, display_test_config};
+use archive::Archive;
+use cli::TestConfig;
+use engine::{LoaderOptions, RunOptions, create_fresh_runtime, create_recording_runtime, vendor_modules, extract_iterations, extract_duration, extract_timeout, extract_vus, extract_thresholds, run_load_test};
+use outputs::OutputConfig;
+use reports::ReportConfig;
+use thresholds::{THRESHOLDS_FAILED_EXIT_CODE, parse_thresholds};
+
+// Where `v6 run` sends results, besides the console summary
+struct ResultTargets {
+    summary_export: Option<String>,
+    outputs: Vec<OutputConfig>,
+    reports: Vec<ReportConfig>,
+}
 
 async fn run_command(
     file: &str, 
@@ -14,27 +44,45 @@ async fn run_command(
     duration_override: Option<f64>,
     timeout_override: Option<f64>,
     vus_override: Option<usize>,
-) -> Result<()> {
-    validate_file_exists(file)?;
-
-    let js_content = std::fs::read_to_string(file)
-        .map_err(|e| anyhow::anyhow!("Failed to read file '{}': {}", file, e))?;
-
-    // Create fresh runtime for configuration extraction only
-    let config_runtime = create_fresh_runtime()?;
+    targets: ResultTargets,
+    mut loader_options: LoaderOptions,
+) -> Result<ExitCode> {
+    let (main_module, runtime, base_iterations, base_duration, base_timeout, base_vus, thresholds) = if Archive::is_archive_path(file) {
+        // Archives carry their resolved config, and every module is served from the archive
+        validate_file_exists(file)?;
+        let archive = Arc::new(Archive::read(file)?);
+        let main_module = archive.main_module.clone();
+        let config = archive.config.clone();
+        loader_options.archive = Some(archive);
+        let runtime = create_fresh_runtime(&main_module, &loader_options).await?;
+        (
+            main_module,
+            runtime,
+            parse_iterations_override(&config.iterations),
+            config.duration,
+            config.timeout,
+            config.vus,
+            config.thresholds,
+        )
+    } else {
+        let main_module = resolve_main_module(file)?;
 
-    // Execute script in config runtime to extract configuration
-    {
-        let mut runtime = config_runtime.lock().unwrap();
-        let _ = runtime.execute_script("<v6/config-extraction>", js_content.clone());
-    }
+        // The entry file is evaluated once, as the main ES module, so imports and top-level
+        // await work. The config is read from this runtime and the test then runs in it.
+        let runtime = create_fresh_runtime(&main_module, &loader_options).await?;
 
-    // Extract iterations, duration, timeout, and vus from globalThis
-    let base_iterations = extract_iterations(config_runtime.clone())?;
-    let base_duration = extract_duration(config_runtime.clone())?;
-    let base_timeout = extract_timeout(config_runtime.clone())?;
-    let base_vus = extract_vus(config_runtime.clone())?;
-    let iteration_fn = extract_iteration_function(config_runtime.clone())?;
+        // Extract iterations, duration, timeout, vus and thresholds from globalThis
+        (
+            main_module,
+            runtime.clone(),
+            extract_iterations(runtime.clone())?,
+            extract_duration(runtime.clone())?,
+            extract_timeout(runtime.clone())?,
+            extract_vus(runtime.clone())?,
+            extract_thresholds(runtime)?,
+        )
+    };
+    let thresholds = parse_thresholds(&thresholds)?;
 
     // Apply CLI overrides
     let iterations = iterations_override
@@ -48,30 +96,129 @@ async fn run_command(
     display_test_config(file, iterations, duration, iteration_timeout_secs, vus);
 
     // Run the load test
-    run_load_test(iterations, duration, iteration_timeout_secs, vus, &iteration_fn).await?;
+    let options = RunOptions {
+        thresholds,
+        outputs: targets.outputs,
+        record_timeline: targets.reports.iter().any(ReportConfig::needs_timeline),
+    };
+    let result = run_load_test(
+        iterations,
+        duration,
+        iteration_timeout_secs,
+        vus,
+        &main_module,
+        runtime,
+        &options,
+    ).await?;
+
+    if let Some(path) = &targets.summary_export {
+        let json = serde_json::to_string_pretty(&result.summary)?;
+        std::fs::write(path, json)
+            .map_err(|e| anyhow::anyhow!("Failed to write summary export '{}': {}", path, e))?;
+        println!("📝 Summary exported to {}", path);
+    }
+    for report in &targets.reports {
+        report.write(&result)?;
+    }
 
-    // Clean up config runtime
-    drop(config_runtime);
+    if !result.thresholds_passed {
+        eprintln!("❌ Some thresholds have failed");
+        return Ok(ExitCode::from(THRESHOLDS_FAILED_EXIT_CODE));
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
 
+async fn bundle_command(file: &str, output: Option<&str>, loader_options: LoaderOptions) -> Result<()> {
+    let main_module = resolve_main_module(file)?;
+    let (config_runtime, recording) = create_recording_runtime(&main_module, &loader_options).await?;
+
+    let iterations = extract_iterations(config_runtime.clone())?;
+    let config = TestConfig {
+        iterations: if iterations.is_infinite() { "inf".to_string() } else { iterations.to_string() },
+        duration: extract_duration(config_runtime.clone())?,
+        timeout: extract_timeout(config_runtime.clone())?,
+        vus: extract_vus(config_runtime.clone())?,
+        thresholds: extract_thresholds(config_runtime)?,
+    };
+    // Fail at bundle time rather than when the archive is run
+    parse_thresholds(&config.thresholds)?;
+
+    let output = output.map(String::from).unwrap_or_else(|| {
+        let stem = std::path::Path::new(file)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "test".to_string());
+        format!("{}.{}", stem, archive::ARCHIVE_EXTENSION)
+    });
+
+    let archive = Archive::from_recording(main_module, config, &recording.borrow());
+    archive.write(&output)?;
+    println!("📦 Bundled {} modules into {}", archive.module_count(), output);
     Ok(())
 }
 
+async fn vendor_command(file: &str, loader_options: LoaderOptions) -> Result<()> {
+    let main_module = resolve_main_module(file)?;
+    let cached = vendor_modules(&main_module, &loader_options).await?;
+    println!("✅ {} remote modules cached and pinned in {}", cached, cache::LOCKFILE_NAME);
+    Ok(())
+}
+
+fn create_loader_options(file: &str, cached_only: bool, import_map: Option<&str>) -> Result<LoaderOptions> {
+    // v6.json, v6.lock and the module cache belong to the test file's directory, not the current one
+    let file_path = std::env::current_dir()?.join(file);
+    let root_dir = file_path.parent().map(Path::to_path_buf).unwrap_or(file_path);
+    Ok(LoaderOptions {
+        cached_only,
+        import_map: import_map::load_import_map(import_map, &root_dir)?.map(Arc::new),
+        archive: None,
+        root_dir,
+    })
+}
+
+fn resolve_main_module(file: &str) -> Result<deno_core::ModuleSpecifier> {
+    validate_file_exists(file)?;
+    deno_core::resolve_path(file, &std::env::current_dir()?)
+        .map_err(|e| anyhow::anyhow!("Failed to resolve '{}': {}", file, e))
+}
+
 #[tokio::main(flavor = "current_thread")]
-async fn main() -> Result<()> {
+async fn main() -> Result<ExitCode> {
     let cli = Cli::parse();
 
     match &cli.command {
         Commands::Init { file, iterations, duration, timeout, vus } => {
             init_command(file, iterations, *duration, *timeout, *vus)
         },
-        Commands::Run { file, iterations, duration, timeout, vus } => {
-            run_command(
+        Commands::Run { file, iterations, duration, timeout, vus, cached_only, import_map, summary_export, outputs, reports } => {
+            let targets = ResultTargets {
+                summary_export: summary_export.clone(),
+                outputs: outputs.iter().map(|spec| OutputConfig::parse(spec)).collect::<Result<_>>()?,
+                reports: reports.iter().map(|spec| ReportConfig::parse(spec)).collect::<Result<_>>()?,
+            };
+            // The only command with an exit code of its own, for failed thresholds
+            return run_command(
                 file, 
                 iterations.clone(), 
                 *duration, 
                 *timeout, 
-                *vus
+                *vus,
+                targets,
+                create_loader_options(file, *cached_only, import_map.as_deref())?,
+            ).await;
+        },
+        Commands::Vendor { file, import_map } => {
+            vendor_command(file, create_loader_options(file, false, import_map.as_deref())?).await
+        },
+        Commands::Bundle { file, output, cached_only, import_map } => {
+            bundle_command(
+                file,
+                output.as_deref(),
+                create_loader_options(file, *cached_only, import_map.as_deref())?,
             ).await
         },
+        Commands::Types { output } => types_command(output),
     }
+    .map(|_| ExitCode::SUCCESS)
 }
\ No newline at end of file
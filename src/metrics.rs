@@ -0,0 +1,1189 @@
+use hdrhistogram::Histogram;
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet},
+    rc::Rc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::http_client::RequestTimings;
+use crate::outputs::{Sample, SampleSender};
+
+pub const HTTP_REQS: &str = "http_reqs";
+pub const HTTP_REQ_DURATION: &str = "http_req_duration";
+pub const HTTP_REQ_FAILED: &str = "http_req_failed";
+pub const HTTP_REQ_BLOCKED: &str = "http_req_blocked";
+pub const HTTP_REQ_DNS_LOOKUP: &str = "http_req_dns_lookup";
+pub const HTTP_REQ_CONNECTING: &str = "http_req_connecting";
+pub const HTTP_REQ_TLS_HANDSHAKING: &str = "http_req_tls_handshaking";
+pub const HTTP_REQ_SENDING: &str = "http_req_sending";
+pub const HTTP_REQ_WAITING: &str = "http_req_waiting";
+pub const HTTP_REQ_RECEIVING: &str = "http_req_receiving";
+pub const DATA_SENT: &str = "data_sent";
+pub const DATA_RECEIVED: &str = "data_received";
+pub const CHECKS: &str = "checks";
+pub const GROUP_DURATION: &str = "group_duration";
+
+// Tag naming the check of each `checks` value
+const CHECK_TAG: &str = "check";
+// Tag holding the path of the enclosing group, e.g. `::login::mfa`
+const GROUP_TAG: &str = "group";
+// Tag holding the id of the VU adding the value
+const VU_TAG: &str = "vu";
+const GROUP_SEPARATOR: &str = "::";
+
+// Trend values are stored as integers in the histogram, with 3 decimals kept
+const TREND_PRECISION: f64 = 1000.0;
+const MAX_METRIC_NAME_LENGTH: usize = 128;
+// Samples are sent to outputs in batches of this size, or when flushed
+const SAMPLE_BATCH_SIZE: usize = 1000;
+
+// Tags with one value per VU or per raw URL, or a single value, aren't broken down in the summary
+const SUMMARY_EXCLUDED_TAGS: &[&str] = &["vu", "url", "scenario"];
+// Sub-metrics per metric, so custom tags with unbounded values can't grow the registry forever
+const MAX_SUBMETRICS: usize = 1000;
+
+/// Metrics registry of a runtime, stored in its `OpState`
+pub type SharedMetrics = Rc<RefCell<MetricsRegistry>>;
+
+/// Tags attached to a sample, sorted by key
+pub type Tags = BTreeMap<String, String>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MetricKind {
+    /// Sum of all values
+    Counter,
+    /// Last value, with min and max
+    Gauge,
+    /// Share of non-zero values
+    Rate,
+    /// Distribution of values
+    Trend,
+}
+
+impl MetricKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Counter => "counter",
+            Self::Gauge => "gauge",
+            Self::Rate => "rate",
+            Self::Trend => "trend",
+        }
+    }
+
+    pub fn parse(kind: &str) -> Option<Self> {
+        match kind {
+            "counter" => Some(Self::Counter),
+            "gauge" => Some(Self::Gauge),
+            "rate" => Some(Self::Rate),
+            "trend" => Some(Self::Trend),
+            _ => None,
+        }
+    }
+}
+
+/// How a metric is reduced to one number, as written in thresholds
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Aggregation {
+    /// Counter total
+    Count,
+    /// Counter per second, or Rate share of passes
+    Rate,
+    /// Gauge last value
+    Value,
+    Avg,
+    Min,
+    Max,
+    Med,
+    /// Trend percentile, 0-100
+    Percentile(f64),
+}
+
+impl Aggregation {
+    pub fn parse(aggregation: &str) -> Option<Self> {
+        match aggregation {
+            "count" => Some(Self::Count),
+            "rate" => Some(Self::Rate),
+            "value" => Some(Self::Value),
+            "avg" => Some(Self::Avg),
+            "min" => Some(Self::Min),
+            "max" => Some(Self::Max),
+            "med" => Some(Self::Med),
+            _ => {
+                let percentile: f64 = aggregation
+                    .strip_prefix("p(")?
+                    .strip_suffix(')')?
+                    .trim()
+                    .parse()
+                    .ok()?;
+                (0.0..=100.0).contains(&percentile).then_some(Self::Percentile(percentile))
+            }
+        }
+    }
+}
+
+/// What the values of a metric measure, used to format them in the summary
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MetricContains {
+    Default,
+    /// Milliseconds
+    Time,
+    /// Bytes
+    Data,
+}
+
+impl MetricContains {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Default => "default",
+            Self::Time => "time",
+            Self::Data => "data",
+        }
+    }
+}
+
+/// Aggregated values of a metric, or of one of its sub-metrics
+pub enum MetricValue {
+    Counter { sum: f64 },
+    Gauge { last: f64, min: f64, max: f64 },
+    Rate { passes: u64, total: u64 },
+    Trend(Histogram<u64>),
+}
+
+impl MetricValue {
+    pub fn new(kind: MetricKind) -> Self {
+        match kind {
+            MetricKind::Counter => Self::Counter { sum: 0.0 },
+            MetricKind::Gauge => Self::Gauge {
+                last: 0.0,
+                min: f64::INFINITY,
+                max: f64::NEG_INFINITY,
+            },
+            MetricKind::Rate => Self::Rate { passes: 0, total: 0 },
+            MetricKind::Trend => Self::Trend(
+                Histogram::new(3).expect("3 significant figures is a valid histogram precision"),
+            ),
+        }
+    }
+
+    /// Rates count non-zero values as passes, trends clamp negative values to 0
+    pub fn add(&mut self, value: f64) {
+        match self {
+            Self::Counter { sum } => *sum += value,
+            Self::Gauge { last, min, max } => {
+                *last = value;
+                *min = min.min(value);
+                *max = max.max(value);
+            }
+            Self::Rate { passes, total } => {
+                *total += 1;
+                if value != 0.0 {
+                    *passes += 1;
+                }
+            }
+            Self::Trend(histogram) => {
+                let value = (value.max(0.0) * TREND_PRECISION).round() as u64;
+                // `record` grows the histogram to fit the value; `saturating_record` alone would
+                // clamp it to the initial range. Only values past the largest possible range are clamped.
+                if histogram.record(value).is_err() {
+                    histogram.saturating_record(value);
+                }
+            }
+        }
+    }
+
+    /// `None` when the aggregation doesn't apply to this kind of metric, or there are no samples
+    pub fn aggregate(&self, aggregation: Aggregation, elapsed_secs: f64) -> Option<f64> {
+        let percentile = |histogram: &Histogram<u64>, quantile: f64| {
+            histogram.value_at_quantile(quantile) as f64 / TREND_PRECISION
+        };
+        match (self, aggregation) {
+            (Self::Counter { sum }, Aggregation::Count) => Some(*sum),
+            (Self::Counter { sum }, Aggregation::Rate) => Some(sum / elapsed_secs),
+            (Self::Gauge { min, .. }, _) if min.is_infinite() => None,
+            (Self::Gauge { last, .. }, Aggregation::Value) => Some(*last),
+            (Self::Gauge { min, .. }, Aggregation::Min) => Some(*min),
+            (Self::Gauge { max, .. }, Aggregation::Max) => Some(*max),
+            (Self::Rate { total: 0, .. }, Aggregation::Rate) => None,
+            (Self::Rate { passes, total }, Aggregation::Rate) => Some(*passes as f64 / *total as f64),
+            (Self::Trend(histogram), _) if histogram.is_empty() => None,
+            (Self::Trend(histogram), Aggregation::Avg) => Some(histogram.mean() / TREND_PRECISION),
+            (Self::Trend(histogram), Aggregation::Min) => Some(histogram.min() as f64 / TREND_PRECISION),
+            (Self::Trend(histogram), Aggregation::Max) => Some(histogram.max() as f64 / TREND_PRECISION),
+            (Self::Trend(histogram), Aggregation::Med) => Some(percentile(histogram, 0.5)),
+            (Self::Trend(histogram), Aggregation::Percentile(p)) => Some(percentile(histogram, p / 100.0)),
+            _ => None,
+        }
+    }
+
+    /// Every aggregation that applies to this kind of metric and has a value, named as in thresholds
+    pub fn stats(&self, elapsed_secs: f64) -> Vec<(&'static str, f64)> {
+        let aggregations: &[(&'static str, Aggregation)] = match self {
+            Self::Counter { .. } => &[("count", Aggregation::Count), ("rate", Aggregation::Rate)],
+            Self::Gauge { .. } => &[
+                ("value", Aggregation::Value),
+                ("min", Aggregation::Min),
+                ("max", Aggregation::Max),
+            ],
+            Self::Rate { .. } => &[("rate", Aggregation::Rate)],
+            Self::Trend(_) => &[
+                ("avg", Aggregation::Avg),
+                ("min", Aggregation::Min),
+                ("med", Aggregation::Med),
+                ("max", Aggregation::Max),
+                ("p(90)", Aggregation::Percentile(90.0)),
+                ("p(95)", Aggregation::Percentile(95.0)),
+                ("p(99)", Aggregation::Percentile(99.0)),
+            ],
+        };
+
+        aggregations
+            .iter()
+            .filter_map(|(name, aggregation)| Some((*name, self.aggregate(*aggregation, elapsed_secs)?)))
+            .collect()
+    }
+
+    // `stats` keyed by name, plus passes and fails of rates
+    fn to_json(&self, elapsed_secs: f64) -> serde_json::Value {
+        let mut values: serde_json::Map<String, serde_json::Value> = self
+            .stats(elapsed_secs)
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value.into()))
+            .collect();
+        if let Self::Rate { passes, total } = self {
+            values.insert("passes".to_string(), (*passes).into());
+            values.insert("fails".to_string(), (total - passes).into());
+        }
+        serde_json::Value::Object(values)
+    }
+
+    fn supports(kind: MetricKind, aggregation: Aggregation) -> bool {
+        matches!(
+            (kind, aggregation),
+            (MetricKind::Counter, Aggregation::Count | Aggregation::Rate)
+                | (MetricKind::Gauge, Aggregation::Value | Aggregation::Min | Aggregation::Max)
+                | (MetricKind::Rate, Aggregation::Rate)
+                | (
+                    MetricKind::Trend,
+                    Aggregation::Avg
+                        | Aggregation::Min
+                        | Aggregation::Max
+                        | Aggregation::Med
+                        | Aggregation::Percentile(_)
+                )
+        )
+    }
+
+    fn summarize(&self, contains: MetricContains, elapsed_secs: f64) -> String {
+        match self {
+            Self::Counter { sum } => format!(
+                "{} {}/s",
+                format_value(*sum, contains),
+                format_value(sum / elapsed_secs, contains)
+            ),
+            Self::Gauge { min, .. } if min.is_infinite() => "no samples".to_string(),
+            Self::Gauge { last, min, max } => format!(
+                "{} min={} max={}",
+                format_value(*last, contains),
+                format_value(*min, contains),
+                format_value(*max, contains)
+            ),
+            Self::Rate { passes, total } => format!(
+                "{:.2}% {} out of {} {:.2}/s",
+                if *total == 0 { 0.0 } else { *passes as f64 / *total as f64 * 100.0 },
+                passes,
+                total,
+                *passes as f64 / elapsed_secs
+            ),
+            Self::Trend(histogram) if histogram.is_empty() => "no samples".to_string(),
+            Self::Trend(histogram) => {
+                let stat = |value: f64| format_value(value / TREND_PRECISION, contains);
+                format!(
+                    "avg={} min={} med={} p(90)={} p(95)={} p(99)={} max={}",
+                    stat(histogram.mean()),
+                    stat(histogram.min() as f64),
+                    stat(histogram.value_at_quantile(0.5) as f64),
+                    stat(histogram.value_at_quantile(0.9) as f64),
+                    stat(histogram.value_at_quantile(0.95) as f64),
+                    stat(histogram.value_at_quantile(0.99) as f64),
+                    stat(histogram.max() as f64),
+                )
+            }
+        }
+    }
+}
+
+pub struct Metric {
+    kind: MetricKind,
+    contains: MetricContains,
+    value: MetricValue,
+    // Aggregates per tag value, keyed by `{key:value}`, up to `MAX_SUBMETRICS`
+    submetrics: BTreeMap<String, MetricValue>,
+    // Set once a tag value was left out because of `MAX_SUBMETRICS`
+    submetrics_capped: bool,
+    // Aggregates of values carrying all of the given tags, for thresholds
+    tracked: Vec<(Tags, MetricValue)>,
+}
+
+impl Metric {
+    fn new(kind: MetricKind, contains: MetricContains) -> Self {
+        Self {
+            kind,
+            contains,
+            value: MetricValue::new(kind),
+            submetrics: BTreeMap::new(),
+            submetrics_capped: false,
+            tracked: Vec::new(),
+        }
+    }
+
+    fn add(&mut self, name: &str, value: f64, tags: &Tags) {
+        self.value.add(value);
+        for (key, tag_value) in tags {
+            if SUMMARY_EXCLUDED_TAGS.contains(&key.as_str()) {
+                continue;
+            }
+            let submetric = format!("{{{}:{}}}", key, tag_value);
+            if let Some(aggregate) = self.submetrics.get_mut(&submetric) {
+                aggregate.add(value);
+            } else if self.submetrics.len() < MAX_SUBMETRICS {
+                let mut aggregate = MetricValue::new(self.kind);
+                aggregate.add(value);
+                self.submetrics.insert(submetric, aggregate);
+            } else if !self.submetrics_capped {
+                self.submetrics_capped = true;
+                eprintln!(
+                    "⚠️  Metric \"{}\" has more than {} tag values, new ones are left out of the summary",
+                    name, MAX_SUBMETRICS
+                );
+            }
+        }
+        for (filter, tracked) in &mut self.tracked {
+            if filter.iter().all(|(key, tag_value)| tags.get(key) == Some(tag_value)) {
+                tracked.add(value);
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.value = MetricValue::new(self.kind);
+        self.submetrics.clear();
+        self.submetrics_capped = false;
+        for (_, tracked) in &mut self.tracked {
+            *tracked = MetricValue::new(self.kind);
+        }
+    }
+}
+
+/// One completed (or failed) `fetch` call
+pub struct HttpRequestSample {
+    pub duration: Duration,
+    /// `None` when no response was received
+    pub status: Option<u16>,
+    pub bytes_sent: usize,
+    pub bytes_received: usize,
+    /// Phase breakdown, when a response was received
+    pub timings: Option<RequestTimings>,
+    pub tags: Tags,
+}
+
+impl HttpRequestSample {
+    // Transport errors and 4xx/5xx responses count as failed requests
+    fn is_failed(&self) -> bool {
+        self.status.is_none_or(|status| status >= 400)
+    }
+}
+
+// Metrics over time, as snapshots of the values added during each interval
+struct Timeline {
+    started: Instant,
+    last_snapshot: Duration,
+    // Values added since the last snapshot, per metric
+    interval: BTreeMap<String, MetricValue>,
+    // VUs that added values since the last snapshot
+    interval_vus: BTreeSet<String>,
+    snapshots: Vec<serde_json::Value>,
+}
+
+impl Timeline {
+    fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            last_snapshot: Duration::ZERO,
+            interval: BTreeMap::new(),
+            interval_vus: BTreeSet::new(),
+            snapshots: Vec::new(),
+        }
+    }
+
+    fn add(&mut self, name: &str, kind: MetricKind, value: f64, tags: &Tags) {
+        match self.interval.get_mut(name) {
+            Some(interval) => interval.add(value),
+            None => {
+                let mut interval = MetricValue::new(kind);
+                interval.add(value);
+                self.interval.insert(name.to_string(), interval);
+            }
+        }
+        if let Some(vu) = tags.get(VU_TAG)
+            && !self.interval_vus.contains(vu)
+        {
+            self.interval_vus.insert(vu.clone());
+        }
+    }
+
+    // `time` is the end of the interval in seconds since the start; rates are per second of the interval
+    fn snapshot(&mut self) {
+        let elapsed = self.started.elapsed();
+        let interval_secs = (elapsed - self.last_snapshot).as_secs_f64().max(f64::EPSILON);
+        let metrics: serde_json::Map<String, serde_json::Value> = std::mem::take(&mut self.interval)
+            .into_iter()
+            .map(|(name, value)| (name, value.to_json(interval_secs)))
+            .collect();
+        self.snapshots.push(serde_json::json!({
+            "time": elapsed.as_secs_f64(),
+            "vus": self.interval_vus.len(),
+            "metrics": metrics,
+        }));
+        self.interval_vus.clear();
+        self.last_snapshot = elapsed;
+    }
+}
+
+/// All metrics collected during a test run, built-in and custom, keyed by name
+pub struct MetricsRegistry {
+    metrics: BTreeMap<String, Metric>,
+    // Passes and fails per (group path, check name), for the summary's group tree
+    checks: BTreeMap<(String, String), (u64, u64)>,
+    // Samples not yet sent to outputs, when there are any (see `stream_samples`)
+    pending_samples: Option<(SampleSender, Vec<Sample>)>,
+    // Snapshots for the HTML report, when there is one (see `record_timeline`)
+    timeline: Option<Timeline>,
+}
+
+impl Default for MetricsRegistry {
+    fn default() -> Self {
+        let mut metrics = BTreeMap::new();
+        for (name, kind, contains) in [
+            (HTTP_REQS, MetricKind::Counter, MetricContains::Default),
+            (HTTP_REQ_DURATION, MetricKind::Trend, MetricContains::Time),
+            (HTTP_REQ_FAILED, MetricKind::Rate, MetricContains::Default),
+            (HTTP_REQ_BLOCKED, MetricKind::Trend, MetricContains::Time),
+            (HTTP_REQ_DNS_LOOKUP, MetricKind::Trend, MetricContains::Time),
+            (HTTP_REQ_CONNECTING, MetricKind::Trend, MetricContains::Time),
+            (HTTP_REQ_TLS_HANDSHAKING, MetricKind::Trend, MetricContains::Time),
+            (HTTP_REQ_SENDING, MetricKind::Trend, MetricContains::Time),
+            (HTTP_REQ_WAITING, MetricKind::Trend, MetricContains::Time),
+            (HTTP_REQ_RECEIVING, MetricKind::Trend, MetricContains::Time),
+            (DATA_SENT, MetricKind::Counter, MetricContains::Data),
+            (DATA_RECEIVED, MetricKind::Counter, MetricContains::Data),
+            (CHECKS, MetricKind::Rate, MetricContains::Default),
+            (GROUP_DURATION, MetricKind::Trend, MetricContains::Time),
+        ] {
+            metrics.insert(name.to_string(), Metric::new(kind, contains));
+        }
+        Self {
+            metrics,
+            checks: BTreeMap::new(),
+            pending_samples: None,
+            timeline: None,
+        }
+    }
+}
+
+impl MetricsRegistry {
+    pub fn shared() -> SharedMetrics {
+        Rc::new(RefCell::new(Self::default()))
+    }
+
+    /// Declare a custom metric. Declaring an existing metric again with the same kind is a no-op,
+    /// so a script can create its metrics at the top level of every module that uses them.
+    pub fn register(&mut self, name: &str, kind: MetricKind, contains: MetricContains) -> Result<(), String> {
+        let is_valid_name = !name.is_empty()
+            && name.len() <= MAX_METRIC_NAME_LENGTH
+            && !name.starts_with(|c: char| c.is_ascii_digit())
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !is_valid_name {
+            return Err(format!(
+                "Invalid metric name \"{}\": use up to {} letters, digits and underscores, not starting with a digit",
+                name, MAX_METRIC_NAME_LENGTH
+            ));
+        }
+
+        match self.metrics.get(name) {
+            Some(metric) if metric.kind != kind => Err(format!(
+                "Metric \"{}\" already exists as a {:?}",
+                name, metric.kind
+            )),
+            Some(_) => Ok(()),
+            None => {
+                self.metrics.insert(name.to_string(), Metric::new(kind, contains));
+                Ok(())
+            }
+        }
+    }
+
+    /// Add a value to a metric; tagged values are also aggregated per tag value
+    pub fn add(&mut self, name: &str, value: f64, tags: &Tags) -> Result<(), String> {
+        if !value.is_finite() {
+            return Err(format!("Value of metric \"{}\" must be a finite number, got {}", name, value));
+        }
+        let metric = self
+            .metrics
+            .get_mut(name)
+            .ok_or_else(|| format!("Unknown metric \"{}\"", name))?;
+        metric.add(name, value, tags);
+
+        if let Some(timeline) = &mut self.timeline {
+            timeline.add(name, metric.kind, value, tags);
+        }
+
+        if let Some((_, pending)) = &mut self.pending_samples {
+            pending.push(Sample {
+                timestamp: SystemTime::now(),
+                metric: name.to_string(),
+                kind: metric.kind,
+                value,
+                tags: tags.clone(),
+            });
+            if pending.len() >= SAMPLE_BATCH_SIZE {
+                self.flush_samples();
+            }
+        }
+
+        if name == CHECKS
+            && let Some(check) = tags.get(CHECK_TAG)
+        {
+            let group = tags.get(GROUP_TAG).cloned().unwrap_or_default();
+            let (passes, fails) = self.checks.entry((group, check.clone())).or_default();
+            if value != 0.0 {
+                *passes += 1;
+            } else {
+                *fails += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Aggregate values carrying all of `filter`'s tags from now on (see `aggregate`)
+    pub fn track_submetric(&mut self, name: &str, filter: &Tags) -> Result<(), String> {
+        let metric = self
+            .metrics
+            .get_mut(name)
+            .ok_or_else(|| format!("Unknown metric \"{}\"", name))?;
+        if !filter.is_empty() && !metric.tracked.iter().any(|(tags, _)| tags == filter) {
+            metric.tracked.push((filter.clone(), MetricValue::new(metric.kind)));
+        }
+        Ok(())
+    }
+
+    /// Reduce a metric, or its tracked sub-metric when `filter` isn't empty, to one number.
+    /// `None` when there are no samples.
+    pub fn aggregate(&self, name: &str, filter: &Tags, aggregation: Aggregation, elapsed_secs: f64) -> Option<f64> {
+        let metric = self.metrics.get(name)?;
+        let value = if filter.is_empty() {
+            &metric.value
+        } else {
+            &metric.tracked.iter().find(|(tags, _)| tags == filter)?.1
+        };
+        value.aggregate(aggregation, elapsed_secs.max(f64::EPSILON))
+    }
+
+    /// Check that `aggregation` applies to the metric, e.g. `p(95)` to a Trend
+    pub fn validate_aggregation(&self, name: &str, aggregation: Aggregation) -> Result<(), String> {
+        let metric = self
+            .metrics
+            .get(name)
+            .ok_or_else(|| format!("Unknown metric \"{}\"", name))?;
+        if MetricValue::supports(metric.kind, aggregation) {
+            Ok(())
+        } else {
+            Err(format!("{:?} can't be applied to {:?} metric \"{}\"", aggregation, metric.kind, name))
+        }
+    }
+
+    /// Send every value added from now on to the `--out` outputs, in batches
+    pub fn stream_samples(&mut self, sender: SampleSender) {
+        self.pending_samples = Some((sender, Vec::with_capacity(SAMPLE_BATCH_SIZE)));
+    }
+
+    /// Send the samples batched so far to the outputs
+    pub fn flush_samples(&mut self) {
+        if let Some((sender, pending)) = &mut self.pending_samples
+            && !pending.is_empty()
+        {
+            // Blocks while a file output is behind, see `SampleSender::send`
+            sender.send(std::mem::replace(pending, Vec::with_capacity(SAMPLE_BATCH_SIZE)));
+        }
+    }
+
+    /// Flush the remaining samples and stop streaming, so the output writer can finish
+    pub fn stop_streaming(&mut self) {
+        self.flush_samples();
+        self.pending_samples = None;
+    }
+
+    /// Record the values added from now on over time, in a snapshot per `snapshot_timeline` call
+    pub fn record_timeline(&mut self) {
+        self.timeline = Some(Timeline::new());
+    }
+
+    /// Close the current interval of the timeline
+    pub fn snapshot_timeline(&mut self) {
+        if let Some(timeline) = &mut self.timeline {
+            timeline.snapshot();
+        }
+    }
+
+    /// Snapshots of the timeline, oldest first: `[{ time, vus, metrics: { <name>: <values> } }]`.
+    /// `None` unless `record_timeline` was called.
+    pub fn timeline_json(&self) -> Option<serde_json::Value> {
+        self.timeline
+            .as_ref()
+            .map(|timeline| serde_json::Value::from(timeline.snapshots.clone()))
+    }
+
+    /// Drop every value recorded so far, keeping the declared metrics
+    pub fn reset(&mut self) {
+        self.metrics.values_mut().for_each(Metric::reset);
+        self.checks.clear();
+    }
+
+    pub fn record_http_request(&mut self, sample: &HttpRequestSample) {
+        let mut values = vec![
+            (HTTP_REQS, 1.0),
+            (HTTP_REQ_DURATION, sample.duration.as_secs_f64() * 1000.0),
+            (HTTP_REQ_FAILED, if sample.is_failed() { 1.0 } else { 0.0 }),
+            (DATA_SENT, sample.bytes_sent as f64),
+            (DATA_RECEIVED, sample.bytes_received as f64),
+        ];
+        if let Some(timings) = &sample.timings {
+            for (name, duration) in [
+                (HTTP_REQ_BLOCKED, timings.blocked),
+                (HTTP_REQ_DNS_LOOKUP, timings.dns_lookup),
+                (HTTP_REQ_CONNECTING, timings.connecting),
+                (HTTP_REQ_TLS_HANDSHAKING, timings.tls_handshaking),
+                (HTTP_REQ_SENDING, timings.sending),
+                (HTTP_REQ_WAITING, timings.waiting),
+                (HTTP_REQ_RECEIVING, timings.receiving),
+            ] {
+                values.push((name, duration.as_secs_f64() * 1000.0));
+            }
+        }
+
+        for (name, value) in values {
+            // Built-in metrics always exist and values are finite
+            let _ = self.add(name, value, &sample.tags);
+        }
+    }
+
+    /// Print the end-of-test summary; `elapsed` is the test duration used for per-second rates
+    pub fn print_summary(&self, elapsed: Duration) {
+        let elapsed_secs = elapsed.as_secs_f64().max(f64::EPSILON);
+        let name_width = self
+            .metrics
+            .iter()
+            .flat_map(|(name, metric)| {
+                std::iter::once(name.len())
+                    .chain(metric.submetrics.keys().map(move |tags| name.len() + tags.len() + 2))
+            })
+            .max()
+            .unwrap_or(0)
+            + 3;
+
+        println!("{}", "─".repeat(50));
+        self.print_groups();
+        println!("📊 Summary ({:.2}s)", elapsed.as_secs_f64());
+        for (name, metric) in &self.metrics {
+            println!(
+                "  {:.<width$}: {}",
+                name,
+                metric.value.summarize(metric.contains, elapsed_secs),
+                width = name_width
+            );
+            // Checks and group durations are listed in the group tree above
+            if name == CHECKS || name == GROUP_DURATION {
+                continue;
+            }
+            for (tags, value) in &metric.submetrics {
+                println!(
+                    "    {:.<width$}: {}",
+                    format!("{}{}", name, tags),
+                    value.summarize(metric.contains, elapsed_secs),
+                    width = name_width - 2
+                );
+            }
+        }
+    }
+
+    /// Every metric's aggregated values and the group tree with its checks, as in `--summary-export`
+    pub fn summary_json(&self, elapsed: Duration) -> serde_json::Value {
+        let elapsed_secs = elapsed.as_secs_f64().max(f64::EPSILON);
+        let metrics: serde_json::Map<String, serde_json::Value> = self
+            .metrics
+            .iter()
+            .map(|(name, metric)| {
+                let submetrics: serde_json::Map<String, serde_json::Value> = metric
+                    .submetrics
+                    .iter()
+                    .map(|(tags, value)| (tags.clone(), value.to_json(elapsed_secs)))
+                    .collect();
+                let summary = serde_json::json!({
+                    "type": metric.kind.as_str(),
+                    "contains": metric.contains.as_str(),
+                    "values": metric.value.to_json(elapsed_secs),
+                    "submetrics": submetrics,
+                });
+                (name.clone(), summary)
+            })
+            .collect();
+
+        let (groups, durations) = self.group_paths();
+        serde_json::json!({
+            "metrics": metrics,
+            "rootGroup": self.group_json("", &groups, &durations, elapsed_secs),
+        })
+    }
+
+    // Paths of every group with checks or a duration, and of their ancestors, with the durations
+    fn group_paths(&self) -> (BTreeSet<&str>, BTreeMap<&str, &MetricValue>) {
+        let prefix = format!("{{{}:", GROUP_TAG);
+        let durations: BTreeMap<&str, &MetricValue> = self.metrics[GROUP_DURATION]
+            .submetrics
+            .iter()
+            .filter_map(|(tags, value)| Some((tags.strip_prefix(&prefix)?.strip_suffix('}')?, value)))
+            .collect();
+
+        let mut groups: BTreeSet<&str> = BTreeSet::new();
+        for path in durations.keys().copied().chain(self.checks.keys().map(|(group, _)| group.as_str())) {
+            let mut path = path;
+            while !path.is_empty() && groups.insert(path) {
+                path = path.rsplit_once(GROUP_SEPARATOR).map_or("", |(parent, _)| parent);
+            }
+        }
+        (groups, durations)
+    }
+
+    // (name, passes, fails) of the checks directly in a group
+    fn group_checks<'a>(&'a self, path: &'a str) -> impl Iterator<Item = (&'a str, u64, u64)> + 'a {
+        self.checks
+            .iter()
+            .filter(move |((group, _), _)| group == path)
+            .map(|((_, name), (passes, fails))| (name.as_str(), *passes, *fails))
+    }
+
+    fn group_json(
+        &self,
+        path: &str,
+        groups: &BTreeSet<&str>,
+        durations: &BTreeMap<&str, &MetricValue>,
+        elapsed_secs: f64,
+    ) -> serde_json::Value {
+        let checks: Vec<serde_json::Value> = self
+            .group_checks(path)
+            .map(|(name, passes, fails)| serde_json::json!({ "name": name, "passes": passes, "fails": fails }))
+            .collect();
+        let children: Vec<serde_json::Value> = child_groups(groups, path)
+            .map(|child| self.group_json(child, groups, durations, elapsed_secs))
+            .collect();
+        serde_json::json!({
+            "name": group_name(path),
+            "path": path,
+            "duration": durations.get(path).map(|value| value.to_json(elapsed_secs)),
+            "checks": checks,
+            "groups": children,
+        })
+    }
+
+    // Checks and group durations as a tree of groups, root-level checks first
+    fn print_groups(&self) {
+        let (groups, durations) = self.group_paths();
+        if groups.is_empty() && self.checks.is_empty() {
+            return;
+        }
+
+        println!("✅ Checks");
+        self.print_group("", 0, &groups, &durations);
+    }
+
+    fn print_group(&self, path: &str, depth: usize, groups: &BTreeSet<&str>, durations: &BTreeMap<&str, &MetricValue>) {
+        let indent = "  ".repeat(depth + 1);
+        for (name, passes, fails) in self.group_checks(path) {
+            println!(
+                "{}{} {}: ✓ {} / ✗ {}",
+                indent,
+                if fails == 0 { "✓" } else { "✗" },
+                name,
+                passes,
+                fails
+            );
+        }
+
+        for child in child_groups(groups, path) {
+            let duration = durations
+                .get(child)
+                .and_then(|value| {
+                    let avg = value.aggregate(Aggregation::Avg, 1.0)?;
+                    let p95 = value.aggregate(Aggregation::Percentile(95.0), 1.0)?;
+                    Some(format!(" (avg={} p(95)={})", format_duration(avg), format_duration(p95)))
+                })
+                .unwrap_or_default();
+            println!("{}█ {}{}", indent, group_name(child), duration);
+            self.print_group(child, depth + 1, groups, durations);
+        }
+    }
+}
+
+// Direct sub-groups of the group at `path`
+fn child_groups<'a>(groups: &'a BTreeSet<&str>, path: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+    groups
+        .iter()
+        .copied()
+        .filter(move |group| group.rsplit_once(GROUP_SEPARATOR).is_some_and(|(parent, _)| parent == path))
+}
+
+// `::login::mfa` -> `mfa`
+fn group_name(path: &str) -> &str {
+    path.rsplit_once(GROUP_SEPARATOR).map_or(path, |(_, name)| name)
+}
+
+fn format_value(value: f64, contains: MetricContains) -> String {
+    match contains {
+        MetricContains::Default => format!("{}", (value * 100.0).round() / 100.0),
+        MetricContains::Time => format_duration(value),
+        MetricContains::Data => format_bytes(value),
+    }
+}
+
+pub(crate) fn format_duration(ms: f64) -> String {
+    if ms < 1.0 {
+        format!("{:.2}µs", ms * 1000.0)
+    } else if ms < 1000.0 {
+        format!("{:.2}ms", ms)
+    } else {
+        format!("{:.2}s", ms / 1000.0)
+    }
+}
+
+pub(crate) fn format_bytes(bytes: f64) -> String {
+    const UNITS: [&str; 4] = ["B", "kB", "MB", "GB"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1000.0 && unit < UNITS.len() - 1 {
+        value /= 1000.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trend_keeps_values_above_the_initial_histogram_range() {
+        let mut trend = MetricValue::new(MetricKind::Trend);
+        for _ in 0..98 {
+            trend.add(5.0);
+        }
+        // Two minutes, far above the range the histogram starts with
+        trend.add(120_000.0);
+        trend.add(120_000.0);
+
+        let close_to = |value: Option<f64>, expected: f64| {
+            let value = value.unwrap();
+            assert!(
+                (value - expected).abs() <= expected * 0.001,
+                "{} != {}",
+                value,
+                expected
+            );
+        };
+        close_to(trend.aggregate(Aggregation::Max, 1.0), 120_000.0);
+        close_to(
+            trend.aggregate(Aggregation::Percentile(99.0), 1.0),
+            120_000.0,
+        );
+        close_to(trend.aggregate(Aggregation::Med, 1.0), 5.0);
+        close_to(trend.aggregate(Aggregation::Min, 1.0), 5.0);
+        close_to(
+            trend.aggregate(Aggregation::Avg, 1.0),
+            (98.0 * 5.0 + 2.0 * 120_000.0) / 100.0,
+        );
+    }
+
+    #[test]
+    fn submetrics_are_capped_per_metric() {
+        let mut registry = MetricsRegistry::default();
+        let tags = |name: String| {
+            Tags::from([
+                ("name".to_string(), name),
+                ("url".to_string(), "/".to_string()),
+            ])
+        };
+        for id in 0..MAX_SUBMETRICS + 500 {
+            registry
+                .add(HTTP_REQS, 1.0, &tags(format!("item {}", id)))
+                .unwrap();
+        }
+        registry
+            .add(HTTP_REQS, 1.0, &tags("item 0".to_string()))
+            .unwrap();
+
+        let metric = &registry.metrics[HTTP_REQS];
+        assert_eq!(metric.submetrics.len(), MAX_SUBMETRICS);
+        assert!(metric.submetrics_capped);
+        // Known tag values keep aggregating, and the metric itself counts every value
+        assert_eq!(
+            metric.submetrics["{name:item 0}"].aggregate(Aggregation::Count, 1.0),
+            Some(2.0)
+        );
+        assert!(
+            !metric
+                .submetrics
+                .contains_key(&format!("{{name:item {}}}", MAX_SUBMETRICS))
+        );
+        assert_eq!(
+            metric.value.aggregate(Aggregation::Count, 1.0),
+            Some((MAX_SUBMETRICS + 501) as f64)
+        );
+    }
+
+    #[test]
+    fn declaring_a_metric_again_needs_the_same_kind() {
+        let mut registry = MetricsRegistry::default();
+        registry
+            .register("logins", MetricKind::Counter, MetricContains::Default)
+            .unwrap();
+        // Declared again by another module
+        registry
+            .register("logins", MetricKind::Counter, MetricContains::Default)
+            .unwrap();
+
+        assert_eq!(
+            registry.register("logins", MetricKind::Trend, MetricContains::Time),
+            Err("Metric \"logins\" already exists as a Counter".to_string())
+        );
+        // Built-in metrics can't be redefined as another kind either
+        assert_eq!(
+            registry.register(
+                HTTP_REQ_DURATION,
+                MetricKind::Counter,
+                MetricContains::Default
+            ),
+            Err("Metric \"http_req_duration\" already exists as a Trend".to_string())
+        );
+        assert_eq!(
+            registry.register(HTTP_REQ_DURATION, MetricKind::Trend, MetricContains::Time),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_metric_names() {
+        let mut registry = MetricsRegistry::default();
+        for name in [
+            "",
+            "1st",
+            "my-metric",
+            "my metric",
+            "métrique",
+            &"x".repeat(MAX_METRIC_NAME_LENGTH + 1),
+        ] {
+            assert!(
+                registry
+                    .register(name, MetricKind::Counter, MetricContains::Default)
+                    .is_err(),
+                "{:?}",
+                name
+            );
+        }
+        for name in ["_private", "Metric_2", &"x".repeat(MAX_METRIC_NAME_LENGTH)] {
+            assert!(
+                registry
+                    .register(name, MetricKind::Counter, MetricContains::Default)
+                    .is_ok(),
+                "{:?}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_values_of_unknown_metrics_and_non_finite_values() {
+        let mut registry = MetricsRegistry::default();
+        assert_eq!(
+            registry.add("missing", 1.0, &Tags::new()),
+            Err("Unknown metric \"missing\"".to_string())
+        );
+        for value in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            assert!(
+                registry.add(HTTP_REQS, value, &Tags::new()).is_err(),
+                "{}",
+                value
+            );
+        }
+        assert_eq!(
+            registry.metrics[HTTP_REQS]
+                .value
+                .aggregate(Aggregation::Count, 1.0),
+            Some(0.0)
+        );
+    }
+
+    #[test]
+    fn summarizes_custom_metrics_by_kind() {
+        let mut registry = MetricsRegistry::default();
+        registry
+            .register("orders", MetricKind::Counter, MetricContains::Default)
+            .unwrap();
+        registry
+            .register("queue_size", MetricKind::Gauge, MetricContains::Default)
+            .unwrap();
+        registry
+            .register("cache_hits", MetricKind::Rate, MetricContains::Default)
+            .unwrap();
+        registry
+            .register("render_time", MetricKind::Trend, MetricContains::Time)
+            .unwrap();
+        for (name, value) in [
+            ("orders", 2.0),
+            ("orders", 3.0),
+            ("queue_size", 5.0),
+            ("queue_size", -1.0),
+            ("queue_size", 3.0),
+            // Any non-zero value is a pass
+            ("cache_hits", 1.0),
+            ("cache_hits", 0.0),
+            ("cache_hits", 0.5),
+            ("cache_hits", 1.0),
+            ("render_time", 10.0),
+            ("render_time", 30.0),
+        ] {
+            registry.add(name, value, &Tags::new()).unwrap();
+        }
+
+        let summary = registry.summary_json(Duration::from_secs(2));
+        let metric = |name: &str| summary["metrics"][name].clone();
+        assert_eq!(
+            metric("orders"),
+            serde_json::json!({
+                "type": "counter",
+                "contains": "default",
+                "values": { "count": 5.0, "rate": 2.5 },
+                "submetrics": {},
+            })
+        );
+        assert_eq!(
+            metric("queue_size")["values"],
+            serde_json::json!({ "value": 3.0, "min": -1.0, "max": 5.0 })
+        );
+        assert_eq!(
+            metric("cache_hits")["values"],
+            serde_json::json!({ "rate": 0.75, "passes": 3, "fails": 1 })
+        );
+        assert_eq!(metric("render_time")["contains"], "time");
+        let avg = metric("render_time")["values"]["avg"].as_f64().unwrap();
+        assert!((avg - 20.0).abs() < 0.1, "{}", avg);
+    }
+
+    fn tags(pairs: &[(&str, &str)]) -> Tags {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn tallies_checks_per_group_and_name() {
+        let mut registry = MetricsRegistry::default();
+        for (value, tags) in [
+            (1.0, tags(&[("check", "status is 200")])),
+            (0.0, tags(&[("check", "status is 200")])),
+            (
+                1.0,
+                tags(&[("check", "status is 200"), ("group", "::login")]),
+            ),
+            // Without a name it only counts in the rate
+            (1.0, Tags::new()),
+        ] {
+            registry.add(CHECKS, value, &tags).unwrap();
+        }
+
+        assert_eq!(
+            registry.checks,
+            BTreeMap::from([
+                ((String::new(), "status is 200".to_string()), (1, 1)),
+                (("::login".to_string(), "status is 200".to_string()), (1, 0)),
+            ])
+        );
+        assert_eq!(
+            registry.metrics[CHECKS]
+                .value
+                .aggregate(Aggregation::Rate, 1.0),
+            Some(0.75)
+        );
+    }
+
+    #[test]
+    fn summary_nests_groups_with_their_checks_and_durations() {
+        let mut registry = MetricsRegistry::default();
+        registry
+            .add(CHECKS, 1.0, &tags(&[("check", "home")]))
+            .unwrap();
+        registry
+            .add(
+                CHECKS,
+                0.0,
+                &tags(&[("check", "code accepted"), ("group", "::login::mfa")]),
+            )
+            .unwrap();
+        registry
+            .add(GROUP_DURATION, 12.0, &tags(&[("group", "::login::mfa")]))
+            .unwrap();
+        registry
+            .add(GROUP_DURATION, 30.0, &tags(&[("group", "::checkout")]))
+            .unwrap();
+
+        let summary = registry.summary_json(Duration::from_secs(1));
+        let root = &summary["rootGroup"];
+        assert_eq!(
+            (&root["name"], &root["path"], &root["duration"]),
+            (&"".into(), &"".into(), &serde_json::Value::Null)
+        );
+        assert_eq!(
+            root["checks"],
+            serde_json::json!([{ "name": "home", "passes": 1, "fails": 0 }])
+        );
+
+        let groups = root["groups"].as_array().unwrap();
+        let paths: Vec<&str> = groups
+            .iter()
+            .map(|group| group["path"].as_str().unwrap())
+            .collect();
+        assert_eq!(paths, ["::checkout", "::login"]);
+        let (checkout, login) = (&groups[0], &groups[1]);
+        assert_eq!(checkout["name"], "checkout");
+        assert_eq!(
+            checkout["duration"]["max"].as_f64().map(f64::round),
+            Some(30.0)
+        );
+        // A parent without checks or a duration of its own is still in the tree
+        assert_eq!(
+            (&login["duration"], &login["checks"]),
+            (&serde_json::Value::Null, &serde_json::json!([]))
+        );
+
+        let mfa = &login["groups"][0];
+        assert_eq!(
+            (&mfa["name"], &mfa["path"]),
+            (&"mfa".into(), &"::login::mfa".into())
+        );
+        assert_eq!(
+            mfa["checks"],
+            serde_json::json!([{ "name": "code accepted", "passes": 0, "fails": 1 }])
+        );
+        assert_eq!(mfa["duration"]["max"].as_f64().map(f64::round), Some(12.0));
+        assert_eq!(mfa["groups"], serde_json::json!([]));
+    }
+}
@@ -0,0 +1,193 @@
+// `check` and `expect`, re-exported from "v6". Every assertion adds a value to the
+// built-in `checks` rate, tagged with its name, and doesn't throw on failure.
+import { normalizeTags } from "ext:v6/modules/metrics.js";
+import { contextTags } from "ext:v6/modules/execution.js";
+
+const { core } = Deno;
+
+const CHECKS_METRIC = "checks";
+const MAX_FORMATTED_LENGTH = 60;
+
+function recordCheck(name, passed, tags) {
+  core.ops.op_metric_add(CHECKS_METRIC, passed ? 1 : 0, {
+    ...contextTags(),
+    ...normalizeTags(tags),
+    check: name,
+  });
+}
+
+// Runs every named predicate against `value` without throwing.
+// Returns true only when all of them pass.
+export function check(value, checks, tags) {
+  let passed = true;
+  for (const [name, predicate] of Object.entries(checks)) {
+    let result;
+    try {
+      result = typeof predicate === "function" ? predicate(value) : predicate;
+    } catch (error) {
+      console.error(`check "${name}" threw: ${error}`);
+      result = false;
+    }
+    recordCheck(name, Boolean(result), tags);
+    passed = passed && Boolean(result);
+  }
+  return passed;
+}
+
+// Thrown by `expect.strict` matchers on failure, which fails the iteration
+export class ExpectationError extends Error {
+  constructor(message) {
+    super(message);
+    this.name = "ExpectationError";
+  }
+}
+
+function format(value) {
+  let formatted;
+  try {
+    formatted = JSON.stringify(value) ?? String(value);
+  } catch {
+    formatted = String(value);
+  }
+  return formatted.length > MAX_FORMATTED_LENGTH
+    ? `${formatted.slice(0, MAX_FORMATTED_LENGTH)}\u2026`
+    : formatted;
+}
+
+function isObject(value) {
+  return typeof value === "object" && value !== null;
+}
+
+// Recursive equality of plain values, arrays and objects
+function equals(actual, expected) {
+  if (Object.is(actual, expected)) {
+    return true;
+  }
+  if (!isObject(actual) || !isObject(expected)) {
+    return false;
+  }
+  if (Array.isArray(actual) !== Array.isArray(expected)) {
+    return false;
+  }
+  if (actual instanceof Date && expected instanceof Date) {
+    return actual.getTime() === expected.getTime();
+  }
+  const keys = Object.keys(actual);
+  return keys.length === Object.keys(expected).length &&
+    keys.every((key) => Object.hasOwn(expected, key) && equals(actual[key], expected[key]));
+}
+
+// Whether `actual` has every property of `expected`, recursively
+function matchesObject(actual, expected) {
+  if (!isObject(expected)) {
+    return equals(actual, expected);
+  }
+  if (!isObject(actual)) {
+    return false;
+  }
+  if (Array.isArray(expected)) {
+    return Array.isArray(actual) && actual.length === expected.length &&
+      expected.every((value, i) => matchesObject(actual[i], value));
+  }
+  return Object.entries(expected).every(([key, value]) =>
+    key in actual && matchesObject(actual[key], value)
+  );
+}
+
+function contains(actual, expected) {
+  if (typeof actual === "string") {
+    return actual.includes(String(expected));
+  }
+  if (actual !== null && typeof actual?.[Symbol.iterator] === "function") {
+    return [...actual].some((item) => Object.is(item, expected));
+  }
+  return false;
+}
+
+// Jest-like matchers. Each returns whether it passed and is recorded as a check named
+// after the optional `name` given to `expect`, or after the matcher, e.g. `expect(received).toBe(200)`
+class Expectation {
+  #actual;
+  #name;
+  #negated;
+  #strict;
+
+  constructor(actual, name, negated, strict) {
+    this.#actual = actual;
+    this.#name = name;
+    this.#negated = negated;
+    this.#strict = strict;
+  }
+
+  get not() {
+    return new Expectation(this.#actual, this.#name, !this.#negated, this.#strict);
+  }
+
+  toBe(expected) {
+    return this.#assert("toBe", [expected], Object.is(this.#actual, expected));
+  }
+
+  toEqual(expected) {
+    return this.#assert("toEqual", [expected], equals(this.#actual, expected));
+  }
+
+  toContain(expected) {
+    return this.#assert("toContain", [expected], contains(this.#actual, expected));
+  }
+
+  toMatchObject(expected) {
+    return this.#assert("toMatchObject", [expected], matchesObject(this.#actual, expected));
+  }
+
+  toMatch(pattern) {
+    const passed = typeof this.#actual === "string" &&
+      (pattern instanceof RegExp ? pattern.test(this.#actual) : this.#actual.includes(pattern));
+    return this.#assert("toMatch", [String(pattern)], passed);
+  }
+
+  toBeLessThan(expected) {
+    return this.#assert("toBeLessThan", [expected], this.#actual < expected);
+  }
+
+  toBeLessThanOrEqual(expected) {
+    return this.#assert("toBeLessThanOrEqual", [expected], this.#actual <= expected);
+  }
+
+  toBeGreaterThan(expected) {
+    return this.#assert("toBeGreaterThan", [expected], this.#actual > expected);
+  }
+
+  toBeGreaterThanOrEqual(expected) {
+    return this.#assert("toBeGreaterThanOrEqual", [expected], this.#actual >= expected);
+  }
+
+  toBeTruthy() {
+    return this.#assert("toBeTruthy", [], Boolean(this.#actual));
+  }
+
+  toBeFalsy() {
+    return this.#assert("toBeFalsy", [], !this.#actual);
+  }
+
+  toBeDefined() {
+    return this.#assert("toBeDefined", [], this.#actual !== undefined);
+  }
+
+  #assert(matcher, args, matched) {
+    const passed = matched !== this.#negated;
+    const name = this.#name ??
+      `expect(received).${this.#negated ? "not." : ""}${matcher}(${args.map(format).join(", ")})`;
+    recordCheck(name, passed);
+    if (!passed && this.#strict) {
+      throw new ExpectationError(`${name} failed, received ${format(this.#actual)}`);
+    }
+    return passed;
+  }
+}
+
+export function expect(actual, name) {
+  return new Expectation(actual, name, false, false);
+}
+
+// Same matchers, throwing an ExpectationError on failure to end the iteration
+expect.strict = (actual, name) => new Expectation(actual, name, false, true);
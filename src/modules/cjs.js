@@ -0,0 +1,59 @@
+// CommonJS support for packages loaded from node_modules.
+// The loader wraps CommonJS files in an ES module that calls `createRequire`.
+const { core } = Deno;
+
+const moduleCache = new Map();
+
+function dirname(filename) {
+  const index = Math.max(filename.lastIndexOf("/"), filename.lastIndexOf("\\"));
+  return index === -1 ? "." : filename.slice(0, index);
+}
+
+function loadModule({ filename, url, format }) {
+  const cached = moduleCache.get(filename);
+  if (cached) {
+    return cached.exports;
+  }
+
+  const module = { id: filename, filename, exports: {}, loaded: false };
+  moduleCache.set(filename, module);
+  try {
+    const source = core.ops.op_require_read(filename);
+    if (format === "json") {
+      module.exports = JSON.parse(source);
+    } else {
+      const body = source.startsWith("#!") ? `//${source}` : source;
+      const [wrapper, error] = core.evalContext(
+        `(function (exports, require, module, __filename, __dirname, global) {${body}\n})`,
+        url,
+      );
+      if (error) {
+        throw error.thrown;
+      }
+      wrapper.call(
+        module.exports,
+        module.exports,
+        createRequire(filename),
+        module,
+        filename,
+        dirname(filename),
+        globalThis,
+      );
+    }
+  } catch (error) {
+    moduleCache.delete(filename);
+    throw error;
+  }
+  module.loaded = true;
+  return module.exports;
+}
+
+export function createRequire(referrer) {
+  const resolve = (id) => core.ops.op_require_resolve(id, referrer);
+  function require(id) {
+    return loadModule(resolve(id));
+  }
+  require.resolve = (id) => resolve(id).filename;
+  require.cache = moduleCache;
+  return require;
+}
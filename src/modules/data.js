@@ -0,0 +1,30 @@
+// `import { SharedArray } from "v6/data"`
+const sharedArrays = new Map();
+
+function deepFreeze(value) {
+  if (value !== null && typeof value === "object" && !Object.isFrozen(value)) {
+    Object.freeze(value);
+    for (const key of Object.keys(value)) {
+      deepFreeze(value[key]);
+    }
+  }
+  return value;
+}
+
+// Read-only array built once per name and shared by every VU.
+// `factory` is only called the first time a name is seen.
+export function SharedArray(name, factory) {
+  if (!new.target) {
+    throw new TypeError("SharedArray must be called with `new`");
+  }
+  if (!sharedArrays.has(name)) {
+    const data = factory();
+    if (!Array.isArray(data)) {
+      throw new TypeError(`SharedArray "${name}" factory must return an array`);
+    }
+    sharedArrays.set(name, deepFreeze(data));
+  }
+  return sharedArrays.get(name);
+}
+
+export default { SharedArray };
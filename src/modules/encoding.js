@@ -0,0 +1,28 @@
+// `import encoding, { b64encode, b64decode } from "v6/encoding"`
+const { core } = Deno;
+
+function toBytes(input) {
+  if (typeof input === "string") {
+    return core.encode(input);
+  }
+  if (ArrayBuffer.isView(input)) {
+    return new Uint8Array(input.buffer, input.byteOffset, input.byteLength);
+  }
+  return new Uint8Array(input);
+}
+
+// encoding: "std" | "rawstd" | "url" | "rawurl"
+export function b64encode(input, encoding = "std") {
+  return core.ops.op_b64_encode(toBytes(input), encoding);
+}
+
+// Returns an ArrayBuffer, or a string when `format` is "s"
+export function b64decode(input, encoding = "std", format) {
+  const bytes = core.ops.op_b64_decode(input, encoding);
+  if (format === "s") {
+    return core.decode(bytes);
+  }
+  return bytes.buffer;
+}
+
+export default { b64encode, b64decode };
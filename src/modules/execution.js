@@ -0,0 +1,74 @@
+// Context of the running iteration, carried across awaits so concurrent VUs
+// sharing one runtime each see their own
+const { core } = Deno;
+
+export const DEFAULT_SCENARIO = "default";
+
+// Group paths are the names of the enclosing groups joined with "::", e.g. "::login::mfa"
+const GROUP_SEPARATOR = "::";
+const GROUP_DURATION_METRIC = "group_duration";
+
+const vuContext = new core.AsyncVariable();
+
+export function runIteration(vu, iteration, fn) {
+  const previous = vuContext.enter({ vu, iteration, scenario: DEFAULT_SCENARIO });
+  try {
+    return fn();
+  } finally {
+    core.setAsyncContext(previous);
+  }
+}
+
+// Tags identifying the running VU and group, added to the metrics it records
+export function contextTags() {
+  const context = vuContext.get();
+  if (!context) {
+    return {};
+  }
+  return {
+    vu: String(context.vu),
+    scenario: context.scenario,
+    ...(context.group ? { group: context.group } : {}),
+  };
+}
+
+// Runs `fn` as a step of the iteration: metrics recorded inside, including by
+// nested groups and after awaits, are tagged with the group path, and the time
+// until `fn` (or the promise it returns) settles is added to `group_duration`
+export function group(name, fn) {
+  const context = vuContext.get();
+  if (!context) {
+    throw new Error("group() can only be called during an iteration");
+  }
+  if (String(name).includes(GROUP_SEPARATOR)) {
+    throw new Error(`Group name "${name}" can't contain "${GROUP_SEPARATOR}"`);
+  }
+
+  const previous = vuContext.enter({
+    ...context,
+    group: `${context.group ?? ""}${GROUP_SEPARATOR}${name}`,
+  });
+  const tags = contextTags();
+  const start = Date.now();
+  const record = () => core.ops.op_metric_add(GROUP_DURATION_METRIC, Date.now() - start, tags);
+
+  let result;
+  try {
+    result = fn();
+  } catch (error) {
+    record();
+    throw error;
+  } finally {
+    core.setAsyncContext(previous);
+  }
+  if (typeof result?.then === "function") {
+    return Promise.resolve(result).finally(record);
+  }
+  record();
+  return result;
+}
+
+// { vu, iteration, scenario, group }, or undefined outside of an iteration (module evaluation)
+export function currentVu() {
+  return vuContext.get();
+}
@@ -0,0 +1,51 @@
+// `import http, { get, post } from "v6/http"`
+import { normalizeTags } from "ext:v6/modules/metrics.js";
+import { contextTags } from "ext:v6/modules/execution.js";
+
+const { core } = Deno;
+
+// URL built by the `url` template tag, named after its template
+export class TaggedUrl {
+  constructor(url, name) {
+    this.url = url;
+    this.name = name;
+  }
+
+  toString() {
+    return this.url;
+  }
+}
+
+// url`${base}/users/${id}` requests the full URL but is named "${}/users/${}",
+// so requests to dynamic URLs are grouped under one name in metrics
+export function url(strings, ...values) {
+  const href = strings.reduce((acc, part, i) => acc + String(values[i - 1]) + part);
+  return new TaggedUrl(href, strings.join("${}"));
+}
+
+// Resolves to a plain { status, ok, statusText, headers, body, timings } object,
+// not a WHATWG Response. `timings` holds the request phases in milliseconds.
+// `init.tags` and `init.name` are added to the request's metrics, next to the
+// default method, status, url, name, scenario, vu and group tags.
+export function fetch(input, init = {}) {
+  const tagged = input instanceof TaggedUrl;
+  const tags = { ...contextTags(), ...normalizeTags(init.tags) };
+  const name = init.name ?? tags.name ?? (tagged ? input.name : undefined);
+  return core.ops.op_fetch(tagged ? input.url : String(input), { ...init, tags, name });
+}
+
+export function request(method, url, body, params = {}) {
+  const payload = body !== null && typeof body === "object"
+    ? JSON.stringify(body)
+    : body;
+  return fetch(url, { ...params, method, body: payload });
+}
+
+export const get = (url, params) => request("GET", url, undefined, params);
+export const head = (url, params) => request("HEAD", url, undefined, params);
+export const post = (url, body, params) => request("POST", url, body, params);
+export const put = (url, body, params) => request("PUT", url, body, params);
+export const patch = (url, body, params) => request("PATCH", url, body, params);
+export const del = (url, body, params) => request("DELETE", url, body, params);
+
+export default { fetch, request, url, get, head, post, put, patch, del };
@@ -0,0 +1,33 @@
+// `import { defineConfig, sleep, check, expect, group } from "v6"`
+import { check, expect, ExpectationError } from "ext:v6/modules/checks.js";
+import { group } from "ext:v6/modules/execution.js";
+
+const { core } = Deno;
+
+export { check, expect, ExpectationError, group };
+
+function noop() {}
+
+export function defineConfig(config) {
+  if (!config.iteration || typeof config.iteration !== "function") {
+    throw new Error("Invalid iteration definition. Expected a function");
+  }
+  globalThis.currentConfig = {
+    setup: config.setup || noop,
+    teardown: config.teardown || noop,
+    iteration: config.iteration,
+    iterations: config.iterations || 1,
+    vus: config.vus || 1,
+    duration: config.duration || 10,
+    timeout: config.timeout || 30,
+    thresholds: config.thresholds || {},
+    handleSummary: config.handleSummary,
+  };
+  return globalThis.currentConfig;
+}
+
+export async function sleep(delay) {
+  await core.ops.op_set_timeout(delay);
+}
+
+export default { defineConfig, sleep, check, expect, group };
@@ -0,0 +1,71 @@
+// `import { Counter, Gauge, Rate, Trend } from "v6/metrics"`
+import { contextTags } from "ext:v6/modules/execution.js";
+
+const { core } = Deno;
+
+// Tag values are always strings. Also used for request tags in http.js
+export function normalizeTags(tags) {
+  if (tags === undefined || tags === null) {
+    return null;
+  }
+  return Object.fromEntries(
+    Object.entries(tags).map(([key, value]) => [key, String(value)]),
+  );
+}
+
+// Metrics are aggregated by the engine and reported in the end-of-test summary.
+// Creating a metric that already exists with the same type returns a handle to it.
+// Added values are tagged with the VU, scenario and group like built-in metrics.
+class Metric {
+  #name;
+
+  constructor(name, type, isTime = false) {
+    core.ops.op_metric_register(name, type, Boolean(isTime));
+    this.#name = name;
+  }
+
+  get name() {
+    return this.#name;
+  }
+
+  add(value, tags) {
+    core.ops.op_metric_add(this.#name, Number(value), {
+      ...contextTags(),
+      ...normalizeTags(tags),
+    });
+  }
+}
+
+// Sum of all added values
+export class Counter extends Metric {
+  constructor(name) {
+    super(name, "counter");
+  }
+}
+
+// Last added value, with min and max
+export class Gauge extends Metric {
+  constructor(name, isTime) {
+    super(name, "gauge", isTime);
+  }
+}
+
+// Share of added values that are truthy
+export class Rate extends Metric {
+  constructor(name) {
+    super(name, "rate");
+  }
+
+  add(value, tags) {
+    super.add(value ? 1 : 0, tags);
+  }
+}
+
+// Distribution of added values (avg, min, med, percentiles, max)
+export class Trend extends Metric {
+  constructor(name, isTime) {
+    super(name, "trend", isTime);
+  }
+}
+
+export default { Counter, Gauge, Rate, Trend };
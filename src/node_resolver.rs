@@ -0,0 +1,700 @@
+use deno_ast::{MediaType, ParseParams};
+use deno_error::JsErrorBox;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+const IMPORT_CONDITIONS: &[&str] = &["import", "module", "default"];
+const REQUIRE_CONDITIONS: &[&str] = &["require", "node", "default"];
+const EXTENSIONS: &[&str] = &["js", "mjs", "cjs", "json"];
+
+/// Whether a package is being resolved for `import` or `require()`, which decides
+/// the `exports` conditions and whether the `module` field is considered
+#[derive(Clone, Copy, PartialEq)]
+pub enum ResolutionMode {
+    Import,
+    Require,
+}
+
+impl ResolutionMode {
+    fn conditions(self) -> &'static [&'static str] {
+        match self {
+            ResolutionMode::Import => IMPORT_CONDITIONS,
+            ResolutionMode::Require => REQUIRE_CONDITIONS,
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+struct PackageJson {
+    main: Option<String>,
+    module: Option<String>,
+    exports: Option<serde_json::Value>,
+    #[serde(rename = "type")]
+    package_type: Option<String>,
+}
+
+/// What `op_require_resolve` hands back to the CommonJS loader in `ext:v6/modules/cjs.js`
+#[derive(Serialize)]
+pub struct RequireResolution {
+    pub filename: String,
+    pub url: String,
+    pub format: &'static str,
+}
+
+/// Bare specifiers (`lodash`, `@faker-js/faker/locale/en`) are neither paths nor URLs
+pub fn is_bare_specifier(specifier: &str) -> bool {
+    !(specifier.starts_with('/')
+        || specifier.starts_with("./")
+        || specifier.starts_with("../")
+        || specifier == "."
+        || specifier == "..")
+        && deno_core::resolve_url(specifier).is_err()
+}
+
+/// Resolve a bare `package` or `package/subpath` specifier through the
+/// `node_modules` directories above `referrer_dir`
+pub fn resolve_package(
+    specifier: &str,
+    referrer_dir: &Path,
+    mode: ResolutionMode,
+) -> Result<Option<PathBuf>, JsErrorBox> {
+    let (name, subpath) = split_package_specifier(specifier)?;
+
+    for dir in referrer_dir.ancestors() {
+        let package_dir = dir.join("node_modules").join(name);
+        if !package_dir.is_dir() {
+            continue;
+        }
+
+        let manifest_path = package_dir.join("package.json");
+        let manifest = if manifest_path.exists() {
+            read_package_json(&manifest_path)?
+        } else {
+            PackageJson::default()
+        };
+
+        return resolve_package_subpath(name, &package_dir, &manifest, &subpath, mode).map(Some);
+    }
+
+    Ok(None)
+}
+
+/// Resolve the argument of `require()` called from the file `referrer`
+pub fn resolve_require(id: &str, referrer: &Path) -> Result<RequireResolution, JsErrorBox> {
+    let referrer_dir = referrer.parent().unwrap_or(Path::new("/"));
+
+    let path = if is_bare_specifier(id) {
+        if let Some(builtin) = id.strip_prefix("node:").or(NODE_BUILTINS.contains(&id).then_some(id)) {
+            return Err(JsErrorBox::generic(format!(
+                "Node.js built-in module \"{}\" is not available in V6",
+                builtin
+            )));
+        }
+        resolve_package(id, referrer_dir, ResolutionMode::Require)?.ok_or_else(|| {
+            JsErrorBox::generic(format!(
+                "Cannot find package \"{}\" in node_modules (required from '{}')",
+                id,
+                referrer.display()
+            ))
+        })?
+    } else {
+        let candidate = referrer_dir.join(id);
+        resolve_file(&candidate).ok_or_else(|| {
+            JsErrorBox::generic(format!(
+                "Cannot find module \"{}\" (required from '{}')",
+                id,
+                referrer.display()
+            ))
+        })?
+    };
+
+    let format = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => "json",
+        Some("cjs") => "commonjs",
+        Some("mjs") => return Err(require_esm_error(&path)),
+        _ if is_module_package(&path) => return Err(require_esm_error(&path)),
+        _ => "commonjs",
+    };
+
+    let url = deno_core::url::Url::from_file_path(&path)
+        .map_err(|_| JsErrorBox::generic(format!("Invalid module path '{}'", path.display())))?;
+
+    Ok(RequireResolution {
+        filename: path.to_string_lossy().into_owned(),
+        url: url.to_string(),
+        format,
+    })
+}
+
+/// Whether a JS file should be loaded through the CommonJS wrapper: `.cjs` files,
+/// and `.js` files in node_modules that aren't `"type": "module"` and have no ESM syntax
+pub fn is_commonjs(path: &Path, code: &str) -> bool {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("cjs") => true,
+        Some("js") => {
+            is_in_node_modules(path)
+                && !is_module_package(path)
+                && parse_script(path, code).is_some_and(|parsed| parsed.compute_is_script())
+        }
+        _ => false,
+    }
+}
+
+/// ES module facade for a CommonJS file: the default export is `module.exports`
+/// and the statically detectable `exports.*` names become named exports
+pub fn commonjs_module_wrapper(path: &Path, code: &str) -> Result<String, JsErrorBox> {
+    let filename = serde_json::to_string(&path.to_string_lossy())
+        .map_err(|e| JsErrorBox::generic(e.to_string()))?;
+
+    let mut visited = HashSet::from([path.to_path_buf()]);
+    let mut export_names = commonjs_export_names(path, code, &mut visited);
+    export_names.retain(|name| name != "default" && name != "__esModule");
+    export_names.sort();
+    export_names.dedup();
+
+    let mut wrapper = format!(
+        "import {{ createRequire }} from \"ext:v6/modules/cjs.js\";\n\
+         const mod = createRequire({filename})({filename});\n\
+         export default mod;\n"
+    );
+    for (index, name) in export_names.iter().enumerate() {
+        let quoted = serde_json::to_string(name).map_err(|e| JsErrorBox::generic(e.to_string()))?;
+        wrapper.push_str(&format!(
+            "const __v6_export_{index} = mod[{quoted}];\nexport {{ __v6_export_{index} as {quoted} }};\n"
+        ));
+    }
+
+    Ok(wrapper)
+}
+
+// Export names found by deno_ast's cjs-module-lexer port, following `module.exports = require(...)` re-exports
+fn commonjs_export_names(path: &Path, code: &str, visited: &mut HashSet<PathBuf>) -> Vec<String> {
+    let Some(parsed) = parse_script(path, code) else {
+        return Vec::new();
+    };
+    let analysis = parsed.analyze_cjs();
+
+    let mut names = analysis.exports;
+    for reexport in analysis.reexports {
+        let Ok(resolution) = resolve_require(&reexport, path) else {
+            continue;
+        };
+        let reexport_path = PathBuf::from(resolution.filename);
+        if resolution.format != "commonjs" || !visited.insert(reexport_path.clone()) {
+            continue;
+        }
+        if let Ok(reexport_code) = std::fs::read_to_string(&reexport_path) {
+            names.extend(commonjs_export_names(&reexport_path, &reexport_code, visited));
+        }
+    }
+    names
+}
+
+fn parse_script(path: &Path, code: &str) -> Option<deno_ast::ParsedSource> {
+    let specifier = deno_core::url::Url::from_file_path(path).ok()?;
+    deno_ast::parse_program(ParseParams {
+        specifier,
+        text: code.into(),
+        media_type: MediaType::Cjs,
+        capture_tokens: false,
+        scope_analysis: false,
+        maybe_syntax: None,
+    })
+    .ok()
+}
+
+fn split_package_specifier(specifier: &str) -> Result<(&str, String), JsErrorBox> {
+    let name_end = if specifier.starts_with('@') {
+        specifier
+            .match_indices('/')
+            .nth(1)
+            .map(|(index, _)| index)
+            .unwrap_or(specifier.len())
+    } else {
+        specifier.find('/').unwrap_or(specifier.len())
+    };
+
+    let name = &specifier[..name_end];
+    if name.is_empty() || (name.starts_with('@') && !name.contains('/')) {
+        return Err(JsErrorBox::type_error(format!("Invalid package specifier \"{}\"", specifier)));
+    }
+
+    Ok((name, format!(".{}", &specifier[name_end..])))
+}
+
+fn resolve_package_subpath(
+    name: &str,
+    package_dir: &Path,
+    manifest: &PackageJson,
+    subpath: &str,
+    mode: ResolutionMode,
+) -> Result<PathBuf, JsErrorBox> {
+    if let Some(exports) = &manifest.exports {
+        let target = resolve_exports(exports, subpath, mode.conditions())
+            .map_err(|target| {
+                JsErrorBox::generic(format!(
+                    "Invalid target \"{}\" for subpath \"{}\" in the exports of \"{}\"",
+                    target, subpath, name
+                ))
+            })?
+            .ok_or_else(|| {
+                JsErrorBox::generic(format!(
+                    "Package subpath \"{}\" is not exported by \"{}\"",
+                    subpath, name
+                ))
+            })?;
+        let path = package_dir.join(target.trim_start_matches("./"));
+        return if path.is_file() {
+            Ok(path)
+        } else {
+            Err(JsErrorBox::generic(format!(
+                "Package \"{}\" exports '{}', which does not exist",
+                name,
+                path.display()
+            )))
+        };
+    }
+
+    let entry = if subpath == "." {
+        let entry = match mode {
+            ResolutionMode::Import => manifest.module.as_ref().or(manifest.main.as_ref()),
+            ResolutionMode::Require => manifest.main.as_ref(),
+        };
+        entry.map(String::as_str).unwrap_or("index.js")
+    } else {
+        &subpath[2..]
+    };
+
+    resolve_file(&package_dir.join(entry.trim_start_matches("./"))).ok_or_else(|| {
+        JsErrorBox::generic(format!(
+            "Cannot find \"{}\" in package \"{}\" ('{}')",
+            subpath,
+            name,
+            package_dir.display()
+        ))
+    })
+}
+
+// Implements the `exports` field: exact subpaths, `*` patterns and nested conditions.
+// A target that would leave the package is an error carrying the offending target.
+fn resolve_exports(
+    exports: &serde_json::Value,
+    subpath: &str,
+    conditions: &[&str],
+) -> Result<Option<String>, String> {
+    let has_subpath_keys = exports
+        .as_object()
+        .is_some_and(|map| map.keys().any(|key| key.starts_with('.')));
+
+    if !has_subpath_keys {
+        return if subpath == "." {
+            resolve_export_target(exports, None, conditions)
+        } else {
+            Ok(None)
+        };
+    }
+
+    let Some(map) = exports.as_object() else {
+        return Ok(None);
+    };
+    if let Some(target) = map.get(subpath) {
+        return resolve_export_target(target, None, conditions);
+    }
+
+    map.iter()
+        .filter_map(|(key, target)| {
+            let (prefix, suffix) = key.split_once('*')?;
+            let matched = subpath.strip_prefix(prefix)?.strip_suffix(suffix)?;
+            Some((prefix.len(), target, matched))
+        })
+        .max_by_key(|(prefix_len, _, _)| *prefix_len)
+        .map_or(Ok(None), |(_, target, matched)| resolve_export_target(target, Some(matched), conditions))
+}
+
+fn resolve_export_target(
+    target: &serde_json::Value,
+    pattern_match: Option<&str>,
+    conditions: &[&str],
+) -> Result<Option<String>, String> {
+    match target {
+        serde_json::Value::String(target) => {
+            // Like Node, targets are relative to the package and can't climb out of it,
+            // neither by themselves nor through what a `*` pattern matched
+            let is_valid = target
+                .strip_prefix("./")
+                .is_some_and(|path| !path.split(['/', '\\']).any(is_invalid_segment))
+                && pattern_match.is_none_or(|matched| !matched.split(['/', '\\']).any(is_invalid_segment));
+            if !is_valid {
+                return Err(match pattern_match {
+                    Some(matched) => target.replace('*', matched),
+                    None => target.clone(),
+                });
+            }
+            Ok(Some(match pattern_match {
+                Some(matched) => target.replace('*', matched),
+                None => target.clone(),
+            }))
+        }
+        // The first target that resolves wins; invalid ones are only reported when none does
+        serde_json::Value::Array(targets) => {
+            let mut invalid = None;
+            for target in targets {
+                match resolve_export_target(target, pattern_match, conditions) {
+                    Ok(Some(resolved)) => return Ok(Some(resolved)),
+                    Ok(None) => {}
+                    Err(target) => invalid = Some(target),
+                }
+            }
+            invalid.map_or(Ok(None), Err)
+        }
+        serde_json::Value::Object(map) => {
+            for target in conditions.iter().filter_map(|condition| map.get(*condition)) {
+                if let Some(resolved) = resolve_export_target(target, pattern_match, conditions)? {
+                    return Ok(Some(resolved));
+                }
+            }
+            Ok(None)
+        }
+        _ => Ok(None),
+    }
+}
+
+// "", ".", ".." and "node_modules" path segments, also when percent-encoded
+fn is_invalid_segment(segment: &str) -> bool {
+    let decoded = segment
+        .to_ascii_lowercase()
+        .replace("%2e", ".")
+        .replace("%5f", "_")
+        .replace("%6e", "n")
+        .replace("%6f", "o")
+        .replace("%64", "d")
+        .replace("%65", "e")
+        .replace("%6d", "m")
+        .replace("%75", "u")
+        .replace("%6c", "l")
+        .replace("%73", "s");
+    matches!(decoded.as_str(), "" | "." | ".." | "node_modules")
+}
+
+// Node's file and directory lookup: exact file, known extensions, then index files
+fn resolve_file(path: &Path) -> Option<PathBuf> {
+    if path.is_file() {
+        return Some(path.to_path_buf());
+    }
+
+    let file_name = path.file_name()?.to_string_lossy();
+    EXTENSIONS
+        .iter()
+        .map(|ext| path.with_file_name(format!("{}.{}", file_name, ext)))
+        .chain(EXTENSIONS.iter().map(|ext| path.join(format!("index.{}", ext))))
+        .find(|candidate| candidate.is_file())
+}
+
+fn read_package_json(path: &Path) -> Result<PackageJson, JsErrorBox> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| JsErrorBox::generic(format!("Failed to read '{}': {}", path.display(), e)))?;
+    serde_json::from_str(&content)
+        .map_err(|e| JsErrorBox::generic(format!("Invalid package.json '{}': {}", path.display(), e)))
+}
+
+fn is_in_node_modules(path: &Path) -> bool {
+    path.components().any(|component| component.as_os_str() == "node_modules")
+}
+
+// `"type": "module"` in the nearest package.json makes `.js` files ES modules
+fn is_module_package(path: &Path) -> bool {
+    path.ancestors()
+        .skip(1)
+        .map(|dir| dir.join("package.json"))
+        .find(|manifest_path| manifest_path.exists())
+        .and_then(|manifest_path| read_package_json(&manifest_path).ok())
+        .is_some_and(|manifest| manifest.package_type.as_deref() == Some("module"))
+}
+
+fn require_esm_error(path: &Path) -> JsErrorBox {
+    JsErrorBox::generic(format!(
+        "require() of ES module '{}' is not supported, use import instead",
+        path.display()
+    ))
+}
+
+const NODE_BUILTINS: &[&str] = &[
+    "assert", "buffer", "child_process", "crypto", "events", "fs", "http", "https", "net", "os",
+    "path", "process", "stream", "string_decoder", "url", "util", "zlib",
+];
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `<project>/node_modules/<name>` with the given package.json and files
+    fn write_package(project: &Path, name: &str, manifest: &str, files: &[&str]) -> PathBuf {
+        let package_dir = project.join("node_modules").join(name);
+        std::fs::create_dir_all(&package_dir).unwrap();
+        std::fs::write(package_dir.join("package.json"), manifest).unwrap();
+        for file in files {
+            let path = package_dir.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+        package_dir
+    }
+
+    // Resolved from a module in `<project>/tests`, as a test file would
+    fn resolve(project: &Path, specifier: &str, mode: ResolutionMode) -> Result<PathBuf, String> {
+        resolve_package(specifier, &project.join("tests"), mode)
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| "not found".to_string())
+    }
+
+    #[test]
+    fn conditional_exports_follow_the_resolution_mode() {
+        let project = tempfile::tempdir().unwrap();
+        let package_dir = write_package(
+            project.path(),
+            "dual",
+            r#"{ "exports": { ".": { "import": "./esm/index.mjs", "require": "./cjs/index.cjs" } } }"#,
+            &["esm/index.mjs", "cjs/index.cjs"],
+        );
+
+        assert_eq!(
+            resolve(project.path(), "dual", ResolutionMode::Import),
+            Ok(package_dir.join("esm/index.mjs"))
+        );
+        assert_eq!(
+            resolve(project.path(), "dual", ResolutionMode::Require),
+            Ok(package_dir.join("cjs/index.cjs"))
+        );
+    }
+
+    #[test]
+    fn nested_conditions_and_target_arrays() {
+        let project = tempfile::tempdir().unwrap();
+        let package_dir = write_package(
+            project.path(),
+            "nested",
+            r#"{ "exports": {
+                "browser": "./browser.js",
+                "node": { "require": "./node.cjs" },
+                "default": ["../outside.js", "./fallback.js"]
+            } }"#,
+            &["browser.js", "node.cjs", "fallback.js"],
+        );
+
+        // "browser" is never a condition; "node" only applies to require()
+        assert_eq!(
+            resolve(project.path(), "nested", ResolutionMode::Require),
+            Ok(package_dir.join("node.cjs"))
+        );
+        // Invalid targets of an array fall through to the next one
+        assert_eq!(
+            resolve(project.path(), "nested", ResolutionMode::Import),
+            Ok(package_dir.join("fallback.js"))
+        );
+    }
+
+    #[test]
+    fn subpath_exports_and_patterns() {
+        let project = tempfile::tempdir().unwrap();
+        let package_dir = write_package(
+            project.path(),
+            "@scope/kit",
+            r#"{ "exports": {
+                ".": "./index.js",
+                "./utils": "./lib/utils.js",
+                "./locale/*": "./dist/locale/*.js",
+                "./locale/en/*": "./dist/english/*.js"
+            } }"#,
+            &[
+                "index.js",
+                "lib/utils.js",
+                "dist/locale/de.js",
+                "dist/english/us.js",
+            ],
+        );
+
+        assert_eq!(
+            resolve(project.path(), "@scope/kit", ResolutionMode::Import),
+            Ok(package_dir.join("index.js"))
+        );
+        assert_eq!(
+            resolve(project.path(), "@scope/kit/utils", ResolutionMode::Import),
+            Ok(package_dir.join("lib/utils.js"))
+        );
+        assert_eq!(
+            resolve(
+                project.path(),
+                "@scope/kit/locale/de",
+                ResolutionMode::Import
+            ),
+            Ok(package_dir.join("dist/locale/de.js"))
+        );
+        // The longest pattern prefix wins
+        assert_eq!(
+            resolve(
+                project.path(),
+                "@scope/kit/locale/en/us",
+                ResolutionMode::Import
+            ),
+            Ok(package_dir.join("dist/english/us.js"))
+        );
+        let error = resolve(
+            project.path(),
+            "@scope/kit/lib/utils.js",
+            ResolutionMode::Import,
+        )
+        .unwrap_err();
+        assert!(error.contains("is not exported"), "{}", error);
+    }
+
+    #[test]
+    fn export_targets_cannot_leave_the_package() {
+        let project = tempfile::tempdir().unwrap();
+        std::fs::write(project.path().join("secret.js"), "").unwrap();
+        write_package(
+            project.path(),
+            "escape",
+            r#"{ "exports": {
+                "./parent": "../../secret.js",
+                "./dotdot": "./lib/../../../secret.js",
+                "./encoded": "./%2E%2E/%2e%2e/secret.js",
+                "./bare": "other-package/index.js",
+                "./nested": "./node_modules/dep/index.js",
+                "./files/*": "./files/*.js"
+            } }"#,
+            &["files/a.js", "node_modules/dep/index.js"],
+        );
+
+        for subpath in ["parent", "dotdot", "encoded", "bare", "nested"] {
+            let error = resolve(
+                project.path(),
+                &format!("escape/{}", subpath),
+                ResolutionMode::Import,
+            )
+            .unwrap_err();
+            assert!(error.contains("Invalid target"), "{}: {}", subpath, error);
+        }
+        // What a pattern matched can't climb out either
+        let error = resolve(
+            project.path(),
+            "escape/files/../../../secret",
+            ResolutionMode::Import,
+        )
+        .unwrap_err();
+        assert!(error.contains("Invalid target"), "{}", error);
+        let error = resolve(project.path(), "escape/files//a", ResolutionMode::Import).unwrap_err();
+        assert!(error.contains("Invalid target"), "{}", error);
+        assert!(resolve(project.path(), "escape/files/a", ResolutionMode::Import).is_ok());
+    }
+
+    #[test]
+    fn module_and_main_fallbacks() {
+        let project = tempfile::tempdir().unwrap();
+        let package_dir = write_package(
+            project.path(),
+            "legacy",
+            r#"{ "main": "./dist/index", "module": "./dist/index.mjs" }"#,
+            &["dist/index.js", "dist/index.mjs", "extra/index.js"],
+        );
+        assert_eq!(
+            resolve(project.path(), "legacy", ResolutionMode::Import),
+            Ok(package_dir.join("dist/index.mjs"))
+        );
+        // require() ignores `module`; `main` is completed with an extension
+        assert_eq!(
+            resolve(project.path(), "legacy", ResolutionMode::Require),
+            Ok(package_dir.join("dist/index.js"))
+        );
+        // Subpaths of packages without `exports` are plain files and directories
+        assert_eq!(
+            resolve(project.path(), "legacy/extra", ResolutionMode::Import),
+            Ok(package_dir.join("extra/index.js"))
+        );
+
+        let package_dir = write_package(project.path(), "bare-index", "{}", &["index.js"]);
+        assert_eq!(
+            resolve(project.path(), "bare-index", ResolutionMode::Import),
+            Ok(package_dir.join("index.js"))
+        );
+
+        assert_eq!(
+            resolve(project.path(), "missing", ResolutionMode::Import),
+            Err("not found".to_string())
+        );
+    }
+
+    #[test]
+    fn commonjs_detection() {
+        let project = tempfile::tempdir().unwrap();
+        let package_dir = write_package(project.path(), "cjs", "{}", &[]);
+        let esm_package_dir = write_package(project.path(), "esm", r#"{ "type": "module" }"#, &[]);
+
+        assert!(is_commonjs(
+            &project.path().join("helpers.cjs"),
+            "module.exports = 1;"
+        ));
+        assert!(is_commonjs(
+            &package_dir.join("index.js"),
+            "module.exports = 1;"
+        ));
+        // ESM syntax, "type": "module" and files outside node_modules aren't CommonJS
+        assert!(!is_commonjs(
+            &package_dir.join("index.js"),
+            "export default 1;"
+        ));
+        assert!(!is_commonjs(
+            &esm_package_dir.join("index.js"),
+            "module.exports = 1;"
+        ));
+        assert!(!is_commonjs(
+            &project.path().join("helpers.js"),
+            "module.exports = 1;"
+        ));
+    }
+
+    #[test]
+    fn commonjs_wrapper_exports_module_exports() {
+        let project = tempfile::tempdir().unwrap();
+        let package_dir = write_package(project.path(), "cjs", "{}", &[]);
+        std::fs::write(package_dir.join("base.js"), "exports.base = 1;").unwrap();
+        let path = package_dir.join("index.js");
+        let code = r#"
+            exports.b = 1;
+            module.exports.a = 2;
+            exports["with-dash"] = 3;
+            exports.default = 4;
+            Object.defineProperty(exports, "__esModule", { value: true });
+        "#;
+
+        let wrapper = commonjs_module_wrapper(&path, code).unwrap();
+        let filename = serde_json::to_string(&path.to_string_lossy()).unwrap();
+        assert!(
+            wrapper.starts_with(&format!(
+                "import {{ createRequire }} from \"ext:v6/modules/cjs.js\";\n\
+                 const mod = createRequire({filename})({filename});\n\
+                 export default mod;\n"
+            )),
+            "{}",
+            wrapper
+        );
+        // Named exports are sorted; `default` and `__esModule` are left to the default export
+        assert!(wrapper.ends_with(
+            "const __v6_export_0 = mod[\"a\"];\nexport { __v6_export_0 as \"a\" };\n\
+             const __v6_export_1 = mod[\"b\"];\nexport { __v6_export_1 as \"b\" };\n\
+             const __v6_export_2 = mod[\"with-dash\"];\nexport { __v6_export_2 as \"with-dash\" };\n"
+        ));
+
+        // `module.exports = require(...)` re-exports the required module's names
+        let wrapper =
+            commonjs_module_wrapper(&path, r#"module.exports = require("./base.js");"#).unwrap();
+        assert!(
+            wrapper.contains("export { __v6_export_0 as \"base\" };"),
+            "{}",
+            wrapper
+        );
+    }
+}
@@ -0,0 +1,452 @@
+use anyhow::Result;
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::metrics::{MetricKind, Tags};
+use crate::outputs::{BlockingRuntime, Output, RunInfo, Sample};
+use crate::protobuf::{write_bytes_field, write_double_field, write_fixed64_field, write_varint_field};
+
+pub const DEFAULT_PUSH_INTERVAL: Duration = Duration::from_secs(10);
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(10);
+const HTTP_PATH: &str = "/v1/metrics";
+const GRPC_PATH: &str = "/opentelemetry.proto.collector.metrics.v1.MetricsService/Export";
+// Bucket bounds of trend histograms, the OpenTelemetry SDK defaults (milliseconds for durations)
+const HISTOGRAM_BOUNDS: &[f64] = &[
+    0.0, 5.0, 10.0, 25.0, 50.0, 75.0, 100.0, 250.0, 500.0, 750.0, 1000.0, 2500.0, 5000.0, 7500.0, 10000.0,
+];
+const AGGREGATION_TEMPORALITY_CUMULATIVE: u64 = 2;
+
+/// Transport of `--out otlp=<endpoint>`
+#[derive(Clone, Copy, Debug)]
+pub enum OtlpProtocol {
+    /// `protocol=http`, POST to `<endpoint>/v1/metrics`
+    HttpProtobuf,
+    /// `protocol=grpc`, unary `MetricsService/Export` over HTTP/2 without TLS negotiation
+    Grpc,
+}
+
+impl OtlpProtocol {
+    pub fn parse(protocol: &str) -> Option<Self> {
+        match protocol {
+            "http" | "http/protobuf" => Some(Self::HttpProtobuf),
+            "grpc" => Some(Self::Grpc),
+            _ => None,
+        }
+    }
+}
+
+// Cumulative value of one metric and tag set since the start of the test
+enum Series {
+    Sum(f64),
+    Gauge(f64),
+    Rate {
+        passes: u64,
+        total: u64,
+    },
+    Histogram {
+        count: u64,
+        sum: f64,
+        min: f64,
+        max: f64,
+        bucket_counts: Vec<u64>,
+    },
+}
+
+impl Series {
+    fn new(kind: MetricKind) -> Self {
+        match kind {
+            MetricKind::Counter => Self::Sum(0.0),
+            MetricKind::Gauge => Self::Gauge(0.0),
+            MetricKind::Rate => Self::Rate { passes: 0, total: 0 },
+            MetricKind::Trend => Self::Histogram {
+                count: 0,
+                sum: 0.0,
+                min: f64::INFINITY,
+                max: f64::NEG_INFINITY,
+                bucket_counts: vec![0; HISTOGRAM_BOUNDS.len() + 1],
+            },
+        }
+    }
+
+    fn add(&mut self, value: f64) {
+        match self {
+            Self::Sum(sum) => *sum += value,
+            Self::Gauge(last) => *last = value,
+            Self::Rate { passes, total } => {
+                *total += 1;
+                if value != 0.0 {
+                    *passes += 1;
+                }
+            }
+            Self::Histogram {
+                count,
+                sum,
+                min,
+                max,
+                bucket_counts,
+            } => {
+                *count += 1;
+                *sum += value;
+                *min = min.min(value);
+                *max = max.max(value);
+                // Buckets are upper-inclusive; the last one has no upper bound
+                let bucket = HISTOGRAM_BOUNDS.partition_point(|bound| *bound < value);
+                bucket_counts[bucket] += 1;
+            }
+        }
+    }
+}
+
+/// `--out otlp=<endpoint>`: exports counters as monotonic sums, gauges and rates as gauges
+/// and trends as histograms to an OpenTelemetry collector, every push interval and at the end
+pub struct OtlpOutput {
+    url: String,
+    protocol: OtlpProtocol,
+    push_interval: Duration,
+    resource: Vec<u8>,
+    series: BTreeMap<(String, Tags), Series>,
+    start_time_nanos: u64,
+    last_push: Instant,
+    client: reqwest::Client,
+    runtime: BlockingRuntime,
+}
+
+impl OtlpOutput {
+    pub fn new(endpoint: &str, protocol: OtlpProtocol, push_interval: Duration, run: &RunInfo) -> Result<Self> {
+        let endpoint = endpoint.trim_end_matches('/');
+        let url = match protocol {
+            OtlpProtocol::HttpProtobuf if endpoint.ends_with(HTTP_PATH) => endpoint.to_string(),
+            OtlpProtocol::HttpProtobuf => format!("{}{}", endpoint, HTTP_PATH),
+            OtlpProtocol::Grpc => format!("{}{}", endpoint, GRPC_PATH),
+        };
+        reqwest::Url::parse(&url).map_err(|e| anyhow::anyhow!("Invalid OTLP endpoint '{}': {}", endpoint, e))?;
+        let client = match protocol {
+            OtlpProtocol::HttpProtobuf => reqwest::Client::new(),
+            OtlpProtocol::Grpc => reqwest::Client::builder().http2_prior_knowledge().build()?,
+        };
+
+        // Resource { repeated KeyValue attributes = 1; }
+        let mut resource = Vec::new();
+        for (key, value) in [
+            ("service.name", "v6"),
+            ("service.version", env!("CARGO_PKG_VERSION")),
+            ("v6.test.file", run.main_module.as_str()),
+            ("v6.run.id", run.id.as_str()),
+        ] {
+            write_bytes_field(&mut resource, 1, &encode_attribute(key, value));
+        }
+
+        Ok(Self {
+            url,
+            protocol,
+            push_interval,
+            resource,
+            series: BTreeMap::new(),
+            start_time_nanos: unix_nanos(),
+            last_push: Instant::now(),
+            client,
+            runtime: BlockingRuntime::default(),
+        })
+    }
+
+    // Failed exports are reported and retried with the next one, which carries the same series
+    fn push(&mut self) -> Result<()> {
+        self.last_push = Instant::now();
+        let message = self.encode_request(unix_nanos());
+        let request = match self.protocol {
+            OtlpProtocol::HttpProtobuf => self
+                .client
+                .post(&self.url)
+                .header("Content-Type", "application/x-protobuf")
+                .body(message),
+            OtlpProtocol::Grpc => {
+                // Length-prefixed message: uncompressed flag, then the big-endian length
+                let mut body = vec![0];
+                body.extend_from_slice(&(message.len() as u32).to_be_bytes());
+                body.extend_from_slice(&message);
+                self.client
+                    .post(&self.url)
+                    .header("Content-Type", "application/grpc")
+                    .header("TE", "trailers")
+                    .body(body)
+            }
+        }
+        .header("User-Agent", concat!("v6/", env!("CARGO_PKG_VERSION")))
+        .timeout(EXPORT_TIMEOUT);
+
+        match self.runtime.send(request)? {
+            // gRPC errors without a response message come back in the headers
+            Ok(response) if response.status().is_success() => {
+                if let Some(status) = response.headers().get("grpc-status")
+                    && status != "0"
+                {
+                    let message = response.headers().get("grpc-message").and_then(|message| message.to_str().ok());
+                    eprintln!(
+                        "⚠️  OTLP export to {} failed: gRPC status {} {}",
+                        self.url,
+                        status.to_str().unwrap_or_default(),
+                        message.unwrap_or_default()
+                    );
+                }
+            }
+            Ok(response) => eprintln!("⚠️  OTLP export to {} failed: HTTP {}", self.url, response.status()),
+            Err(e) => eprintln!("⚠️  OTLP export to {} failed: {}", self.url, e),
+        }
+        Ok(())
+    }
+
+    // ExportMetricsServiceRequest { repeated ResourceMetrics resource_metrics = 1; }
+    fn encode_request(&self, time_nanos: u64) -> Vec<u8> {
+        // Data points per metric name, in one Metric message each
+        let mut metrics: BTreeMap<&str, (&Series, Vec<u8>)> = BTreeMap::new();
+        for ((name, tags), series) in &self.series {
+            let (_, data_points) = metrics.entry(name.as_str()).or_insert_with(|| (series, Vec::new()));
+            let data_point = encode_data_point(series, tags, self.start_time_nanos, time_nanos);
+            write_bytes_field(data_points, 1, &data_point);
+        }
+
+        // ScopeMetrics { InstrumentationScope scope = 1; repeated Metric metrics = 2; }
+        let mut scope = Vec::new();
+        write_bytes_field(&mut scope, 1, b"v6");
+        write_bytes_field(&mut scope, 2, env!("CARGO_PKG_VERSION").as_bytes());
+        let mut scope_metrics = Vec::new();
+        write_bytes_field(&mut scope_metrics, 1, &scope);
+        for (name, (series, data_points)) in metrics {
+            write_bytes_field(&mut scope_metrics, 2, &encode_metric(name, series, data_points));
+        }
+
+        // ResourceMetrics { Resource resource = 1; repeated ScopeMetrics scope_metrics = 2; }
+        let mut resource_metrics = Vec::new();
+        write_bytes_field(&mut resource_metrics, 1, &self.resource);
+        write_bytes_field(&mut resource_metrics, 2, &scope_metrics);
+
+        let mut request = Vec::new();
+        write_bytes_field(&mut request, 1, &resource_metrics);
+        request
+    }
+}
+
+impl Output for OtlpOutput {
+    fn write(&mut self, samples: &[Sample]) -> Result<()> {
+        for sample in samples {
+            self.series
+                .entry((sample.metric.clone(), sample.series_tags()))
+                .or_insert_with(|| Series::new(sample.kind))
+                .add(sample.value);
+        }
+        if self.last_push.elapsed() >= self.push_interval {
+            self.push()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.push()
+    }
+}
+
+// Metric { string name = 1; Gauge gauge = 5; Sum sum = 7; Histogram histogram = 9; }
+fn encode_metric(name: &str, series: &Series, mut data: Vec<u8>) -> Vec<u8> {
+    let mut metric = Vec::new();
+    write_bytes_field(&mut metric, 1, name.as_bytes());
+    match series {
+        Series::Sum(_) => {
+            // Sum { data_points = 1; aggregation_temporality = 2; bool is_monotonic = 3; }
+            write_varint_field(&mut data, 2, AGGREGATION_TEMPORALITY_CUMULATIVE);
+            write_varint_field(&mut data, 3, 1);
+            write_bytes_field(&mut metric, 7, &data);
+        }
+        Series::Gauge(_) | Series::Rate { .. } => write_bytes_field(&mut metric, 5, &data),
+        Series::Histogram { .. } => {
+            write_varint_field(&mut data, 2, AGGREGATION_TEMPORALITY_CUMULATIVE);
+            write_bytes_field(&mut metric, 9, &data);
+        }
+    }
+    metric
+}
+
+// NumberDataPoint, or HistogramDataPoint for trends
+fn encode_data_point(series: &Series, tags: &Tags, start_time_nanos: u64, time_nanos: u64) -> Vec<u8> {
+    let mut point = Vec::new();
+    write_fixed64_field(&mut point, 2, start_time_nanos);
+    write_fixed64_field(&mut point, 3, time_nanos);
+    let value = match series {
+        Series::Sum(sum) => *sum,
+        Series::Gauge(last) => *last,
+        Series::Rate { passes, total } => *passes as f64 / (*total).max(1) as f64,
+        Series::Histogram {
+            count,
+            sum,
+            min,
+            max,
+            bucket_counts,
+        } => {
+            write_fixed64_field(&mut point, 4, *count);
+            write_double_field(&mut point, 5, *sum);
+            // Packed repeated fixed64 bucket_counts = 6 and double explicit_bounds = 7
+            let counts: Vec<u8> = bucket_counts.iter().flat_map(|count| count.to_le_bytes()).collect();
+            write_bytes_field(&mut point, 6, &counts);
+            let bounds: Vec<u8> = HISTOGRAM_BOUNDS.iter().flat_map(|bound| bound.to_le_bytes()).collect();
+            write_bytes_field(&mut point, 7, &bounds);
+            for (key, value) in tags {
+                write_bytes_field(&mut point, 9, &encode_attribute(key, value));
+            }
+            write_double_field(&mut point, 11, *min);
+            write_double_field(&mut point, 12, *max);
+            return point;
+        }
+    };
+    // double as_double = 4; repeated KeyValue attributes = 7;
+    write_double_field(&mut point, 4, value);
+    for (key, value) in tags {
+        write_bytes_field(&mut point, 7, &encode_attribute(key, value));
+    }
+    point
+}
+
+// KeyValue { string key = 1; AnyValue value = 2; } with AnyValue { string string_value = 1; }
+fn encode_attribute(key: &str, value: &str) -> Vec<u8> {
+    let mut any_value = Vec::new();
+    write_bytes_field(&mut any_value, 1, value.as_bytes());
+    let mut attribute = Vec::new();
+    write_bytes_field(&mut attribute, 1, key.as_bytes());
+    write_bytes_field(&mut attribute, 2, &any_value);
+    attribute
+}
+
+fn unix_nanos() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Top-level fields of a message as (field number, wire type, payload)
+    fn decode_fields(mut bytes: &[u8]) -> Vec<(u64, u8, Vec<u8>)> {
+        fn read_varint(bytes: &mut &[u8]) -> u64 {
+            let mut value = 0;
+            for shift in (0..64).step_by(7) {
+                let byte = bytes[0];
+                *bytes = &bytes[1..];
+                value |= u64::from(byte & 0x7f) << shift;
+                if byte < 0x80 {
+                    break;
+                }
+            }
+            value
+        }
+
+        let mut fields = Vec::new();
+        while !bytes.is_empty() {
+            let tag = read_varint(&mut bytes);
+            let wire_type = (tag & 7) as u8;
+            let length = match wire_type {
+                1 => 8,
+                2 => read_varint(&mut bytes) as usize,
+                _ => panic!("unexpected wire type {}", wire_type),
+            };
+            fields.push((tag >> 3, wire_type, bytes[..length].to_vec()));
+            bytes = &bytes[length..];
+        }
+        fields
+    }
+
+    fn fixed64(payload: &[u8]) -> u64 {
+        u64::from_le_bytes(payload.try_into().unwrap())
+    }
+
+    fn double(payload: &[u8]) -> f64 {
+        f64::from_bits(fixed64(payload))
+    }
+
+    #[test]
+    fn encodes_number_data_points() {
+        let tags = Tags::from([("status".to_string(), "200".to_string())]);
+        #[rustfmt::skip]
+        let expected: &[u8] = &[
+            // start_time_unix_nano = 2, time_unix_nano = 3
+            0x11, 1, 0, 0, 0, 0, 0, 0, 0,
+            0x19, 2, 0, 0, 0, 0, 0, 0, 0,
+            // as_double = 4: 3.0
+            0x21, 0, 0, 0, 0, 0, 0, 0x08, 0x40,
+            // attributes = 7: KeyValue { key: "status", value: AnyValue { string_value: "200" } }
+            0x3a, 15,
+                0x0a, 6, b's', b't', b'a', b't', b'u', b's',
+                0x12, 5, 0x0a, 3, b'2', b'0', b'0',
+        ];
+        assert_eq!(encode_data_point(&Series::Sum(3.0), &tags, 1, 2), expected);
+    }
+
+    #[test]
+    fn encodes_rates_as_the_share_of_passes() {
+        let point = |series: Series| {
+            double(&decode_fields(&encode_data_point(&series, &Tags::new(), 0, 0))[2].2)
+        };
+        assert_eq!(
+            point(Series::Rate {
+                passes: 1,
+                total: 4
+            }),
+            0.25
+        );
+        assert_eq!(
+            point(Series::Rate {
+                passes: 0,
+                total: 0
+            }),
+            0.0
+        );
+        assert_eq!(point(Series::Gauge(7.5)), 7.5);
+    }
+
+    #[test]
+    fn encodes_histogram_data_points() {
+        let mut series = Series::new(MetricKind::Trend);
+        for value in [3.0, 5.0, 60.0, 20_000.0] {
+            series.add(value);
+        }
+        let tags = Tags::from([("name".to_string(), "login".to_string())]);
+        let fields = decode_fields(&encode_data_point(&series, &tags, 10, 20));
+
+        let layout: Vec<(u64, u8)> = fields
+            .iter()
+            .map(|(field, wire_type, _)| (*field, *wire_type))
+            .collect();
+        assert_eq!(
+            layout,
+            [
+                (2, 1),
+                (3, 1),
+                (4, 1),
+                (5, 1),
+                (6, 2),
+                (7, 2),
+                (9, 2),
+                (11, 1),
+                (12, 1)
+            ]
+        );
+
+        assert_eq!(fixed64(&fields[0].2), 10);
+        assert_eq!(fixed64(&fields[1].2), 20);
+        assert_eq!(fixed64(&fields[2].2), 4);
+        assert_eq!(double(&fields[3].2), 20_068.0);
+
+        // Upper-inclusive buckets, one more than there are bounds
+        let bucket_counts: Vec<u64> = fields[4].2.chunks(8).map(fixed64).collect();
+        let mut expected_counts = vec![0; HISTOGRAM_BOUNDS.len() + 1];
+        expected_counts[1] = 2;
+        expected_counts[5] = 1;
+        expected_counts[HISTOGRAM_BOUNDS.len()] = 1;
+        assert_eq!(bucket_counts, expected_counts);
+        let bounds: Vec<f64> = fields[5].2.chunks(8).map(double).collect();
+        assert_eq!(bounds, HISTOGRAM_BOUNDS);
+
+        assert_eq!(fields[6].2, encode_attribute("name", "login"));
+        assert_eq!(double(&fields[7].2), 3.0);
+        assert_eq!(double(&fields[8].2), 20_000.0);
+    }
+}
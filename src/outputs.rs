@@ -0,0 +1,725 @@
+use anyhow::Result;
+use flate2::{Compression, write::GzEncoder};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufWriter, Write},
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+        mpsc,
+    },
+    thread::JoinHandle,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::cli::parse_duration;
+use crate::metrics::{MetricKind, Tags};
+use crate::influxdb::{self, InfluxDbOutput};
+use crate::otlp::{self, OtlpOutput, OtlpProtocol};
+use crate::prometheus::{self, RemoteWriteOutput, ScrapeOutput};
+use crate::statsd::{self, StatsdOutput, StatsdTagFormat};
+
+// Tags with their own CSV column; any other tag goes to `extra_tags`
+const CSV_TAG_COLUMNS: &[&str] = &["method", "status", "url", "name", "group", "check", "scenario", "vu"];
+// Left out of aggregated series: a series per VU would multiply every series by the number of VUs,
+// and one per raw URL by the number of distinct ids in them. Requests are grouped by `name` instead.
+const SERIES_DROPPED_TAGS: &[&str] = &["vu", "url"];
+// Batches waiting for an output's thread. Past that the engine blocks on a file output,
+// and drops the batch for a network output.
+const QUEUED_BATCHES: usize = 16;
+
+/// One value added to a metric during the test, with its tags
+pub struct Sample {
+    pub timestamp: SystemTime,
+    pub metric: String,
+    pub kind: MetricKind,
+    pub value: f64,
+    pub tags: Tags,
+}
+
+impl Sample {
+    pub fn unix_millis(&self) -> u64 {
+        self.timestamp
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64
+    }
+
+    /// Tags identifying the series the sample belongs to in aggregating outputs
+    pub fn series_tags(&self) -> Tags {
+        self.tags
+            .iter()
+            .filter(|(tag, _)| !SERIES_DROPPED_TAGS.contains(&tag.as_str()))
+            .map(|(tag, value)| (tag.clone(), value.clone()))
+            .collect()
+    }
+}
+
+/// Runs the requests of network outputs on the writer thread. Built on first use:
+/// a runtime can't be dropped from async code, where outputs are created.
+#[derive(Default)]
+pub struct BlockingRuntime(Option<tokio::runtime::Runtime>);
+
+impl BlockingRuntime {
+    /// Send the request and wait for the response headers
+    pub fn send(&mut self, request: reqwest::RequestBuilder) -> Result<reqwest::Result<reqwest::Response>> {
+        let runtime = match self.0.take() {
+            Some(runtime) => runtime,
+            None => tokio::runtime::Builder::new_current_thread().enable_all().build()?,
+        };
+        // Sending starts the request's timeout, which needs the runtime
+        let response = runtime.block_on(async move { request.send().await });
+        self.0 = Some(runtime);
+        Ok(response)
+    }
+}
+
+/// The test run, for outputs that label what they export
+pub struct RunInfo {
+    pub id: String,
+    pub main_module: String,
+}
+
+/// Sends the engine's batches of samples to the queue of every output
+#[derive(Clone)]
+pub struct SampleSender {
+    queues: Vec<OutputQueue>,
+}
+
+impl SampleSender {
+    /// Queue a batch for every output. Blocks while a file output is behind; a network output
+    /// that is behind misses the batch, which is counted. Outputs that failed are skipped.
+    pub fn send(&self, batch: Vec<Sample>) {
+        let batch = Arc::new(batch);
+        for queue in &self.queues {
+            match &queue.dropped {
+                None => {
+                    let _ = queue.sender.send(batch.clone());
+                }
+                Some(dropped) => {
+                    if let Err(mpsc::TrySendError::Full(_)) = queue.sender.try_send(batch.clone()) {
+                        dropped.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+struct OutputQueue {
+    sender: mpsc::SyncSender<Arc<Vec<Sample>>>,
+    // Batches dropped while the queue was full, for network outputs
+    dropped: Option<Arc<AtomicU64>>,
+}
+
+/// An `--out` destination, written as `<type>=<target>[,<option>=<value>...]`
+#[derive(Clone, Debug)]
+pub enum OutputConfig {
+    /// One JSON object per line
+    Json(String),
+    Csv(String),
+    /// Remote-write endpoint, pushed to every `push_interval`
+    PrometheusRemoteWrite { url: String, push_interval: Duration },
+    /// Address of the `/metrics` endpoint served during the run, e.g. `:9091` (localhost)
+    PrometheusScrape(String),
+    /// OpenTelemetry collector, exported to every `push_interval`
+    Otlp {
+        endpoint: String,
+        protocol: OtlpProtocol,
+        push_interval: Duration,
+    },
+    /// InfluxDB write endpoint, written to in batches every `push_interval`
+    InfluxDb {
+        url: String,
+        token: Option<String>,
+        push_interval: Duration,
+    },
+    /// `host:port` of a StatsD server or agent
+    Statsd {
+        address: String,
+        namespace: String,
+        tag_format: StatsdTagFormat,
+    },
+}
+
+impl OutputConfig {
+    pub fn parse(spec: &str) -> Result<Self> {
+        let (kind, rest) = spec.split_once('=').ok_or_else(|| {
+            anyhow::anyhow!("Invalid output \"{}\": expected <type>=<target>, e.g. json=results.ndjson", spec)
+        })?;
+        let mut parts = rest.split(',');
+        let target = parts.next().unwrap_or_default().to_string();
+        if target.is_empty() {
+            return Err(anyhow::anyhow!("Invalid output \"{}\": missing target", spec));
+        }
+        let mut options = parts
+            .map(|option| {
+                option
+                    .split_once('=')
+                    .ok_or_else(|| anyhow::anyhow!("Invalid option \"{}\" of output \"{}\": expected <option>=<value>", option, spec))
+            })
+            .collect::<Result<BTreeMap<&str, &str>>>()?;
+
+        let config = match kind {
+            "json" => Self::Json(target),
+            "csv" => Self::Csv(target),
+            "prometheus-rw" => Self::PrometheusRemoteWrite {
+                url: target,
+                push_interval: push_interval(&mut options, spec, prometheus::DEFAULT_PUSH_INTERVAL)?,
+            },
+            "prometheus-scrape" => Self::PrometheusScrape(target),
+            "otlp" => Self::Otlp {
+                endpoint: target,
+                protocol: match options.remove("protocol") {
+                    None => OtlpProtocol::HttpProtobuf,
+                    Some(protocol) => OtlpProtocol::parse(protocol).ok_or_else(|| {
+                        anyhow::anyhow!("Invalid protocol \"{}\" of output \"{}\" (expected http or grpc)", protocol, spec)
+                    })?,
+                },
+                push_interval: push_interval(&mut options, spec, otlp::DEFAULT_PUSH_INTERVAL)?,
+            },
+            "influxdb" => Self::InfluxDb {
+                url: target,
+                token: options.remove("token").map(str::to_string),
+                push_interval: push_interval(&mut options, spec, influxdb::DEFAULT_PUSH_INTERVAL)?,
+            },
+            "statsd" => Self::Statsd {
+                address: target,
+                namespace: options
+                    .remove("namespace")
+                    .unwrap_or(statsd::DEFAULT_NAMESPACE)
+                    .to_string(),
+                tag_format: match options.remove("tags") {
+                    None => StatsdTagFormat::Datadog,
+                    Some(format) => StatsdTagFormat::parse(format).ok_or_else(|| {
+                        anyhow::anyhow!(
+                            "Invalid tags \"{}\" of output \"{}\" (expected datadog, influx or none)",
+                            format,
+                            spec
+                        )
+                    })?,
+                },
+            },
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "Unknown output type \"{}\" (expected json, csv, prometheus-rw, prometheus-scrape, otlp, influxdb or statsd)",
+                    kind
+                ));
+            }
+        };
+        if let Some(option) = options.keys().next() {
+            return Err(anyhow::anyhow!("Unknown option \"{}\" of output \"{}\"", option, spec));
+        }
+        Ok(config)
+    }
+
+    // `<type>=<target>`, naming the output in errors
+    fn label(&self) -> String {
+        match self {
+            Self::Json(path) => format!("json={}", path),
+            Self::Csv(path) => format!("csv={}", path),
+            Self::PrometheusRemoteWrite { url, .. } => format!("prometheus-rw={}", url),
+            Self::PrometheusScrape(address) => format!("prometheus-scrape={}", address),
+            Self::Otlp { endpoint, .. } => format!("otlp={}", endpoint),
+            Self::InfluxDb { url, .. } => format!("influxdb={}", url),
+            Self::Statsd { address, .. } => format!("statsd={}", address),
+        }
+    }
+
+    // A slow network output must not hold up the test, a file output must get every sample
+    fn is_network(&self) -> bool {
+        !matches!(self, Self::Json(_) | Self::Csv(_))
+    }
+
+    fn open(&self, run: &RunInfo) -> Result<Box<dyn Output>> {
+        Ok(match self {
+            Self::Json(path) => Box::new(JsonOutput::create(path)?),
+            Self::Csv(path) => Box::new(CsvOutput::create(path)?),
+            Self::PrometheusRemoteWrite { url, push_interval } => Box::new(RemoteWriteOutput::new(url, *push_interval)?),
+            Self::PrometheusScrape(address) => Box::new(ScrapeOutput::bind(address)?),
+            Self::Otlp {
+                endpoint,
+                protocol,
+                push_interval,
+            } => Box::new(OtlpOutput::new(endpoint, *protocol, *push_interval, run)?),
+            Self::InfluxDb {
+                url,
+                token,
+                push_interval,
+            } => Box::new(InfluxDbOutput::new(url, token.as_deref(), *push_interval)?),
+            Self::Statsd {
+                address,
+                namespace,
+                tag_format,
+            } => Box::new(StatsdOutput::connect(address, namespace, *tag_format)?),
+        })
+    }
+}
+
+// `interval=10s` option of pushing outputs
+fn push_interval(options: &mut BTreeMap<&str, &str>, spec: &str, default: Duration) -> Result<Duration> {
+    match options.remove("interval") {
+        Some(interval) => parse_duration(interval)
+            .ok_or_else(|| anyhow::anyhow!("Invalid interval \"{}\" of output \"{}\"", interval, spec)),
+        None => Ok(default),
+    }
+}
+
+/// A destination for samples, driven by the output writer thread
+pub trait Output: Send {
+    fn write(&mut self, samples: &[Sample]) -> Result<()>;
+
+    /// Called once after the last batch
+    fn finish(&mut self) -> Result<()>;
+}
+
+/// A background thread and queue per output writing the samples the engine sends,
+/// so file and network I/O don't block iterations, and one output can't hold up another.
+/// An output that fails is reported and left out; the others keep being written.
+pub struct OutputWriter {
+    sender: SampleSender,
+    threads: Vec<OutputThread>,
+}
+
+struct OutputThread {
+    label: String,
+    dropped: Option<Arc<AtomicU64>>,
+    thread: JoinHandle<Result<()>>,
+}
+
+impl OutputWriter {
+    /// Open every output, failing early on e.g. an unwritable path. `None` without outputs.
+    pub fn start(configs: &[OutputConfig], run: &RunInfo) -> Result<Option<Self>> {
+        if configs.is_empty() {
+            return Ok(None);
+        }
+        let outputs = configs
+            .iter()
+            .map(|config| Ok((config.label(), config.open(run)?, config.is_network())))
+            .collect::<Result<Vec<_>>>()?;
+        Self::spawn(outputs).map(Some)
+    }
+
+    // (label, output, whether it drops batches while it's behind)
+    fn spawn(outputs: Vec<(String, Box<dyn Output>, bool)>) -> Result<Self> {
+        let mut queues = Vec::new();
+        let mut threads = Vec::new();
+        for (label, mut output, drops_when_behind) in outputs {
+            let (sender, receiver) = mpsc::sync_channel::<Arc<Vec<Sample>>>(QUEUED_BATCHES);
+            let thread_label = label.clone();
+            let thread = std::thread::Builder::new()
+                .name(format!("v6-output-{}", threads.len()))
+                .spawn(move || {
+                    for batch in receiver {
+                        if let Err(e) = output.write(&batch) {
+                            return Err(report_output_error(&thread_label, e));
+                        }
+                    }
+                    output.finish().map_err(|e| report_output_error(&thread_label, e))
+                })?;
+
+            let dropped = drops_when_behind.then(|| Arc::new(AtomicU64::new(0)));
+            queues.push(OutputQueue {
+                sender,
+                dropped: dropped.clone(),
+            });
+            threads.push(OutputThread { label, dropped, thread });
+        }
+
+        Ok(Self {
+            sender: SampleSender { queues },
+            threads,
+        })
+    }
+
+    pub fn sender(&self) -> SampleSender {
+        self.sender.clone()
+    }
+
+    /// Wait for every batch queued so far to be written. Other senders must be dropped first.
+    pub fn finish(self) -> Result<()> {
+        drop(self.sender);
+        let mut first_error = None;
+        for OutputThread { label, dropped, thread } in self.threads {
+            let result = thread
+                .join()
+                .map_err(|_| anyhow::anyhow!("Output writer thread of {} panicked", label))
+                .and_then(|result| result);
+            if let Err(e) = result {
+                first_error.get_or_insert(e);
+            }
+            let dropped = dropped.map_or(0, |dropped| dropped.load(Ordering::Relaxed));
+            if dropped > 0 {
+                eprintln!(
+                    "⚠️  Output {} fell behind, {} batches of samples were dropped",
+                    label, dropped
+                );
+            }
+        }
+        first_error.map_or(Ok(()), Err)
+    }
+}
+
+// Print an output's error as soon as it happens; the error is returned by `OutputWriter::finish`
+fn report_output_error(label: &str, error: anyhow::Error) -> anyhow::Error {
+    eprintln!("❌ Output {} failed, no more samples are written to it: {}", label, error);
+    anyhow::anyhow!("Failed to write output {}: {}", label, error)
+}
+
+// Buffered file, gzip-compressed when its path ends with `.gz`
+enum OutputFile {
+    Plain(BufWriter<File>),
+    // Buffered in front of the encoder, which is slow with the small writes of serializers
+    Gzip(BufWriter<GzEncoder<File>>),
+}
+
+impl OutputFile {
+    fn create(path: &str) -> Result<Self> {
+        let file = File::create(path).map_err(|e| anyhow::anyhow!("Failed to create output '{}': {}", path, e))?;
+        Ok(if path.ends_with(".gz") {
+            Self::Gzip(BufWriter::new(GzEncoder::new(file, Compression::default())))
+        } else {
+            Self::Plain(BufWriter::new(file))
+        })
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        match self {
+            Self::Plain(writer) => writer.flush()?,
+            Self::Gzip(writer) => {
+                writer.flush()?;
+                writer.get_mut().try_finish()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Plain(writer) => writer.write(buf),
+            Self::Gzip(writer) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Self::Plain(writer) => writer.flush(),
+            Self::Gzip(writer) => writer.flush(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct JsonSample<'a> {
+    /// Unix time in milliseconds
+    timestamp: u64,
+    metric: &'a str,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    value: f64,
+    tags: &'a Tags,
+}
+
+// `--out json=results.ndjson`: one JSON object per sample and line
+struct JsonOutput {
+    file: OutputFile,
+}
+
+impl JsonOutput {
+    fn create(path: &str) -> Result<Self> {
+        Ok(Self { file: OutputFile::create(path)? })
+    }
+}
+
+impl Output for JsonOutput {
+    fn write(&mut self, samples: &[Sample]) -> Result<()> {
+        for sample in samples {
+            let line = JsonSample {
+                timestamp: sample.unix_millis(),
+                metric: &sample.metric,
+                kind: sample.kind.as_str(),
+                value: sample.value,
+                tags: &sample.tags,
+            };
+            serde_json::to_writer(&mut self.file, &line)?;
+            self.file.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.file.finish()
+    }
+}
+
+// `--out csv=results.csv`: common tags in their own columns, the others as `key=value&...`
+struct CsvOutput {
+    file: OutputFile,
+}
+
+impl CsvOutput {
+    fn create(path: &str) -> Result<Self> {
+        let mut file = OutputFile::create(path)?;
+        writeln!(file, "timestamp,metric,type,value,{},extra_tags", CSV_TAG_COLUMNS.join(","))?;
+        Ok(Self { file })
+    }
+}
+
+impl Output for CsvOutput {
+    fn write(&mut self, samples: &[Sample]) -> Result<()> {
+        for sample in samples {
+            let mut row = vec![
+                sample.unix_millis().to_string(),
+                csv_field(&sample.metric),
+                sample.kind.as_str().to_string(),
+                sample.value.to_string(),
+            ];
+            row.extend(
+                CSV_TAG_COLUMNS
+                    .iter()
+                    .map(|tag| sample.tags.get(*tag).map(|value| csv_field(value)).unwrap_or_default()),
+            );
+            let extra_tags: Vec<String> = sample
+                .tags
+                .iter()
+                .filter(|(tag, _)| !CSV_TAG_COLUMNS.contains(&tag.as_str()))
+                .map(|(tag, value)| format!("{}={}", tag, value))
+                .collect();
+            row.push(csv_field(&extra_tags.join("&")));
+            writeln!(self.file, "{}", row.join(","))?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.file.finish()
+    }
+}
+
+// Quote fields containing separators, quotes or line breaks
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(tags: &[(&str, &str)]) -> Sample {
+        Sample {
+            timestamp: UNIX_EPOCH + std::time::Duration::from_millis(1_700_000_000_123),
+            metric: "http_req_duration".to_string(),
+            kind: MetricKind::Trend,
+            value: 12.5,
+            tags: tags
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn series_tags_leave_out_vu_and_url() {
+        let sample = sample(&[
+            ("vu", "3"),
+            ("url", "https://example.com/items/42"),
+            ("name", "item"),
+            ("status", "200"),
+        ]);
+        let expected: Tags = [("name", "item"), ("status", "200")]
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        assert_eq!(sample.series_tags(), expected);
+    }
+
+    fn read_to_string(path: &std::path::Path) -> String {
+        std::fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn json_output_writes_one_object_per_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("results.ndjson");
+        let mut output = JsonOutput::create(path.to_str().unwrap()).unwrap();
+        output
+            .write(&[sample(&[("status", "200"), ("name", "login")]), sample(&[])])
+            .unwrap();
+        output.finish().unwrap();
+
+        assert_eq!(
+            read_to_string(&path),
+            concat!(
+                r#"{"timestamp":1700000000123,"metric":"http_req_duration","type":"trend","value":12.5,"tags":{"name":"login","status":"200"}}"#,
+                "\n",
+                r#"{"timestamp":1700000000123,"metric":"http_req_duration","type":"trend","value":12.5,"tags":{}}"#,
+                "\n",
+            )
+        );
+    }
+
+    #[test]
+    fn csv_output_quotes_fields_and_collects_extra_tags() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("results.csv");
+        let mut output = CsvOutput::create(path.to_str().unwrap()).unwrap();
+        output
+            .write(&[sample(&[
+                ("status", "200"),
+                ("url", "https://example.com/?a=1,b=2"),
+                ("check", "says \"ok\""),
+                ("region", "eu"),
+                ("tier", "gold"),
+            ])])
+            .unwrap();
+        output.finish().unwrap();
+
+        assert_eq!(
+            read_to_string(&path),
+            concat!(
+                "timestamp,metric,type,value,method,status,url,name,group,check,scenario,vu,extra_tags\n",
+                "1700000000123,http_req_duration,trend,12.5,,200,\"https://example.com/?a=1,b=2\",,,\"says \"\"ok\"\"\",,,region=eu&tier=gold\n",
+            )
+        );
+    }
+
+    #[test]
+    fn gzip_output_is_complete_after_finish() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("results.ndjson.gz");
+        let mut output = JsonOutput::create(path.to_str().unwrap()).unwrap();
+        let batch: Vec<Sample> = (0..2000).map(|_| sample(&[("vu", "1")])).collect();
+        output.write(&batch).unwrap();
+        output.finish().unwrap();
+
+        let mut contents = String::new();
+        std::io::Read::read_to_string(
+            &mut flate2::read::GzDecoder::new(File::open(&path).unwrap()),
+            &mut contents,
+        )
+        .unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 2000);
+        assert!(
+            lines
+                .iter()
+                .all(|line| line.ends_with(r#""tags":{"vu":"1"}}"#))
+        );
+    }
+
+    // Fails on its first batch
+    struct FailingOutput;
+
+    impl Output for FailingOutput {
+        fn write(&mut self, _samples: &[Sample]) -> Result<()> {
+            Err(anyhow::anyhow!("connection refused"))
+        }
+
+        fn finish(&mut self) -> Result<()> {
+            panic!("a failed output isn't finished")
+        }
+    }
+
+    #[test]
+    fn writer_keeps_feeding_outputs_after_one_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("results.ndjson");
+        let json = JsonOutput::create(path.to_str().unwrap()).unwrap();
+        let writer = OutputWriter::spawn(vec![
+            (
+                "statsd=localhost:8125".to_string(),
+                Box::new(FailingOutput),
+                true,
+            ),
+            ("json=results.ndjson".to_string(), Box::new(json), false),
+        ])
+        .unwrap();
+
+        let sender = writer.sender();
+        for _ in 0..QUEUED_BATCHES * 2 {
+            sender.send(vec![sample(&[])]);
+        }
+        drop(sender);
+
+        let error = writer.finish().unwrap_err().to_string();
+        assert_eq!(
+            error,
+            "Failed to write output statsd=localhost:8125: connection refused"
+        );
+        assert_eq!(read_to_string(&path).lines().count(), QUEUED_BATCHES * 2);
+    }
+
+    // Hangs on every batch until released, like a push to an unresponsive collector
+    struct StalledOutput {
+        writing: mpsc::Sender<()>,
+        release: mpsc::Receiver<()>,
+        writes: Arc<AtomicU64>,
+    }
+
+    impl Output for StalledOutput {
+        fn write(&mut self, _samples: &[Sample]) -> Result<()> {
+            self.writes.fetch_add(1, Ordering::Relaxed);
+            let _ = self.writing.send(());
+            let _ = self.release.recv();
+            Ok(())
+        }
+
+        fn finish(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn stalled_network_output_drops_batches_without_holding_up_a_file_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("results.ndjson");
+        let json = JsonOutput::create(path.to_str().unwrap()).unwrap();
+        let (writing_sender, writing) = mpsc::channel();
+        let (release, release_receiver) = mpsc::channel();
+        let writes = Arc::new(AtomicU64::new(0));
+        let stalled = StalledOutput {
+            writing: writing_sender,
+            release: release_receiver,
+            writes: writes.clone(),
+        };
+        let writer = OutputWriter::spawn(vec![
+            (
+                "otlp=http://localhost:4318".to_string(),
+                Box::new(stalled),
+                true,
+            ),
+            ("json=results.ndjson".to_string(), Box::new(json), false),
+        ])
+        .unwrap();
+
+        let sender = writer.sender();
+        sender.send(vec![sample(&[])]);
+        // The stalled output took the first batch and hangs on it; the rest can't block
+        writing.recv().unwrap();
+        let batches = QUEUED_BATCHES * 4;
+        for _ in 1..batches {
+            sender.send(vec![sample(&[])]);
+        }
+        let dropped = writer.threads[0]
+            .dropped
+            .as_ref()
+            .unwrap()
+            .load(Ordering::Relaxed);
+        drop(sender);
+        drop(release);
+        writer.finish().unwrap();
+
+        assert_eq!(dropped, (batches - 1 - QUEUED_BATCHES) as u64);
+        assert_eq!(writes.load(Ordering::Relaxed), (QUEUED_BATCHES + 1) as u64);
+        assert_eq!(read_to_string(&path).lines().count(), batches);
+    }
+}
@@ -0,0 +1,450 @@
+use anyhow::Result;
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::metrics::{Aggregation, MetricKind, MetricValue, Tags};
+use crate::outputs::{BlockingRuntime, Output, Sample};
+use crate::protobuf::{write_bytes_field, write_double_field, write_varint_field};
+
+pub const DEFAULT_PUSH_INTERVAL: Duration = Duration::from_secs(5);
+const PUSH_TIMEOUT: Duration = Duration::from_secs(10);
+const METRIC_PREFIX: &str = "v6_";
+const MAX_SCRAPE_REQUEST_SIZE: usize = 16 * 1024;
+// How often the scrape listener checks whether it should stop, between connections
+const SCRAPE_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+// Label pairs sorted by name, and the value of one series
+type Point = (Vec<(String, String)>, f64);
+
+// Series of one Prometheus metric name
+struct Family {
+    is_counter: bool,
+    points: Vec<Point>,
+}
+
+// Cumulative values per metric and tag set since the start of the test
+#[derive(Default)]
+struct SeriesSet {
+    series: BTreeMap<(String, Tags), (MetricKind, MetricValue)>,
+}
+
+impl SeriesSet {
+    fn add(&mut self, samples: &[Sample]) {
+        for sample in samples {
+            self.series
+                .entry((sample.metric.clone(), sample.series_tags()))
+                .or_insert_with(|| (sample.kind, MetricValue::new(sample.kind)))
+                .1
+                .add(sample.value);
+        }
+    }
+
+    // Counters as `v6_<name>_total`, gauges as `v6_<name>`, rates as `v6_<name>_rate`
+    // and trends as one `v6_<name>_<stat>` gauge per stat (avg, min, med, max, p90, ...)
+    fn families(&self) -> BTreeMap<String, Family> {
+        let mut families: BTreeMap<String, Family> = BTreeMap::new();
+        for ((metric, tags), (kind, value)) in &self.series {
+            let base = format!("{}{}", METRIC_PREFIX, sanitize_name(metric));
+            let points: Vec<(String, f64)> = match kind {
+                MetricKind::Counter => value
+                    .aggregate(Aggregation::Count, 1.0)
+                    .map(|count| (format!("{}_total", base), count))
+                    .into_iter()
+                    .collect(),
+                MetricKind::Gauge => value
+                    .aggregate(Aggregation::Value, 1.0)
+                    .map(|last| (base.clone(), last))
+                    .into_iter()
+                    .collect(),
+                MetricKind::Rate => value
+                    .aggregate(Aggregation::Rate, 1.0)
+                    .map(|rate| (format!("{}_rate", base), rate))
+                    .into_iter()
+                    .collect(),
+                MetricKind::Trend => value
+                    .stats(1.0)
+                    .into_iter()
+                    .map(|(stat, value)| (format!("{}_{}", base, stat.replace(['(', ')'], "")), value))
+                    .collect(),
+            };
+
+            let labels = labels(tags);
+            for (name, value) in points {
+                families
+                    .entry(name)
+                    .or_insert_with(|| Family {
+                        is_counter: *kind == MetricKind::Counter,
+                        points: Vec::new(),
+                    })
+                    .points
+                    .push((labels.clone(), value));
+            }
+        }
+        families
+    }
+}
+
+/// `--out prometheus-rw=<url>`: pushes every series to a remote-write endpoint,
+/// as a snappy-compressed protobuf `WriteRequest`, every push interval and at the end
+pub struct RemoteWriteOutput {
+    url: String,
+    push_interval: Duration,
+    series: SeriesSet,
+    last_push: Instant,
+    client: reqwest::Client,
+    runtime: BlockingRuntime,
+}
+
+impl RemoteWriteOutput {
+    pub fn new(url: &str, push_interval: Duration) -> Result<Self> {
+        reqwest::Url::parse(url).map_err(|e| anyhow::anyhow!("Invalid Prometheus remote-write URL '{}': {}", url, e))?;
+        Ok(Self {
+            url: url.to_string(),
+            push_interval,
+            series: SeriesSet::default(),
+            last_push: Instant::now(),
+            client: reqwest::Client::new(),
+            runtime: BlockingRuntime::default(),
+        })
+    }
+
+    // Failed pushes are reported and retried with the next one, which carries the same series
+    fn push(&mut self) -> Result<()> {
+        self.last_push = Instant::now();
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as i64;
+        let body = snap::raw::Encoder::new().compress_vec(&encode_write_request(&self.series.families(), timestamp))?;
+
+        let request = self
+            .client
+            .post(&self.url)
+            .header("Content-Type", "application/x-protobuf")
+            .header("Content-Encoding", "snappy")
+            .header("X-Prometheus-Remote-Write-Version", "0.1.0")
+            .header("User-Agent", concat!("v6/", env!("CARGO_PKG_VERSION")))
+            .timeout(PUSH_TIMEOUT)
+            .body(body);
+        match self.runtime.send(request)? {
+            Ok(response) if response.status().is_success() => {}
+            Ok(response) => eprintln!("⚠️  Prometheus remote write to {} failed: HTTP {}", self.url, response.status()),
+            Err(e) => eprintln!("⚠️  Prometheus remote write to {} failed: {}", self.url, e),
+        }
+        Ok(())
+    }
+}
+
+impl Output for RemoteWriteOutput {
+    fn write(&mut self, samples: &[Sample]) -> Result<()> {
+        self.series.add(samples);
+        if self.last_push.elapsed() >= self.push_interval {
+            self.push()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.push()
+    }
+}
+
+/// `--out prometheus-scrape=:9091`: serves every series on `/metrics` in the
+/// Prometheus text format while the test runs
+pub struct ScrapeOutput {
+    series: Arc<Mutex<SeriesSet>>,
+    stop: Arc<AtomicBool>,
+    listener: Option<JoinHandle<()>>,
+}
+
+impl ScrapeOutput {
+    /// `:9091` listens on localhost only; `0.0.0.0:9091` on every interface
+    pub fn bind(address: &str) -> Result<Self> {
+        let address = if address.starts_with(':') {
+            format!("127.0.0.1{}", address)
+        } else {
+            address.to_string()
+        };
+        let listener = TcpListener::bind(&address)
+            .map_err(|e| anyhow::anyhow!("Failed to listen on '{}' for Prometheus scrapes: {}", address, e))?;
+        // Accepting doesn't block, so the thread sees `stop` without another connection
+        listener.set_nonblocking(true)?;
+
+        let series = Arc::new(Mutex::new(SeriesSet::default()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let (scraped, stopped) = (series.clone(), stop.clone());
+        let listener = std::thread::Builder::new()
+            .name("v6-prometheus-scrape".to_string())
+            .spawn(move || {
+                while !stopped.load(Ordering::Relaxed) {
+                    match listener.accept() {
+                        // A failed scrape only affects that client
+                        Ok((stream, _)) => {
+                            let _ = stream.set_nonblocking(false).and_then(|_| serve_scrape(stream, &scraped));
+                        }
+                        Err(_) => std::thread::sleep(SCRAPE_POLL_INTERVAL),
+                    }
+                }
+            })?;
+
+        println!("📡 Prometheus metrics on http://{}/metrics", address);
+        Ok(Self {
+            series,
+            stop,
+            listener: Some(listener),
+        })
+    }
+
+    // Close the listener once the scrape in progress, if any, is answered
+    fn shutdown(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(listener) = self.listener.take() {
+            let _ = listener.join();
+        }
+    }
+}
+
+impl Output for ScrapeOutput {
+    fn write(&mut self, samples: &[Sample]) -> Result<()> {
+        self.series.lock().unwrap().add(samples);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.shutdown();
+        Ok(())
+    }
+}
+
+// Also when the output is dropped without `finish`, e.g. after another output failed to open
+impl Drop for ScrapeOutput {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+// Answer one HTTP/1.1 request: `GET /metrics`, or 404
+fn serve_scrape(mut stream: TcpStream, series: &Mutex<SeriesSet>) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+
+    let mut request = Vec::new();
+    let mut buffer = [0u8; 1024];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") && request.len() < MAX_SCRAPE_REQUEST_SIZE {
+        let read = stream.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        request.extend_from_slice(&buffer[..read]);
+    }
+
+    let request = String::from_utf8_lossy(&request);
+    let mut request_line = request.split_whitespace();
+    let method = request_line.next();
+    let path = request_line.next().and_then(|target| target.split('?').next());
+    let (status, body) = match (method, path) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", render_text(&series.lock().unwrap().families())),
+        _ => ("404 Not Found", "Not found\n".to_string()),
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+// Prometheus text exposition format
+fn render_text(families: &BTreeMap<String, Family>) -> String {
+    let mut text = String::new();
+    for (name, family) in families {
+        let _ = writeln!(text, "# TYPE {} {}", name, if family.is_counter { "counter" } else { "gauge" });
+        for (labels, value) in &family.points {
+            let labels: Vec<String> = labels
+                .iter()
+                .map(|(label, value)| {
+                    let value = value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+                    format!("{}=\"{}\"", label, value)
+                })
+                .collect();
+            if labels.is_empty() {
+                let _ = writeln!(text, "{} {}", name, value);
+            } else {
+                let _ = writeln!(text, "{}{{{}}} {}", name, labels.join(","), value);
+            }
+        }
+    }
+    text
+}
+
+// Remote-write `WriteRequest` protobuf: repeated TimeSeries { repeated Label, repeated Sample }
+fn encode_write_request(families: &BTreeMap<String, Family>, timestamp_ms: i64) -> Vec<u8> {
+    let mut request = Vec::new();
+    for (name, family) in families {
+        for (labels, value) in &family.points {
+            // Labels must be sorted by name, `__name__` included
+            let mut all_labels: Vec<(&str, &str)> = labels
+                .iter()
+                .map(|(label, value)| (label.as_str(), value.as_str()))
+                .collect();
+            all_labels.push(("__name__", name.as_str()));
+            all_labels.sort();
+
+            let mut series = Vec::new();
+            for (label, value) in all_labels {
+                let mut encoded = Vec::new();
+                write_bytes_field(&mut encoded, 1, label.as_bytes());
+                write_bytes_field(&mut encoded, 2, value.as_bytes());
+                write_bytes_field(&mut series, 1, &encoded);
+            }
+
+            // Sample { double value = 1; int64 timestamp = 2; }
+            let mut sample = Vec::new();
+            write_double_field(&mut sample, 1, *value);
+            write_varint_field(&mut sample, 2, timestamp_ms as u64);
+            write_bytes_field(&mut series, 2, &sample);
+
+            write_bytes_field(&mut request, 1, &series);
+        }
+    }
+    request
+}
+
+fn labels(tags: &Tags) -> Vec<(String, String)> {
+    let mut labels: Vec<(String, String)> = tags
+        .iter()
+        .map(|(tag, value)| (sanitize_name(tag), value.clone()))
+        .collect();
+    labels.sort();
+    labels
+}
+
+// Metric and label names: letters, digits and underscores, not starting with a digit
+fn sanitize_name(name: &str) -> String {
+    let sanitized: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if sanitized.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("_{}", sanitized)
+    } else {
+        sanitized
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(metric: &str, kind: MetricKind, value: f64, tags: &[(&str, &str)]) -> Sample {
+        Sample {
+            timestamp: SystemTime::now(),
+            metric: metric.to_string(),
+            kind,
+            value,
+            tags: tags
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn renders_the_text_format() {
+        let mut series = SeriesSet::default();
+        series.add(&[
+            sample(
+                "http_reqs",
+                MetricKind::Counter,
+                1.0,
+                &[("name", "say \"hi\"\n"), ("status", "200"), ("vu", "1")],
+            ),
+            sample(
+                "http_reqs",
+                MetricKind::Counter,
+                1.0,
+                &[("name", "say \"hi\"\n"), ("status", "200"), ("vu", "2")],
+            ),
+            sample("vus", MetricKind::Gauge, 5.0, &[]),
+            sample("checks", MetricKind::Rate, 1.0, &[("check", "C:\\ok")]),
+            sample("checks", MetricKind::Rate, 0.0, &[("check", "C:\\ok")]),
+            sample("my-metric", MetricKind::Gauge, 2.5, &[("my.tag", "x")]),
+        ]);
+
+        assert_eq!(
+            render_text(&series.families()),
+            concat!(
+                "# TYPE v6_checks_rate gauge\n",
+                "v6_checks_rate{check=\"C:\\\\ok\"} 0.5\n",
+                "# TYPE v6_http_reqs_total counter\n",
+                "v6_http_reqs_total{name=\"say \\\"hi\\\"\\n\",status=\"200\"} 2\n",
+                "# TYPE v6_my_metric gauge\n",
+                "v6_my_metric{my_tag=\"x\"} 2.5\n",
+                "# TYPE v6_vus gauge\n",
+                "v6_vus 5\n",
+            )
+        );
+    }
+
+    #[test]
+    fn encodes_the_write_request() {
+        let families = BTreeMap::from([(
+            "v6_vus".to_string(),
+            Family {
+                is_counter: false,
+                points: vec![(vec![("a".to_string(), "b".to_string())], 1.5)],
+            },
+        )]);
+
+        #[rustfmt::skip]
+        let expected: &[u8] = &[
+            // WriteRequest.timeseries, 42 bytes
+            0x0a, 42,
+                // Label { name: "__name__", value: "v6_vus" }, sorted before "a"
+                0x0a, 18, 0x0a, 8, b'_', b'_', b'n', b'a', b'm', b'e', b'_', b'_', 0x12, 6, b'v', b'6', b'_', b'v', b'u', b's',
+                // Label { name: "a", value: "b" }
+                0x0a, 6, 0x0a, 1, b'a', 0x12, 1, b'b',
+                // Sample { value: 1.5, timestamp: 1000 }
+                0x12, 12, 0x09, 0, 0, 0, 0, 0, 0, 0xf8, 0x3f, 0x10, 0xe8, 0x07,
+        ];
+        assert_eq!(encode_write_request(&families, 1000), expected);
+    }
+
+    #[test]
+    fn scrape_output_listens_on_localhost_until_finished() {
+        // A port that was just free
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let mut output = ScrapeOutput::bind(&format!(":{}", port)).unwrap();
+        output
+            .write(&[sample("vus", MetricKind::Gauge, 3.0, &[])])
+            .unwrap();
+
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+        assert!(
+            response.ends_with("\r\n\r\n# TYPE v6_vus gauge\nv6_vus 3\n"),
+            "{}",
+            response
+        );
+
+        output.finish().unwrap();
+        assert!(TcpStream::connect(("127.0.0.1", port)).is_err());
+    }
+}
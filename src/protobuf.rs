@@ -0,0 +1,114 @@
+// Minimal protobuf encoding for the remote-write and OTLP outputs, whose messages are
+// small enough not to need generated code
+
+pub fn write_varint(buffer: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buffer.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buffer.push(value as u8);
+}
+
+pub fn write_varint_field(buffer: &mut Vec<u8>, field: u64, value: u64) {
+    write_varint(buffer, field << 3);
+    write_varint(buffer, value);
+}
+
+pub fn write_fixed64_field(buffer: &mut Vec<u8>, field: u64, value: u64) {
+    write_varint(buffer, (field << 3) | 1);
+    buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+pub fn write_double_field(buffer: &mut Vec<u8>, field: u64, value: f64) {
+    write_fixed64_field(buffer, field, value.to_bits());
+}
+
+/// Length-delimited field: strings, bytes, embedded messages and packed repeated values
+pub fn write_bytes_field(buffer: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+    write_varint(buffer, (field << 3) | 2);
+    write_varint(buffer, bytes.len() as u64);
+    buffer.extend_from_slice(bytes);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encoded(write: impl FnOnce(&mut Vec<u8>)) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        write(&mut buffer);
+        buffer
+    }
+
+    #[test]
+    fn varints_use_seven_bits_per_byte() {
+        for (value, expected) in [
+            (0, &[0x00][..]),
+            (1, &[0x01]),
+            (127, &[0x7f]),
+            (128, &[0x80, 0x01]),
+            (300, &[0xac, 0x02]),
+            (16_384, &[0x80, 0x80, 0x01]),
+            (
+                u64::MAX,
+                &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01],
+            ),
+        ] {
+            assert_eq!(
+                encoded(|buffer| write_varint(buffer, value)),
+                expected,
+                "{}",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn varint_fields_are_tagged_with_wire_type_0() {
+        assert_eq!(
+            encoded(|buffer| write_varint_field(buffer, 1, 150)),
+            [0x08, 0x96, 0x01]
+        );
+        // Field numbers from 16 take two bytes of tag
+        assert_eq!(
+            encoded(|buffer| write_varint_field(buffer, 16, 1)),
+            [0x80, 0x01, 0x01]
+        );
+    }
+
+    #[test]
+    fn fixed64_fields_are_little_endian_with_wire_type_1() {
+        assert_eq!(
+            encoded(|buffer| write_fixed64_field(buffer, 2, 0x0102_0304_0506_0708)),
+            [0x11, 0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01]
+        );
+    }
+
+    #[test]
+    fn double_fields_are_ieee_754_fixed64() {
+        assert_eq!(
+            encoded(|buffer| write_double_field(buffer, 1, 1.0)),
+            [0x09, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xf0, 0x3f]
+        );
+        assert_eq!(
+            encoded(|buffer| write_double_field(buffer, 4, -2.5)),
+            [0x21, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0xc0]
+        );
+    }
+
+    #[test]
+    fn bytes_fields_are_length_delimited_with_wire_type_2() {
+        assert_eq!(
+            encoded(|buffer| write_bytes_field(buffer, 2, b"testing")),
+            [0x12, 0x07, b't', b'e', b's', b't', b'i', b'n', b'g']
+        );
+        assert_eq!(
+            encoded(|buffer| write_bytes_field(buffer, 1, b"")),
+            [0x0a, 0x00]
+        );
+
+        let long = encoded(|buffer| write_bytes_field(buffer, 1, &[0xab; 200]));
+        assert_eq!(long[..3], [0x0a, 0xc8, 0x01]);
+        assert_eq!(long.len(), 3 + 200);
+    }
+}
@@ -0,0 +1,700 @@
+use anyhow::Result;
+use serde_json::Value;
+use std::{collections::BTreeMap, fmt::Write as _};
+
+use crate::engine::TestRunResult;
+use crate::metrics::{
+    CHECKS, DATA_RECEIVED, DATA_SENT, HTTP_REQ_DURATION, HTTP_REQ_FAILED, HTTP_REQS, format_bytes, format_duration,
+};
+
+// Stats of the latency table, in the order of the summary's trend values
+const LATENCY_STATS: &[&str] = &["avg", "med", "p(90)", "p(95)", "p(99)", "max"];
+// (seconds since the start, value) of a chart series; `None` leaves a gap
+type ChartPoints = Vec<(f64, Option<f64>)>;
+
+const CHART_COLORS: &[&str] = &["#2563eb", "#dc2626", "#16a34a", "#d97706", "#7c3aed"];
+const HTML_STYLE: &str = "\
+body{font-family:system-ui,sans-serif;margin:2rem auto;max-width:1320px;padding:0 1rem;color:#111827}\
+h1{margin-bottom:.25rem}.meta{color:#4b5563}.ok{color:#15803d}.failed{color:#b91c1c}\
+.cards{display:flex;flex-wrap:wrap;gap:.75rem}.card{border:1px solid #e5e7eb;border-radius:6px;padding:.5rem 1rem}\
+.card div{color:#6b7280;font-size:.85rem}.charts{display:grid;grid-template-columns:repeat(auto-fit,minmax(560px,1fr));gap:1rem}\
+figure{margin:0}figcaption{font-weight:600}svg{width:100%;height:auto}svg text{font-size:11px;fill:#6b7280}\
+.grid{stroke:#e5e7eb}.legend span{margin-right:1rem;font-size:.85rem}\
+.legend i{display:inline-block;width:.75rem;height:.75rem;margin-right:.3rem;border-radius:2px}\
+table{border-collapse:collapse;margin-bottom:1rem}th,td{border-bottom:1px solid #e5e7eb;padding:.3rem .75rem;text-align:left}\
+.number{text-align:right;font-variant-numeric:tabular-nums}.group td{font-weight:600;background:#f9fafb}";
+
+/// A `--report` file, written as `<type>=<file>` once the test is over
+#[derive(Clone, Debug)]
+pub enum ReportConfig {
+    /// One testcase per threshold and check, for CI test result views
+    Junit(String),
+    /// Key results as compact tables, e.g. for PR comments
+    Markdown(String),
+    /// Self-contained page with charts over time and per-tag and per-group tables
+    Html(String),
+}
+
+impl ReportConfig {
+    pub fn parse(spec: &str) -> Result<Self> {
+        let (kind, path) = spec.split_once('=').ok_or_else(|| {
+            anyhow::anyhow!("Invalid report \"{}\": expected <type>=<file>, e.g. junit=results.xml", spec)
+        })?;
+        if path.is_empty() {
+            return Err(anyhow::anyhow!("Invalid report \"{}\": missing file", spec));
+        }
+        match kind {
+            "junit" => Ok(Self::Junit(path.to_string())),
+            "markdown" | "md" => Ok(Self::Markdown(path.to_string())),
+            "html" => Ok(Self::Html(path.to_string())),
+            _ => Err(anyhow::anyhow!("Unknown report type \"{}\" (expected junit, markdown or html)", kind)),
+        }
+    }
+
+    /// Whether the run must record `TestRunResult::timeline` for this report
+    pub fn needs_timeline(&self) -> bool {
+        matches!(self, Self::Html(_))
+    }
+
+    pub fn write(&self, result: &TestRunResult) -> Result<()> {
+        let (path, content, name) = match self {
+            Self::Junit(path) => (path, junit_report(&result.summary), "JUnit"),
+            Self::Markdown(path) => (path, markdown_report(&result.summary), "Markdown"),
+            Self::Html(path) => {
+                let timeline = result.timeline.as_ref().and_then(Value::as_array).map(Vec::as_slice);
+                (path, html_report(&result.summary, timeline.unwrap_or_default()), "HTML")
+            }
+        };
+        std::fs::write(path, content).map_err(|e| anyhow::anyhow!("Failed to write report '{}': {}", path, e))?;
+        println!("📄 {} report written to {}", name, path);
+        Ok(())
+    }
+}
+
+// <testsuites> with a "thresholds" and a "checks" suite; failed ones as <failure>
+fn junit_report(summary: &Value) -> String {
+    let duration_secs = summary["metadata"]["testRunDurationMs"].as_f64().unwrap_or_default() / 1000.0;
+
+    let mut thresholds = String::new();
+    let mut threshold_failures = 0;
+    let threshold_results = summary["thresholds"].as_array().map(Vec::as_slice).unwrap_or_default();
+    for threshold in threshold_results {
+        let metric = threshold["metric"].as_str().unwrap_or_default();
+        let expression = threshold["threshold"].as_str().unwrap_or_default();
+        let _ = write!(
+            thresholds,
+            "    <testcase name=\"{}\" classname=\"thresholds.{}\"",
+            xml_escape(expression),
+            xml_escape(metric)
+        );
+        if threshold["ok"].as_bool().unwrap_or(true) {
+            thresholds.push_str("/>\n");
+        } else {
+            threshold_failures += 1;
+            let message = format!("{} on {} failed with {}", expression, metric, format_value(&threshold["actual"]));
+            let _ = writeln!(
+                thresholds,
+                ">\n      <failure type=\"threshold\" message=\"{}\"/>\n    </testcase>",
+                xml_escape(&message)
+            );
+        }
+    }
+
+    let mut checks = String::new();
+    let (mut check_count, mut check_failures) = (0, 0);
+    write_junit_checks(&summary["rootGroup"], &mut checks, &mut check_count, &mut check_failures);
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let _ = writeln!(
+        xml,
+        "<testsuites name=\"v6\" tests=\"{}\" failures=\"{}\" time=\"{:.3}\">",
+        threshold_results.len() + check_count,
+        threshold_failures + check_failures,
+        duration_secs
+    );
+    for (name, tests, failures, testcases) in [
+        ("thresholds", threshold_results.len(), threshold_failures, thresholds),
+        ("checks", check_count, check_failures, checks),
+    ] {
+        let _ = writeln!(
+            xml,
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" time=\"{:.3}\">",
+            name, tests, failures, duration_secs
+        );
+        xml.push_str(&testcases);
+        xml.push_str("  </testsuite>\n");
+    }
+    xml.push_str("</testsuites>\n");
+    xml
+}
+
+// Checks of a group and its subgroups, with the group path as class name
+fn write_junit_checks(group: &Value, xml: &mut String, count: &mut usize, failures: &mut usize) {
+    let classname = match group["path"].as_str().unwrap_or_default() {
+        "" => "checks".to_string(),
+        path => format!("checks.{}", path),
+    };
+    for check in group["checks"].as_array().into_iter().flatten() {
+        let name = check["name"].as_str().unwrap_or_default();
+        let passes = check["passes"].as_u64().unwrap_or_default();
+        let fails = check["fails"].as_u64().unwrap_or_default();
+        *count += 1;
+        let _ = write!(xml, "    <testcase name=\"{}\" classname=\"{}\"", xml_escape(name), xml_escape(&classname));
+        if fails == 0 {
+            xml.push_str("/>\n");
+        } else {
+            *failures += 1;
+            let message = format!("{} of {} failed", fails, passes + fails);
+            let _ = writeln!(
+                xml,
+                ">\n      <failure type=\"check\" message=\"{}\"/>\n    </testcase>",
+                xml_escape(&message)
+            );
+        }
+    }
+    for child in group["groups"].as_array().into_iter().flatten() {
+        write_junit_checks(child, xml, count, failures);
+    }
+}
+
+// Overview, latencies of every trend and threshold results
+fn markdown_report(summary: &Value) -> String {
+    let metadata = &summary["metadata"];
+    let metrics = &summary["metrics"];
+    let test_file = test_file_name(summary);
+
+    let mut md = format!("## V6 results: {}\n\n", test_file);
+    let _ = writeln!(
+        md,
+        "{} · {:.1}s · {} VUs{}\n",
+        if metadata["thresholdsPassed"].as_bool().unwrap_or(true) {
+            "✅ Thresholds passed"
+        } else {
+            "❌ Thresholds failed"
+        },
+        metadata["testRunDurationMs"].as_f64().unwrap_or_default() / 1000.0,
+        metadata["vus"],
+        if metadata["aborted"].as_bool().unwrap_or_default() { " · aborted" } else { "" }
+    );
+
+    md.push_str("| Metric | Value |\n|---|---|\n");
+    let requests = &metrics[HTTP_REQS]["values"];
+    if let Some(count) = requests["count"].as_f64() {
+        let _ = writeln!(md, "| Requests | {} ({:.1}/s) |", count, requests["rate"].as_f64().unwrap_or_default());
+    }
+    for (label, metric) in [("Failed requests", HTTP_REQ_FAILED), ("Checks", CHECKS)] {
+        let values = &metrics[metric]["values"];
+        if let Some(rate) = values["rate"].as_f64() {
+            let _ = writeln!(
+                md,
+                "| {} | {:.2}% ({} of {}) |",
+                label,
+                rate * 100.0,
+                values["passes"],
+                values["passes"].as_u64().unwrap_or_default() + values["fails"].as_u64().unwrap_or_default()
+            );
+        }
+    }
+    for (label, metric) in [("Data sent", DATA_SENT), ("Data received", DATA_RECEIVED)] {
+        if let Some(bytes) = metrics[metric]["values"]["count"].as_f64() {
+            let _ = writeln!(md, "| {} | {} |", label, format_bytes(bytes));
+        }
+    }
+
+    let trends: Vec<(&String, &Value)> = metrics
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter(|(_, metric)| metric["type"] == "trend" && metric["values"]["avg"].is_number())
+        .collect();
+    if !trends.is_empty() {
+        let _ = writeln!(md, "\n| Latency | {} |", LATENCY_STATS.join(" | "));
+        let _ = writeln!(md, "|---|{}", "---:|".repeat(LATENCY_STATS.len()));
+        for (name, metric) in trends {
+            let stats: Vec<String> = LATENCY_STATS
+                .iter()
+                .map(|stat| format_value(&metric["values"][*stat]))
+                .collect();
+            let _ = writeln!(md, "| {} | {} |", name, stats.join(" | "));
+        }
+    }
+
+    let thresholds = summary["thresholds"].as_array().map(Vec::as_slice).unwrap_or_default();
+    if !thresholds.is_empty() {
+        md.push_str("\n| | Threshold | Metric | Actual |\n|---|---|---|---:|\n");
+        for threshold in thresholds {
+            let _ = writeln!(
+                md,
+                "| {} | `{}` | {} | {} |",
+                if threshold["ok"].as_bool().unwrap_or(true) { "✅" } else { "❌" },
+                markdown_escape(threshold["threshold"].as_str().unwrap_or_default()),
+                markdown_escape(threshold["metric"].as_str().unwrap_or_default()),
+                format_value(&threshold["actual"])
+            );
+        }
+    }
+    md
+}
+
+// Overview, charts of the timeline, thresholds, and per-tag and per-group tables.
+// Charts are inline SVG so the file works offline and can be attached as is.
+fn html_report(summary: &Value, timeline: &[Value]) -> String {
+    let metadata = &summary["metadata"];
+    let metrics = &summary["metrics"];
+    let test_file = test_file_name(summary);
+
+    let mut html = String::new();
+    let _ = writeln!(
+        html,
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>V6 report: {}</title>\n<style>{}</style>\n</head>\n<body>",
+        xml_escape(test_file),
+        HTML_STYLE
+    );
+    let _ = writeln!(
+        html,
+        "<h1>V6 report: {}</h1>\n<p class=\"meta\">{} · {:.1}s · {} VUs · run {}{}</p>",
+        xml_escape(test_file),
+        if metadata["thresholdsPassed"].as_bool().unwrap_or(true) {
+            "<span class=\"ok\">✅ Thresholds passed</span>"
+        } else {
+            "<span class=\"failed\">❌ Thresholds failed</span>"
+        },
+        metadata["testRunDurationMs"].as_f64().unwrap_or_default() / 1000.0,
+        metadata["vus"],
+        xml_escape(metadata["runId"].as_str().unwrap_or_default()),
+        if metadata["aborted"].as_bool().unwrap_or_default() { " · aborted" } else { "" }
+    );
+
+    // Overview cards
+    let mut cards = Vec::new();
+    let requests = &metrics[HTTP_REQS]["values"];
+    if let Some(count) = requests["count"].as_f64() {
+        cards.push(("Requests", format!("{} ({:.1}/s)", count, requests["rate"].as_f64().unwrap_or_default())));
+    }
+    for (label, metric) in [("Failed requests", HTTP_REQ_FAILED), ("Checks passed", CHECKS)] {
+        if let Some(rate) = metrics[metric]["values"]["rate"].as_f64() {
+            cards.push((label, format!("{:.2}%", rate * 100.0)));
+        }
+    }
+    if let Some(p95) = metrics[HTTP_REQ_DURATION]["values"]["p(95)"].as_f64() {
+        cards.push(("p(95) latency", format_duration(p95)));
+    }
+    for (label, metric) in [("Data sent", DATA_SENT), ("Data received", DATA_RECEIVED)] {
+        if let Some(bytes) = metrics[metric]["values"]["count"].as_f64() {
+            cards.push((label, format_bytes(bytes)));
+        }
+    }
+    html.push_str("<div class=\"cards\">\n");
+    for (label, value) in cards {
+        let _ = writeln!(html, "<div class=\"card\"><div>{}</div><strong>{}</strong></div>", label, value);
+    }
+    html.push_str("</div>\n");
+
+    if !timeline.is_empty() {
+        let snapshot_value = |metric: &str, stat: &str| -> ChartPoints {
+            timeline
+                .iter()
+                .map(|snapshot| {
+                    let time = snapshot["time"].as_f64().unwrap_or_default();
+                    (time, snapshot["metrics"][metric][stat].as_f64())
+                })
+                .collect()
+        };
+        let requests_per_second: ChartPoints = snapshot_value(HTTP_REQS, "rate")
+            .into_iter()
+            .map(|(time, rate)| (time, Some(rate.unwrap_or_default())))
+            .collect();
+        let vus: ChartPoints = timeline
+            .iter()
+            .map(|snapshot| (snapshot["time"].as_f64().unwrap_or_default(), snapshot["vus"].as_f64()))
+            .collect();
+        let percent = |points: ChartPoints, failed: fn(f64) -> f64| -> ChartPoints {
+            points
+                .into_iter()
+                .map(|(time, rate)| (time, rate.map(|rate| failed(rate) * 100.0)))
+                .collect()
+        };
+
+        html.push_str("<h2>Over time</h2>\n<div class=\"charts\">\n");
+        html.push_str(&line_chart("Requests per second", &[("http_reqs", requests_per_second)]));
+        html.push_str(&line_chart("Active VUs", &[("VUs", vus)]));
+        let latencies: Vec<(&str, ChartPoints)> = ["med", "p(90)", "p(95)", "p(99)"]
+            .into_iter()
+            .map(|stat| (stat, snapshot_value(HTTP_REQ_DURATION, stat)))
+            .collect();
+        html.push_str(&line_chart("Request duration (ms)", &latencies));
+        html.push_str(&line_chart(
+            "Error rate (%)",
+            &[
+                ("failed requests", percent(snapshot_value(HTTP_REQ_FAILED, "rate"), |rate| rate)),
+                ("failed checks", percent(snapshot_value(CHECKS, "rate"), |rate| 1.0 - rate)),
+            ],
+        ));
+        html.push_str("</div>\n");
+    }
+
+    let thresholds = summary["thresholds"].as_array().map(Vec::as_slice).unwrap_or_default();
+    if !thresholds.is_empty() {
+        html.push_str("<h2>Thresholds</h2>\n<table>\n<tr><th></th><th>Metric</th><th>Threshold</th><th>Actual</th></tr>\n");
+        for threshold in thresholds {
+            let _ = writeln!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td><code>{}</code></td><td class=\"number\">{}</td></tr>",
+                if threshold["ok"].as_bool().unwrap_or(true) { "✅" } else { "❌" },
+                xml_escape(threshold["metric"].as_str().unwrap_or_default()),
+                xml_escape(threshold["threshold"].as_str().unwrap_or_default()),
+                format_value(&threshold["actual"])
+            );
+        }
+        html.push_str("</table>\n");
+    }
+
+    // Requests per value of each tag, from the summary's `{tag:value}` sub-metrics
+    let mut breakdowns: BTreeMap<&str, Vec<(&str, &Value)>> = BTreeMap::new();
+    for (key, values) in metrics[HTTP_REQ_DURATION]["submetrics"].as_object().into_iter().flatten() {
+        if let Some((tag, value)) = key.strip_prefix('{').and_then(|key| key.strip_suffix('}')?.split_once(':')) {
+            breakdowns.entry(tag).or_default().push((value, values));
+        }
+    }
+    for (tag, rows) in breakdowns {
+        let _ = writeln!(
+            html,
+            "<h2>Requests by {}</h2>\n<table>\n<tr><th>{}</th><th>Requests</th><th>Failed</th><th>avg</th><th>med</th><th>p(95)</th><th>max</th></tr>",
+            xml_escape(tag),
+            xml_escape(tag)
+        );
+        for (value, durations) in rows {
+            let key = format!("{{{}:{}}}", tag, value);
+            let count = metrics[HTTP_REQS]["submetrics"][&key]["count"]
+                .as_f64()
+                .map(|count| count.to_string())
+                .unwrap_or_default();
+            let failed = metrics[HTTP_REQ_FAILED]["submetrics"][&key]["rate"]
+                .as_f64()
+                .map(|rate| format!("{:.2}%", rate * 100.0))
+                .unwrap_or_default();
+            let _ = write!(
+                html,
+                "<tr><td>{}</td><td class=\"number\">{}</td><td class=\"number\">{}</td>",
+                xml_escape(value),
+                count,
+                failed
+            );
+            for stat in ["avg", "med", "p(95)", "max"] {
+                let _ = write!(html, "<td class=\"number\">{}</td>", format_value(&durations[stat]));
+            }
+            html.push_str("</tr>\n");
+        }
+        html.push_str("</table>\n");
+    }
+
+    let mut groups = String::new();
+    write_html_groups(&summary["rootGroup"], &mut groups);
+    if !groups.is_empty() {
+        html.push_str("<h2>Groups and checks</h2>\n<table>\n<tr><th>Group</th><th>Check</th><th>Passes</th><th>Fails</th><th>avg</th><th>p(95)</th></tr>\n");
+        html.push_str(&groups);
+        html.push_str("</table>\n");
+    }
+
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+// A row per group with its duration, then a row per check
+fn write_html_groups(group: &Value, html: &mut String) {
+    let path = group["path"].as_str().unwrap_or_default();
+    if !path.is_empty() {
+        let _ = writeln!(
+            html,
+            "<tr class=\"group\"><td>{}</td><td></td><td></td><td></td><td class=\"number\">{}</td><td class=\"number\">{}</td></tr>",
+            xml_escape(path),
+            format_value(&group["duration"]["avg"]),
+            format_value(&group["duration"]["p(95)"])
+        );
+    }
+    for check in group["checks"].as_array().into_iter().flatten() {
+        let fails = check["fails"].as_u64().unwrap_or_default();
+        let _ = writeln!(
+            html,
+            "<tr><td>{}</td><td>{} {}</td><td class=\"number\">{}</td><td class=\"number{}\">{}</td><td></td><td></td></tr>",
+            xml_escape(path),
+            if fails == 0 { "✓" } else { "✗" },
+            xml_escape(check["name"].as_str().unwrap_or_default()),
+            check["passes"],
+            if fails == 0 { "" } else { " failed" },
+            fails
+        );
+    }
+    for child in group["groups"].as_array().into_iter().flatten() {
+        write_html_groups(child, html);
+    }
+}
+
+// SVG line chart of series sharing the axes
+fn line_chart(title: &str, series: &[(&str, ChartPoints)]) -> String {
+    const WIDTH: f64 = 640.0;
+    const HEIGHT: f64 = 240.0;
+    const LEFT: f64 = 56.0;
+    const RIGHT: f64 = 12.0;
+    const TOP: f64 = 12.0;
+    const BOTTOM: f64 = 28.0;
+    const TICKS: usize = 4;
+
+    let points = series.iter().flat_map(|(_, points)| points);
+    let max_time = points.clone().map(|(time, _)| *time).fold(0.0, f64::max).max(1.0);
+    let max_value = points.filter_map(|(_, value)| *value).fold(0.0, f64::max);
+    let max_value = if max_value > 0.0 { max_value * 1.1 } else { 1.0 };
+    let x = |time: f64| LEFT + time / max_time * (WIDTH - LEFT - RIGHT);
+    let y = |value: f64| HEIGHT - BOTTOM - value / max_value * (HEIGHT - TOP - BOTTOM);
+
+    let mut svg = format!(
+        "<figure>\n<figcaption>{}</figcaption>\n<svg viewBox=\"0 0 {} {}\" role=\"img\" aria-label=\"{}\">\n",
+        xml_escape(title),
+        WIDTH,
+        HEIGHT,
+        xml_escape(title)
+    );
+    for tick in 0..=TICKS {
+        let value = max_value * tick as f64 / TICKS as f64;
+        let time = max_time * tick as f64 / TICKS as f64;
+        let label = if max_value < 10.0 { format!("{:.2}", value) } else { format!("{:.0}", value) };
+        let tick_y = y(value);
+        let _ = writeln!(
+            svg,
+            "<line class=\"grid\" x1=\"{}\" x2=\"{}\" y1=\"{:.1}\" y2=\"{:.1}\"/><text x=\"{}\" y=\"{:.1}\" text-anchor=\"end\">{}</text>",
+            LEFT,
+            WIDTH - RIGHT,
+            tick_y,
+            tick_y,
+            LEFT - 6.0,
+            tick_y + 4.0,
+            label
+        );
+        let _ = writeln!(
+            svg,
+            "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\">{:.0}s</text>",
+            x(time),
+            HEIGHT - 8.0,
+            time
+        );
+    }
+    for (index, (label, points)) in series.iter().enumerate() {
+        let mut path = String::new();
+        let mut drawing = false;
+        for (time, value) in points {
+            match value {
+                Some(value) => {
+                    let _ = write!(path, "{}{:.1},{:.1} ", if drawing { "L" } else { "M" }, x(*time), y(*value));
+                    drawing = true;
+                }
+                None => drawing = false,
+            }
+        }
+        let _ = writeln!(
+            svg,
+            "<path d=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"2\"><title>{}</title></path>",
+            path.trim_end(),
+            CHART_COLORS[index % CHART_COLORS.len()],
+            xml_escape(label)
+        );
+    }
+    svg.push_str("</svg>\n<div class=\"legend\">");
+    for (index, (label, _)) in series.iter().enumerate() {
+        let _ = write!(
+            svg,
+            "<span><i style=\"background:{}\"></i>{}</span>",
+            CHART_COLORS[index % CHART_COLORS.len()],
+            xml_escape(label)
+        );
+    }
+    svg.push_str("</div>\n</figure>\n");
+    svg
+}
+
+// `file:///tests/login.ts` -> `login.ts`
+fn test_file_name(summary: &Value) -> &str {
+    summary["metadata"]["mainModule"]
+        .as_str()
+        .and_then(|module| module.rsplit('/').next())
+        .unwrap_or("test")
+}
+
+fn format_value(value: &Value) -> String {
+    match value.as_f64() {
+        Some(value) => format!("{:.2}", value),
+        None => "no samples".to_string(),
+    }
+}
+
+fn markdown_escape(text: &str) -> String {
+    text.replace('|', "\\|")
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A failed and a passed threshold, a failed root check, and a group whose name and
+    // check need escaping
+    fn summary() -> Value {
+        serde_json::json!({
+            "metadata": {
+                "mainModule": "file:///tests/<login> & co.ts",
+                "runId": "run-1",
+                "testRunDurationMs": 12500.0,
+                "vus": 5,
+                "thresholdsPassed": false,
+                "aborted": false,
+            },
+            "metrics": {
+                "http_reqs": { "type": "counter", "values": { "count": 10.0, "rate": 0.8 }, "submetrics": {} },
+            },
+            "thresholds": [
+                { "metric": "http_req_duration{name:a|b}", "threshold": "p(95)<300", "ok": false, "actual": 412.5 },
+                { "metric": "http_reqs", "threshold": "count>5", "ok": true, "actual": 10.0 },
+            ],
+            "rootGroup": {
+                "name": "",
+                "path": "",
+                "duration": null,
+                "checks": [{ "name": "status is 200", "passes": 9, "fails": 1 }],
+                "groups": [{
+                    "name": "<login> & co",
+                    "path": "::<login> & co",
+                    "duration": { "avg": 12.0, "p(95)": 20.0 },
+                    "checks": [{ "name": "token \"ok\"", "passes": 10, "fails": 0 }],
+                    "groups": [],
+                }],
+            },
+        })
+    }
+
+    #[test]
+    fn junit_counts_tests_and_failures_per_suite() {
+        let xml = junit_report(&summary());
+
+        assert!(
+            xml.contains("<testsuites name=\"v6\" tests=\"4\" failures=\"2\" time=\"12.500\">"),
+            "{}",
+            xml
+        );
+        assert!(xml.contains(
+            "<testsuite name=\"thresholds\" tests=\"2\" failures=\"1\" time=\"12.500\">"
+        ));
+        assert!(
+            xml.contains("<testsuite name=\"checks\" tests=\"2\" failures=\"1\" time=\"12.500\">")
+        );
+        assert!(xml.contains("<failure type=\"check\" message=\"1 of 10 failed\"/>"));
+    }
+
+    #[test]
+    fn junit_escapes_names_and_messages() {
+        let xml = junit_report(&summary());
+
+        assert!(
+            xml.contains(
+                "<testcase name=\"p(95)&lt;300\" classname=\"thresholds.http_req_duration{name:a|b}\">"
+            ),
+            "{}",
+            xml
+        );
+        assert!(xml.contains(
+            "<failure type=\"threshold\" message=\"p(95)&lt;300 on http_req_duration{name:a|b} failed with 412.50\"/>"
+        ));
+        assert!(xml.contains("<testcase name=\"token &quot;ok&quot;\" classname=\"checks.::&lt;login&gt; &amp; co\"/>"));
+        assert!(!xml.contains("<login>"));
+    }
+
+    #[test]
+    fn markdown_escapes_pipes_in_table_cells() {
+        let md = markdown_report(&summary());
+
+        assert!(
+            md.starts_with(
+                "## V6 results: <login> & co.ts\n\n❌ Thresholds failed · 12.5s · 5 VUs\n"
+            ),
+            "{}",
+            md
+        );
+        assert!(
+            md.contains("| ❌ | `p(95)<300` | http_req_duration{name:a\\|b} | 412.50 |"),
+            "{}",
+            md
+        );
+        assert!(md.contains("| ✅ | `count>5` | http_reqs | 10.00 |"));
+        assert_eq!(markdown_escape("a|b||c"), "a\\|b\\|\\|c");
+    }
+
+    #[test]
+    fn xml_escape_covers_markup_and_quotes() {
+        assert_eq!(
+            xml_escape(r#"<a href="x">'&'</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;&apos;&amp;&apos;&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn parses_report_specs() {
+        assert!(
+            matches!(ReportConfig::parse("junit=results.xml").unwrap(), ReportConfig::Junit(path) if path == "results.xml")
+        );
+        assert!(
+            matches!(ReportConfig::parse("md=summary.md").unwrap(), ReportConfig::Markdown(path) if path == "summary.md")
+        );
+        assert!(
+            matches!(ReportConfig::parse("markdown=a=b.md").unwrap(), ReportConfig::Markdown(path) if path == "a=b.md")
+        );
+        assert!(
+            matches!(ReportConfig::parse("html=report.html").unwrap(), ReportConfig::Html(path) if path == "report.html")
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_report_specs() {
+        for (spec, error) in [
+            (
+                "junit",
+                "Invalid report \"junit\": expected <type>=<file>, e.g. junit=results.xml",
+            ),
+            ("junit=", "Invalid report \"junit=\": missing file"),
+            (
+                "pdf=report.pdf",
+                "Unknown report type \"pdf\" (expected junit, markdown or html)",
+            ),
+            (
+                "=report.xml",
+                "Unknown report type \"\" (expected junit, markdown or html)",
+            ),
+        ] {
+            assert_eq!(ReportConfig::parse(spec).unwrap_err().to_string(), error);
+        }
+    }
+
+    #[test]
+    fn html_escapes_the_test_file_and_group_names() {
+        let html = html_report(&summary(), &[]);
+
+        assert!(
+            html.contains("<title>V6 report: &lt;login&gt; &amp; co.ts</title>"),
+            "{}",
+            html
+        );
+        assert!(html.contains("<h1>V6 report: &lt;login&gt; &amp; co.ts</h1>"));
+        assert!(html.contains(
+            "<tr class=\"group\"><td>::&lt;login&gt; &amp; co</td><td></td><td></td><td></td><td class=\"number\">12.00</td><td class=\"number\">20.00</td></tr>"
+        ));
+        assert!(html.contains("<td>✓ token &quot;ok&quot;</td>"));
+        assert!(html.contains("<td><code>p(95)&lt;300</code></td>"));
+        assert!(!html.contains("<login>"));
+        // No timeline, no charts
+        assert!(!html.contains("<svg"));
+    }
+}
@@ -1,10 +1,20 @@
+import { defineConfig, sleep } from "ext:v6/modules/index.js";
+import { check, expect } from "ext:v6/modules/checks.js";
+import { fetch } from "ext:v6/modules/http.js";
+import "ext:v6/modules/data.js";
+import "ext:v6/modules/encoding.js";
+import "ext:v6/modules/cjs.js";
+import "ext:v6/modules/metrics.js";
+import { group, runIteration } from "ext:v6/modules/execution.js";
+
 globalThis.setTimeout = async (delay) => {
   await core.ops.op_set_timeout(delay);
 };
 
-globalThis.sleep = async (delay) => {
-  await core.ops.op_set_timeout(delay);
-};
+globalThis.sleep = sleep;
+globalThis.check = check;
+globalThis.expect = expect;
+globalThis.group = group;
 
 const { core } = Deno;
 
@@ -22,26 +32,63 @@ globalThis.console = {
 };
 
 globalThis.currentConfig = null;
-function noop() {}
-globalThis.defineConfig = (config) => {
-  if (!config.iteration || typeof config.iteration !== "function") {
-    throw new Error("Invalid iteration definition. Expected a function");
-  }
-  globalThis.currentConfig = {
-    setup: config.setup || noop,
-    teardown: config.teardown || noop,
-    iteration: config.iteration,
-    iterations: config.iterations || 1,
-    vus: config.vus || 1,
-    duration: config.duration || 10,
-    timeout: config.timeout || 30,
-  };
-};
+globalThis.defineConfig = defineConfig;
+
+// Resolves the config from the entry module's exports when the script did not
+// call the defineConfig global: `export default defineConfig(...)`,
+// `export default { ... }` or `export const options` with an exported iteration.
+Object.defineProperty(globalThis, "__v6ResolveModuleConfig", {
+  value: (exports) => {
+    if (globalThis.currentConfig) {
+      return;
+    }
+    const defaultExport = exports.default;
+    if (exports.options && typeof exports.options === "object") {
+      const iteration = exports.options.iteration ||
+        (typeof defaultExport === "function" ? defaultExport : undefined);
+      globalThis.defineConfig({ ...exports.options, iteration });
+      return;
+    }
+    if (defaultExport && typeof defaultExport === "object") {
+      globalThis.defineConfig(defaultExport);
+      return;
+    }
+    throw new Error(
+      "No config found. Call defineConfig(...), `export default defineConfig(...)` or `export const options`",
+    );
+  },
+  enumerable: false,
+});
+
+// Called by the engine for every iteration; `vu` is 1-based
+Object.defineProperty(globalThis, "__v6RunIteration", {
+  value: (vu, iteration) =>
+    runIteration(vu, iteration, () => globalThis.currentConfig.iteration()),
+  enumerable: false,
+});
+
+// Called by the engine with the end-of-test summary as JSON. Resolves to the JSON of
+// the { path: content } map returned by the config's handleSummary, or null without one.
+Object.defineProperty(globalThis, "__v6HandleSummary", {
+  value: async (json) => {
+    const handleSummary = globalThis.currentConfig?.handleSummary;
+    if (typeof handleSummary !== "function") {
+      return null;
+    }
+    const outputs = (await handleSummary(JSON.parse(json))) ?? {};
+    return JSON.stringify(Object.fromEntries(
+      Object.entries(outputs).map(([path, content]) => [
+        path,
+        typeof content === "string" ? content : JSON.stringify(content, null, 2),
+      ]),
+    ));
+  },
+  enumerable: false,
+});
+
 // fetch
 
-globalThis.fetch = (input, init) => {
-  return core.ops.op_fetch(input, init);
-};
+globalThis.fetch = fetch;
 
 
 
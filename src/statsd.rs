@@ -0,0 +1,208 @@
+use anyhow::Result;
+use std::net::UdpSocket;
+
+use crate::metrics::{MetricKind, Tags};
+use crate::outputs::{Output, Sample};
+
+pub const DEFAULT_NAMESPACE: &str = "v6.";
+// Keeps packets within the MTU of most networks
+const MAX_PACKET_SIZE: usize = 1432;
+
+/// How tags are appended to StatsD lines (`tags=` option)
+#[derive(Clone, Copy, Debug)]
+pub enum StatsdTagFormat {
+    /// `name:1|c|#tag:value,...` for the Datadog agent
+    Datadog,
+    /// `name,tag=value,...:1|c` for Telegraf
+    Influx,
+    /// Plain StatsD, without tags
+    None,
+}
+
+impl StatsdTagFormat {
+    pub fn parse(format: &str) -> Option<Self> {
+        match format {
+            "datadog" | "dogstatsd" => Some(Self::Datadog),
+            "influx" | "telegraf" => Some(Self::Influx),
+            "none" => Some(Self::None),
+            _ => None,
+        }
+    }
+}
+
+/// `--out statsd=<host>:<port>`: sends every sample over UDP, counters as `c`, gauges as `g`
+/// and trends as `ms`. Rates count their passes and fails in `<name>.passes` and `<name>.fails`.
+pub struct StatsdOutput {
+    socket: UdpSocket,
+    namespace: String,
+    tag_format: StatsdTagFormat,
+    packet: String,
+}
+
+impl StatsdOutput {
+    pub fn connect(address: &str, namespace: &str, tag_format: StatsdTagFormat) -> Result<Self> {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket
+            .connect(address)
+            .map_err(|e| anyhow::anyhow!("Failed to resolve StatsD address '{}': {}", address, e))?;
+        Ok(Self {
+            socket,
+            namespace: namespace.to_string(),
+            tag_format,
+            packet: String::new(),
+        })
+    }
+
+    fn line(&self, sample: &Sample) -> String {
+        let (suffix, value, kind) = match sample.kind {
+            MetricKind::Counter => ("", sample.value, "c"),
+            MetricKind::Gauge => ("", sample.value, "g"),
+            MetricKind::Rate if sample.value != 0.0 => (".passes", 1.0, "c"),
+            MetricKind::Rate => (".fails", 1.0, "c"),
+            MetricKind::Trend => ("", sample.value, "ms"),
+        };
+        let name = format!("{}{}{}", self.namespace, sanitize(&sample.metric), suffix);
+        let tags = sample.series_tags();
+        match self.tag_format {
+            StatsdTagFormat::Datadog if !tags.is_empty() => {
+                format!("{}:{}|{}|#{}", name, value, kind, join_tags(&tags, ':'))
+            }
+            StatsdTagFormat::Influx if !tags.is_empty() => {
+                format!("{},{}:{}|{}", name, join_tags(&tags, '='), value, kind)
+            }
+            _ => format!("{}:{}|{}", name, value, kind),
+        }
+    }
+
+    // UDP is fire-and-forget: a dropped packet only loses its samples
+    fn send(&mut self) {
+        if !self.packet.is_empty() {
+            if let Err(e) = self.socket.send(self.packet.as_bytes()) {
+                eprintln!("⚠️  StatsD packet failed: {}", e);
+            }
+            self.packet.clear();
+        }
+    }
+}
+
+impl Output for StatsdOutput {
+    fn write(&mut self, samples: &[Sample]) -> Result<()> {
+        for sample in samples {
+            let line = self.line(sample);
+            if !self.packet.is_empty() && self.packet.len() + 1 + line.len() > MAX_PACKET_SIZE {
+                self.send();
+            }
+            if !self.packet.is_empty() {
+                self.packet.push('\n');
+            }
+            self.packet.push_str(&line);
+        }
+        self.send();
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.send();
+        Ok(())
+    }
+}
+
+fn join_tags(tags: &Tags, separator: char) -> String {
+    tags.iter()
+        .map(|(tag, value)| format!("{}{}{}", sanitize(tag), separator, sanitize(value)))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+// Characters delimiting names, values and tags in StatsD lines, and whitespace
+fn sanitize(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| if matches!(c, ':' | '|' | '@' | '#' | ',' | '=') || c.is_whitespace() { '_' } else { c })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::SystemTime;
+
+    fn sample(metric: &str, kind: MetricKind, value: f64, tags: &[(&str, &str)]) -> Sample {
+        Sample {
+            timestamp: SystemTime::now(),
+            metric: metric.to_string(),
+            kind,
+            value,
+            tags: tags
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+        }
+    }
+
+    // UDP sockets connect without anyone listening
+    fn output(tag_format: StatsdTagFormat) -> StatsdOutput {
+        StatsdOutput::connect("127.0.0.1:8125", DEFAULT_NAMESPACE, tag_format).unwrap()
+    }
+
+    #[test]
+    fn sanitizes_delimiters_and_whitespace() {
+        assert_eq!(sanitize("http_req_duration"), "http_req_duration");
+        assert_eq!(sanitize("a:b|c@d#e,f=g"), "a_b_c_d_e_f_g");
+        assert_eq!(sanitize("get item\t\n"), "get_item__");
+        // Dots separate StatsD name segments and are kept
+        assert_eq!(sanitize("api.v2"), "api.v2");
+    }
+
+    #[test]
+    fn formats_each_kind_of_metric() {
+        let output = output(StatsdTagFormat::None);
+        assert_eq!(
+            output.line(&sample("http_reqs", MetricKind::Counter, 1.0, &[])),
+            "v6.http_reqs:1|c"
+        );
+        assert_eq!(
+            output.line(&sample("vus", MetricKind::Gauge, 10.0, &[])),
+            "v6.vus:10|g"
+        );
+        assert_eq!(
+            output.line(&sample("http_req_duration", MetricKind::Trend, 12.5, &[])),
+            "v6.http_req_duration:12.5|ms"
+        );
+        assert_eq!(
+            output.line(&sample("checks", MetricKind::Rate, 1.0, &[])),
+            "v6.checks.passes:1|c"
+        );
+        assert_eq!(
+            output.line(&sample("checks", MetricKind::Rate, 0.0, &[])),
+            "v6.checks.fails:1|c"
+        );
+    }
+
+    #[test]
+    fn appends_tags_in_the_configured_format() {
+        let sample = sample(
+            "http_req_duration",
+            MetricKind::Trend,
+            12.5,
+            &[
+                ("name", "get item"),
+                ("status", "200"),
+                ("url", "/items/1"),
+                ("vu", "1"),
+            ],
+        );
+        assert_eq!(
+            output(StatsdTagFormat::Datadog).line(&sample),
+            "v6.http_req_duration:12.5|ms|#name:get_item,status:200"
+        );
+        assert_eq!(
+            output(StatsdTagFormat::Influx).line(&sample),
+            "v6.http_req_duration,name=get_item,status=200:12.5|ms"
+        );
+        assert_eq!(
+            output(StatsdTagFormat::None).line(&sample),
+            "v6.http_req_duration:12.5|ms"
+        );
+    }
+}
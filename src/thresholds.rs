@@ -0,0 +1,558 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, time::Duration};
+
+use crate::cli::parse_duration;
+use crate::metrics::{Aggregation, MetricsRegistry, Tags};
+
+/// Exit code of `v6 run` when at least one threshold fails
+pub const THRESHOLDS_FAILED_EXIT_CODE: u8 = 99;
+
+/// `thresholds` of the config: metric (optionally with `{tag:value,...}`) to expressions
+pub type ThresholdsConfig = BTreeMap<String, Vec<ThresholdDefinition>>;
+
+/// `"p(95)<300"`, or `{ threshold: "p(95)<300", abortOnFail: true, delayAbortEval: "30s" }`
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum ThresholdDefinition {
+    Expression(String),
+    #[serde(rename_all = "camelCase")]
+    Options {
+        threshold: String,
+        #[serde(default)]
+        abort_on_fail: bool,
+        /// Seconds as a number, or a duration such as "500ms", "30s", "5m"
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        delay_abort_eval: Option<serde_json::Value>,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Operator {
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Equal,
+    NotEqual,
+}
+
+impl Operator {
+    fn apply(self, left: f64, right: f64) -> bool {
+        match self {
+            Self::Less => left < right,
+            Self::LessOrEqual => left <= right,
+            Self::Greater => left > right,
+            Self::GreaterOrEqual => left >= right,
+            Self::Equal => left == right,
+            Self::NotEqual => left != right,
+        }
+    }
+}
+
+/// One expression such as `p(95)<300`, on a metric or tagged sub-metric
+#[derive(Clone)]
+pub struct Threshold {
+    /// The config key, e.g. `http_req_duration{name:login}`
+    pub key: String,
+    /// The expression as written in the config
+    pub source: String,
+    pub metric: String,
+    pub tags: Tags,
+    aggregation: Aggregation,
+    operator: Operator,
+    value: f64,
+    /// Stop the test as soon as this threshold fails while it runs
+    pub abort_on_fail: bool,
+    /// Time since the start of the test before `abort_on_fail` is evaluated
+    pub delay_abort_eval: Duration,
+}
+
+impl Threshold {
+    fn from_definition(key: &str, definition: &ThresholdDefinition) -> Result<Self> {
+        match definition {
+            ThresholdDefinition::Expression(source) => Self::parse(key, source),
+            ThresholdDefinition::Options {
+                threshold,
+                abort_on_fail,
+                delay_abort_eval,
+            } => {
+                let delay_abort_eval = match delay_abort_eval {
+                    None => Duration::ZERO,
+                    Some(delay) => parse_delay(delay).ok_or_else(|| {
+                        anyhow::anyhow!("Invalid delayAbortEval {} for threshold \"{}\" on \"{}\"", delay, threshold, key)
+                    })?,
+                };
+                Ok(Self {
+                    abort_on_fail: *abort_on_fail,
+                    delay_abort_eval,
+                    ..Self::parse(key, threshold)?
+                })
+            }
+        }
+    }
+
+    fn parse(key: &str, source: &str) -> Result<Self> {
+        let (metric, tags) = parse_metric_key(key)?;
+        let invalid = || anyhow::anyhow!("Invalid threshold \"{}\" on \"{}\"", source, key);
+
+        let operator_start = source.find(['<', '>', '=', '!']).ok_or_else(invalid)?;
+        let (aggregation, rest) = source.split_at(operator_start);
+        let (operator, value) = [
+            ("<=", Operator::LessOrEqual),
+            (">=", Operator::GreaterOrEqual),
+            ("===", Operator::Equal),
+            ("==", Operator::Equal),
+            ("!=", Operator::NotEqual),
+            ("<", Operator::Less),
+            (">", Operator::Greater),
+        ]
+        .into_iter()
+        .find_map(|(symbol, operator)| rest.strip_prefix(symbol).map(|value| (operator, value)))
+        .ok_or_else(invalid)?;
+
+        Ok(Self {
+            key: key.to_string(),
+            source: source.to_string(),
+            metric,
+            tags,
+            aggregation: Aggregation::parse(aggregation.trim()).ok_or_else(invalid)?,
+            operator,
+            value: value.trim().parse().ok().filter(|value: &f64| value.is_finite()).ok_or_else(invalid)?,
+            abort_on_fail: false,
+            delay_abort_eval: Duration::ZERO,
+        })
+    }
+}
+
+/// Result of one threshold at the end of the run
+pub struct ThresholdResult<'a> {
+    pub threshold: &'a Threshold,
+    /// `None` when the metric got no samples
+    pub actual: Option<f64>,
+    pub passed: bool,
+}
+
+/// Parse every threshold of the config, failing on the first invalid one
+pub fn parse_thresholds(config: &ThresholdsConfig) -> Result<Vec<Threshold>> {
+    config
+        .iter()
+        .flat_map(|(key, definitions)| {
+            definitions
+                .iter()
+                .map(move |definition| Threshold::from_definition(key, definition))
+        })
+        .collect()
+}
+
+/// Make the registry aggregate the tagged sub-metrics thresholds refer to.
+/// Must be called once custom metrics are declared and before the test starts.
+pub fn track_thresholds(registry: &mut MetricsRegistry, thresholds: &[Threshold]) -> Result<()> {
+    for threshold in thresholds {
+        registry
+            .validate_aggregation(&threshold.metric, threshold.aggregation)
+            .and_then(|_| registry.track_submetric(&threshold.metric, &threshold.tags))
+            .map_err(|e| anyhow::anyhow!("Threshold \"{}\" on \"{}\": {}", threshold.source, threshold.key, e))?;
+    }
+    Ok(())
+}
+
+/// Evaluate thresholds against the aggregated metrics. Metrics without samples pass.
+pub fn evaluate_thresholds<'a>(
+    registry: &MetricsRegistry,
+    thresholds: &'a [Threshold],
+    elapsed_secs: f64,
+) -> Vec<ThresholdResult<'a>> {
+    thresholds
+        .iter()
+        .map(|threshold| {
+            let actual = registry.aggregate(&threshold.metric, &threshold.tags, threshold.aggregation, elapsed_secs);
+            let passed = actual.is_none_or(|actual| threshold.operator.apply(actual, threshold.value));
+            ThresholdResult { threshold, actual, passed }
+        })
+        .collect()
+}
+
+/// First `abort_on_fail` threshold failing at this point of the run, once its delay has passed.
+/// Metrics without samples yet don't abort.
+pub fn find_aborting_threshold<'a>(
+    registry: &MetricsRegistry,
+    thresholds: &'a [Threshold],
+    elapsed: Duration,
+) -> Option<&'a Threshold> {
+    thresholds
+        .iter()
+        .filter(|threshold| threshold.abort_on_fail && elapsed >= threshold.delay_abort_eval)
+        .find(|threshold| {
+            registry
+                .aggregate(&threshold.metric, &threshold.tags, threshold.aggregation, elapsed.as_secs_f64())
+                .is_some_and(|actual| !threshold.operator.apply(actual, threshold.value))
+        })
+}
+
+/// Threshold results as in `--summary-export`
+pub fn thresholds_json(results: &[ThresholdResult<'_>]) -> serde_json::Value {
+    results
+        .iter()
+        .map(|result| {
+            serde_json::json!({
+                "metric": result.threshold.key,
+                "threshold": result.threshold.source,
+                "ok": result.passed,
+                "actual": result.actual,
+                "abortOnFail": result.threshold.abort_on_fail,
+            })
+        })
+        .collect()
+}
+
+/// Print pass/fail per threshold; returns whether all passed
+pub fn print_threshold_results(results: &[ThresholdResult<'_>]) -> bool {
+    if results.is_empty() {
+        return true;
+    }
+
+    println!("{}", "─".repeat(50));
+    println!("🎯 Thresholds");
+    for result in results {
+        let actual = match result.actual {
+            Some(actual) => format!("{:.2}", actual),
+            None => "no samples".to_string(),
+        };
+        println!(
+            "  {} {}: {} ({})",
+            if result.passed { "✅" } else { "❌" },
+            result.threshold.key,
+            result.threshold.source,
+            actual
+        );
+    }
+    results.iter().all(|result| result.passed)
+}
+
+// 30 -> 30s; "500ms", "30s", "5m", "1h"
+fn parse_delay(delay: &serde_json::Value) -> Option<Duration> {
+    if let Some(seconds) = delay.as_f64() {
+        return Duration::try_from_secs_f64(seconds).ok();
+    }
+    parse_duration(delay.as_str()?)
+}
+
+// `http_req_duration{name:login,status:200}` -> ("http_req_duration", {name: login, status: 200})
+fn parse_metric_key(key: &str) -> Result<(String, Tags)> {
+    let key = key.trim();
+    let (metric, rest) = match key.split_once('{') {
+        Some((metric, rest)) => (metric.trim(), Some(rest)),
+        None => (key, None),
+    };
+    if metric.is_empty() {
+        return Err(anyhow::anyhow!("Invalid threshold metric \"{}\": missing metric name", key));
+    }
+    let Some(rest) = rest else {
+        return Ok((metric.to_string(), Tags::new()));
+    };
+    let tag_list = rest
+        .strip_suffix('}')
+        .filter(|tag_list| !tag_list.contains(['{', '}']))
+        .ok_or_else(|| anyhow::anyhow!("Invalid threshold metric \"{}\": expected one {{...}} at the end", key))?;
+
+    let mut tags = Tags::new();
+    for pair in tag_list.split(',').filter(|pair| !pair.trim().is_empty()) {
+        let (tag, value) = pair
+            .split_once(':')
+            .filter(|(tag, _)| !tag.trim().is_empty())
+            .ok_or_else(|| anyhow::anyhow!("Invalid tag \"{}\" in threshold metric \"{}\"", pair, key))?;
+        tags.insert(
+            tag.trim().to_string(),
+            value.trim().trim_matches(|c| c == '"' || c == '\'').to_string(),
+        );
+    }
+    Ok((metric.to_string(), tags))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::{CHECKS, HTTP_REQ_DURATION, HTTP_REQS};
+
+    fn tags(pairs: &[(&str, &str)]) -> Tags {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn parses_every_operator() {
+        for (source, operator) in [
+            ("avg<200", Operator::Less),
+            ("avg<=200", Operator::LessOrEqual),
+            ("avg>200", Operator::Greater),
+            ("avg>=200", Operator::GreaterOrEqual),
+            ("avg==200", Operator::Equal),
+            ("avg===200", Operator::Equal),
+            ("avg!=200", Operator::NotEqual),
+        ] {
+            let threshold = Threshold::parse("http_req_duration", source).unwrap();
+            assert_eq!(threshold.operator, operator, "{}", source);
+            assert_eq!(threshold.aggregation, Aggregation::Avg, "{}", source);
+            assert_eq!(threshold.value, 200.0, "{}", source);
+        }
+    }
+
+    #[test]
+    fn parses_aggregations() {
+        for (source, aggregation, value) in [
+            ("p(95)<300", Aggregation::Percentile(95.0), 300.0),
+            ("p(99.9)<1500.5", Aggregation::Percentile(99.9), 1500.5),
+            ("avg<200", Aggregation::Avg, 200.0),
+            ("med<100", Aggregation::Med, 100.0),
+            ("rate<0.01", Aggregation::Rate, 0.01),
+            ("count>=10", Aggregation::Count, 10.0),
+        ] {
+            let threshold = Threshold::parse("http_req_duration", source).unwrap();
+            assert_eq!(threshold.aggregation, aggregation, "{}", source);
+            assert_eq!(threshold.value, value, "{}", source);
+        }
+    }
+
+    #[test]
+    fn ignores_whitespace() {
+        let threshold = Threshold::parse(
+            " http_req_duration { status : 200 , name : 'login' } ",
+            " p( 95 ) <= 300 ",
+        )
+        .unwrap();
+        assert_eq!(threshold.metric, "http_req_duration");
+        assert_eq!(
+            threshold.tags,
+            tags(&[("status", "200"), ("name", "login")])
+        );
+        assert_eq!(threshold.aggregation, Aggregation::Percentile(95.0));
+        assert_eq!(threshold.operator, Operator::LessOrEqual);
+        assert_eq!(threshold.value, 300.0);
+    }
+
+    #[test]
+    fn parses_tagged_metric_keys() {
+        assert_eq!(
+            parse_metric_key("http_req_duration").unwrap(),
+            ("http_req_duration".to_string(), Tags::new())
+        );
+        assert_eq!(
+            parse_metric_key("http_req_duration{status:200}").unwrap(),
+            ("http_req_duration".to_string(), tags(&[("status", "200")]))
+        );
+        assert_eq!(
+            parse_metric_key("http_req_duration{name:\"login\",status:200}").unwrap(),
+            (
+                "http_req_duration".to_string(),
+                tags(&[("name", "login"), ("status", "200")])
+            )
+        );
+        // The value keeps anything after the first colon
+        assert_eq!(
+            parse_metric_key("http_reqs{url:https://example.com/}").unwrap(),
+            (
+                "http_reqs".to_string(),
+                tags(&[("url", "https://example.com/")])
+            )
+        );
+        assert_eq!(
+            parse_metric_key("checks{}").unwrap(),
+            ("checks".to_string(), Tags::new())
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_metric_keys() {
+        for key in [
+            "",
+            "{status:200}",
+            "http_req_duration{",
+            "http_req_duration{status:200",
+            "http_req_duration{status:200}x",
+            "http_req_duration{status}",
+            "http_req_duration{:200}",
+            "http_req_duration{status:200}{name:login}",
+        ] {
+            assert!(parse_metric_key(key).is_err(), "{:?}", key);
+            assert!(Threshold::parse(key, "avg<200").is_err(), "{:?}", key);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_expressions() {
+        for source in [
+            "",
+            "avg",
+            "<200",
+            "avg200",
+            "avg<",
+            "avg=200",
+            "avg=<200",
+            "avg<<200",
+            "avg<200ms",
+            "avg<NaN",
+            "avg<inf",
+            "average<200",
+            "p95<200",
+            "p(95<200",
+            "p(abc)<200",
+            "p(101)<200",
+            "p(-1)<200",
+        ] {
+            assert!(
+                Threshold::parse("http_req_duration", source).is_err(),
+                "{:?}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn evaluates_thresholds_against_the_registry() {
+        let config: ThresholdsConfig = serde_json::from_value(serde_json::json!({
+            "http_req_duration": ["p(95)<300", "avg>=100"],
+            "http_req_duration{status:200}": ["max<150"],
+            "http_reqs": ["count==3"],
+            // No samples: passes, with no actual value
+            "checks": ["rate>0.9"],
+        }))
+        .unwrap();
+        let thresholds = parse_thresholds(&config).unwrap();
+        let mut registry = MetricsRegistry::default();
+        track_thresholds(&mut registry, &thresholds).unwrap();
+
+        for (duration, status) in [(100.0, "200"), (120.0, "200"), (500.0, "500")] {
+            registry
+                .add(HTTP_REQ_DURATION, duration, &tags(&[("status", status)]))
+                .unwrap();
+            registry
+                .add(HTTP_REQS, 1.0, &tags(&[("status", status)]))
+                .unwrap();
+        }
+
+        let results = evaluate_thresholds(&registry, &thresholds, 1.0);
+        let outcome = |key: &str, source: &str| {
+            let result = results
+                .iter()
+                .find(|result| result.threshold.key == key && result.threshold.source == source)
+                .unwrap();
+            (result.passed, result.actual)
+        };
+        assert!(!outcome("http_req_duration", "p(95)<300").0);
+        assert!(outcome("http_req_duration", "avg>=100").0);
+        assert!(outcome("http_req_duration{status:200}", "max<150").0);
+        assert_eq!(outcome("http_reqs", "count==3"), (true, Some(3.0)));
+        assert_eq!(outcome(CHECKS, "rate>0.9"), (true, None));
+    }
+
+    #[test]
+    fn rejects_aggregations_the_metric_does_not_have() {
+        let thresholds = vec![Threshold::parse("http_reqs", "p(95)<300").unwrap()];
+        assert!(track_thresholds(&mut MetricsRegistry::default(), &thresholds).is_err());
+    }
+
+    #[test]
+    fn parses_the_options_form() {
+        let config: ThresholdsConfig = serde_json::from_value(serde_json::json!({
+            "http_req_duration": [
+                "p(95)<300",
+                { "threshold": "avg<200" },
+                { "threshold": "max<1000", "abortOnFail": true, "delayAbortEval": "30s" },
+                { "threshold": "med<100", "abortOnFail": true, "delayAbortEval": 1.5 },
+            ],
+        }))
+        .unwrap();
+        let thresholds = parse_thresholds(&config).unwrap();
+        let options: Vec<_> = thresholds
+            .iter()
+            .map(|threshold| {
+                (
+                    threshold.source.as_str(),
+                    threshold.abort_on_fail,
+                    threshold.delay_abort_eval,
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            options,
+            [
+                ("p(95)<300", false, Duration::ZERO),
+                ("avg<200", false, Duration::ZERO),
+                ("max<1000", true, Duration::from_secs(30)),
+                ("med<100", true, Duration::from_millis(1500)),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_an_invalid_delay_abort_eval() {
+        for delay in [
+            serde_json::json!("soon"),
+            serde_json::json!(-1),
+            serde_json::json!(true),
+        ] {
+            let config: ThresholdsConfig = serde_json::from_value(serde_json::json!({
+                "http_req_duration": [{ "threshold": "avg<200", "abortOnFail": true, "delayAbortEval": delay }],
+            }))
+            .unwrap();
+            assert!(parse_thresholds(&config).is_err(), "{}", delay);
+        }
+    }
+
+    #[test]
+    fn parses_delays_as_seconds_or_durations() {
+        for (delay, expected) in [
+            (serde_json::json!(30), Duration::from_secs(30)),
+            (serde_json::json!(0.25), Duration::from_millis(250)),
+            (serde_json::json!("500ms"), Duration::from_millis(500)),
+            (serde_json::json!("30s"), Duration::from_secs(30)),
+            (serde_json::json!("5m"), Duration::from_secs(300)),
+            (serde_json::json!("1h"), Duration::from_secs(3600)),
+        ] {
+            assert_eq!(parse_delay(&delay), Some(expected), "{}", delay);
+        }
+        for delay in [
+            serde_json::json!(-1),
+            serde_json::json!("30"),
+            serde_json::json!("30d"),
+            serde_json::json!(null),
+            serde_json::json!([30]),
+        ] {
+            assert_eq!(parse_delay(&delay), None, "{}", delay);
+        }
+    }
+
+    #[test]
+    fn aborts_on_the_first_failing_abort_on_fail_threshold_after_its_delay() {
+        let config: ThresholdsConfig = serde_json::from_value(serde_json::json!({
+            // Failing, but only reported at the end
+            "http_req_duration": [
+                "avg<100",
+                { "threshold": "max<300", "abortOnFail": true, "delayAbortEval": "10s" },
+                { "threshold": "min<1000", "abortOnFail": true },
+            ],
+            // No samples yet
+            "checks": [{ "threshold": "rate>0.9", "abortOnFail": true }],
+        }))
+        .unwrap();
+        let thresholds = parse_thresholds(&config).unwrap();
+        let mut registry = MetricsRegistry::default();
+        track_thresholds(&mut registry, &thresholds).unwrap();
+
+        let aborting = |registry: &MetricsRegistry, elapsed: u64| {
+            find_aborting_threshold(registry, &thresholds, Duration::from_secs(elapsed))
+                .map(|threshold| threshold.source.clone())
+        };
+        assert_eq!(aborting(&registry, 20), None);
+
+        registry
+            .add(HTTP_REQ_DURATION, 500.0, &Tags::new())
+            .unwrap();
+        assert_eq!(aborting(&registry, 5), None);
+        assert_eq!(aborting(&registry, 10).as_deref(), Some("max<300"));
+    }
+}
@@ -0,0 +1,302 @@
+// Type declarations for V6 test scripts.
+// Generated by `v6 types`. Reference it from a test file with:
+//   /// <reference path="./v6.d.ts" />
+// V6 provides its own `fetch`, so compile with `"lib": ["esnext"]` (no "dom").
+
+declare namespace V6 {
+  type HttpMethod = "GET" | "POST" | "PUT" | "DELETE" | "PATCH" | "HEAD";
+
+  /** Options accepted by `fetch` (a subset of WHATWG `RequestInit`). */
+  interface FetchInit {
+    method?: HttpMethod | Lowercase<HttpMethod>;
+    headers?: Record<string, string>;
+    body?: string;
+    /** Extra tags for this request's metrics. */
+    tags?: Tags;
+    /** Groups requests in metrics; replaces the `url` tag. Defaults to the template of a `url` tagged URL. */
+    name?: string;
+  }
+
+  /** URL built with the `url` template tag from "v6/http". */
+  interface TaggedUrl {
+    readonly url: string;
+    /** The template, with `${}` in place of every value. */
+    readonly name: string;
+  }
+
+  type RequestInput = string | TaggedUrl;
+
+  /** Plain object returned by `fetch`. This is NOT a WHATWG `Response`. */
+  interface FetchResponse {
+    status: number;
+    ok: boolean;
+    statusText: string;
+    headers: Record<string, string>;
+    /** Response body as text. */
+    body: string;
+    timings: RequestTimings;
+  }
+
+  /** Request phases in milliseconds. Connection setup phases are 0 on reused connections. */
+  interface RequestTimings {
+    /** Waiting before a connection started opening. */
+    blocked: number;
+    dnsLookup: number;
+    /** TCP connect. */
+    connecting: number;
+    tlsHandshaking: number;
+    /** Writing the request body; 0 for requests without one. */
+    sending: number;
+    /** Time to first byte once the request was sent. */
+    waiting: number;
+    receiving: number;
+    /** `sending + waiting + receiving`. */
+    duration: number;
+  }
+
+  /**
+   * A threshold expression such as `"p(95)<300"`, or an object that can also
+   * stop the test as soon as the threshold fails while it runs.
+   */
+  type ThresholdDefinition =
+    | string
+    | {
+        threshold: string;
+        /** Stop all VUs when this threshold fails during the run. Default: false. */
+        abortOnFail?: boolean;
+        /** Time before the threshold is evaluated for aborting, e.g. `"30s"` or `30`. Default: 0. */
+        delayAbortEval?: string | number;
+      };
+
+  /** Config accepted by `defineConfig`. */
+  interface Config {
+    /** Number of iterations, or `Infinity` to run for `duration`. Default: 1. */
+    iterations?: number;
+    /** Test duration in seconds when `iterations` is `Infinity`. Default: 10. */
+    duration?: number;
+    /** Max time per iteration in seconds. Default: 30. */
+    timeout?: number;
+    /** Number of virtual users. Default: 1. */
+    vus?: number;
+    /**
+     * Pass/fail criteria evaluated at the end of the run, keyed by metric name with
+     * optional tags, e.g. `{ "http_req_duration{name:login}": ["p(95)<300"] }`.
+     * `v6 run` exits with code 99 when any fails.
+     */
+    thresholds?: Record<string, ThresholdDefinition | ThresholdDefinition[]>;
+    /** Function executed on every iteration. */
+    iteration: () => void | Promise<void>;
+    setup?: () => void | Promise<void>;
+    teardown?: () => void | Promise<void>;
+    /**
+     * Called once the test is over with the summary data (the same JSON as
+     * `--summary-export`). Returns file paths mapped to their contents; the
+     * `stdout` and `stderr` keys print to the console. Non-string contents are
+     * written as JSON.
+     */
+    handleSummary?: (data: SummaryData) => SummaryOutputs | Promise<SummaryOutputs>;
+  }
+
+  /** Config after defaults are applied, as stored in `currentConfig`. */
+  interface ResolvedConfig extends Required<Omit<Config, "handleSummary">> {
+    handleSummary?: Config["handleSummary"];
+  }
+
+  type SummaryOutputs = Record<string, unknown> | null | undefined;
+
+  /** Aggregated values keyed like threshold aggregations, e.g. `count`, `rate`, `avg`, `p(95)`. */
+  type MetricValues = Record<string, number>;
+
+  interface MetricSummary {
+    type: "counter" | "gauge" | "rate" | "trend";
+    contains: "default" | "time" | "data";
+    values: MetricValues;
+    /** Values per tag, keyed by `{tag:value}`. */
+    submetrics: Record<string, MetricValues>;
+  }
+
+  interface GroupSummary {
+    name: string;
+    /** Names of the enclosing groups joined with `::`, `""` for the root group. */
+    path: string;
+    /** `group_duration` values, absent for the root group. */
+    duration: MetricValues | null;
+    checks: { name: string; passes: number; fails: number }[];
+    groups: GroupSummary[];
+  }
+
+  interface SummaryData {
+    metrics: Record<string, MetricSummary>;
+    rootGroup: GroupSummary;
+    thresholds: {
+      metric: string;
+      threshold: string;
+      ok: boolean;
+      /** `null` when the metric got no samples. */
+      actual: number | null;
+      abortOnFail: boolean;
+    }[];
+    metadata: {
+      v6Version: string;
+      /** Also labels the samples of the `otlp` output. */
+      runId: string;
+      mainModule: string;
+      /** Unix time in milliseconds. */
+      startTime: number;
+      testRunDurationMs: number;
+      /** `null` for duration-based runs. */
+      iterations: number | null;
+      duration: number;
+      vus: number;
+      /** Whether an `abortOnFail` threshold stopped the test. */
+      aborted: boolean;
+      thresholdsPassed: boolean;
+    };
+  }
+
+  /** Named predicates passed to `check`. */
+  type Checks<T> = Record<string, ((value: T) => unknown) | boolean>;
+
+  /** Tags attached to a metric value. Values are converted to strings. */
+  type Tags = Record<string, string | number | boolean>;
+
+  /**
+   * Matchers returned by `expect`. Each returns whether it passed and adds a
+   * value to the `checks` rate; failures don't throw unless `expect.strict` is used.
+   */
+  interface Matchers {
+    /** Inverts the following matcher. */
+    not: Matchers;
+    /** `Object.is` equality. */
+    toBe(expected: unknown): boolean;
+    /** Recursive equality of values, arrays and objects. */
+    toEqual(expected: unknown): boolean;
+    /** Substring of a string, or item of an array or iterable. */
+    toContain(expected: unknown): boolean;
+    /** The received object has all of `expected`'s properties, recursively. */
+    toMatchObject(expected: object): boolean;
+    toMatch(pattern: RegExp | string): boolean;
+    toBeLessThan(expected: number): boolean;
+    toBeLessThanOrEqual(expected: number): boolean;
+    toBeGreaterThan(expected: number): boolean;
+    toBeGreaterThanOrEqual(expected: number): boolean;
+    toBeTruthy(): boolean;
+    toBeFalsy(): boolean;
+    toBeDefined(): boolean;
+  }
+
+  interface Expect {
+    /** `name` labels the check in the summary; defaults to the matcher, e.g. `expect(received).toBe(200)`. */
+    (actual: unknown, name?: string): Matchers;
+    /** Same matchers, throwing an `ExpectationError` on failure, which ends the iteration. */
+    strict(actual: unknown, name?: string): Matchers;
+  }
+}
+
+declare function defineConfig(config: V6.Config): V6.ResolvedConfig;
+declare function sleep(ms: number): Promise<void>;
+/** Runs every named predicate without throwing; each adds a value to the `checks` rate. */
+declare function check<T>(value: T, checks: V6.Checks<T>, tags?: V6.Tags): boolean;
+declare const expect: V6.Expect;
+/**
+ * Runs `fn` as a named step of the iteration. Metrics recorded inside, including in
+ * nested groups, are tagged with the group path (e.g. `::checkout::payment`) and its
+ * time is added to `group_duration`. Returns what `fn` returns.
+ */
+declare function group<T>(name: string, fn: () => T): T;
+/** Resolves after `delay` ms. Unlike the web API it takes no callback. */
+declare function setTimeout(delay: number): Promise<void>;
+declare function fetch(input: V6.RequestInput, init?: V6.FetchInit): Promise<V6.FetchResponse>;
+declare var currentConfig: V6.ResolvedConfig | null;
+declare var console: {
+  log(...args: unknown[]): void;
+  error(...args: unknown[]): void;
+};
+
+declare module "v6" {
+  export function defineConfig(config: V6.Config): V6.ResolvedConfig;
+  export function sleep(ms: number): Promise<void>;
+  export function check<T>(value: T, checks: V6.Checks<T>, tags?: V6.Tags): boolean;
+  export const expect: V6.Expect;
+  export class ExpectationError extends Error {}
+  export function group<T>(name: string, fn: () => T): T;
+  const v6: {
+    defineConfig: typeof defineConfig;
+    sleep: typeof sleep;
+    check: typeof check;
+    expect: typeof expect;
+    group: typeof group;
+  };
+  export default v6;
+}
+
+declare module "v6/http" {
+  type Params = Omit<V6.FetchInit, "method" | "body">;
+  type Body = string | object | undefined;
+  type Input = V6.RequestInput;
+  export function fetch(input: Input, init?: V6.FetchInit): Promise<V6.FetchResponse>;
+  export function request(method: V6.HttpMethod, url: Input, body?: Body, params?: Params): Promise<V6.FetchResponse>;
+  /** Template tag: url`/users/${id}` is requested as is but named "/users/${}" in metrics. */
+  export function url(strings: TemplateStringsArray, ...values: unknown[]): V6.TaggedUrl;
+  export function get(url: Input, params?: Params): Promise<V6.FetchResponse>;
+  export function head(url: Input, params?: Params): Promise<V6.FetchResponse>;
+  export function post(url: Input, body?: Body, params?: Params): Promise<V6.FetchResponse>;
+  export function put(url: Input, body?: Body, params?: Params): Promise<V6.FetchResponse>;
+  export function patch(url: Input, body?: Body, params?: Params): Promise<V6.FetchResponse>;
+  export function del(url: Input, body?: Body, params?: Params): Promise<V6.FetchResponse>;
+  const http: {
+    fetch: typeof fetch;
+    request: typeof request;
+    url: typeof url;
+    get: typeof get;
+    head: typeof head;
+    post: typeof post;
+    put: typeof put;
+    patch: typeof patch;
+    del: typeof del;
+  };
+  export default http;
+}
+
+declare module "v6/data" {
+  export const SharedArray: {
+    new <T>(name: string, factory: () => T[]): ReadonlyArray<T>;
+  };
+  const data: { SharedArray: typeof SharedArray };
+  export default data;
+}
+
+declare module "v6/encoding" {
+  type Base64Encoding = "std" | "rawstd" | "url" | "rawurl";
+  export function b64encode(input: string | ArrayBuffer | ArrayBufferView, encoding?: Base64Encoding): string;
+  export function b64decode(input: string, encoding?: Base64Encoding): ArrayBuffer;
+  export function b64decode(input: string, encoding: Base64Encoding, format: "s"): string;
+  const encoding: { b64encode: typeof b64encode; b64decode: typeof b64decode };
+  export default encoding;
+}
+
+declare module "v6/metrics" {
+  abstract class Metric {
+    readonly name: string;
+    add(value: number | boolean, tags?: V6.Tags): void;
+  }
+  /** Sum of all added values. */
+  export class Counter extends Metric {
+    constructor(name: string);
+  }
+  /** Last added value, with min and max. */
+  export class Gauge extends Metric {
+    constructor(name: string, isTime?: boolean);
+  }
+  /** Share of added values that are truthy. */
+  export class Rate extends Metric {
+    constructor(name: string);
+    add(value: unknown, tags?: V6.Tags): void;
+  }
+  /** Distribution of added values. Pass `isTime` for values in milliseconds. */
+  export class Trend extends Metric {
+    constructor(name: string, isTime?: boolean);
+  }
+  const metrics: { Counter: typeof Counter; Gauge: typeof Gauge; Rate: typeof Rate; Trend: typeof Trend };
+  export default metrics;
+}